@@ -0,0 +1,14 @@
+-- Move any queued videos back to pending
+UPDATE videos SET processing_status = 'pending' WHERE processing_status = 'queued';
+
+-- Postgres can't drop enum values, so recreate the type without 'queued'
+ALTER TYPE processing_status RENAME TO processing_status_old;
+CREATE TYPE processing_status AS ENUM ('pending', 'processing', 'completed', 'failed');
+
+ALTER TABLE videos ALTER COLUMN processing_status DROP DEFAULT;
+ALTER TABLE videos
+    ALTER COLUMN processing_status TYPE processing_status
+    USING processing_status::text::processing_status;
+ALTER TABLE videos ALTER COLUMN processing_status SET DEFAULT 'pending';
+
+DROP TYPE processing_status_old;
@@ -0,0 +1,2 @@
+-- Add a queued state for videos waiting on a runner to pick up their processing job
+ALTER TYPE processing_status ADD VALUE IF NOT EXISTS 'queued' AFTER 'pending';
@@ -0,0 +1,25 @@
+-- Create enum type for job status
+CREATE TYPE job_status AS ENUM ('queued', 'running', 'retrying', 'completed', 'failed');
+
+-- Records every job published to the queue so its state can be tracked
+CREATE TABLE jobs (
+    id UUID PRIMARY KEY,
+    subject TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    video_id TEXT REFERENCES videos(id) ON DELETE CASCADE,
+    status job_status NOT NULL DEFAULT 'queued',
+    attempts INT NOT NULL DEFAULT 0,
+    progress REAL NOT NULL DEFAULT 0,
+    error TEXT,
+    started_at TIMESTAMPTZ,
+    finished_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_jobs_video_id ON jobs(video_id);
+CREATE INDEX idx_jobs_status ON jobs(status);
+
+-- Create trigger using existing function
+CREATE TRIGGER update_jobs_updated_at BEFORE
+UPDATE ON jobs FOR EACH ROW EXECUTE FUNCTION update_updated_at_column ();
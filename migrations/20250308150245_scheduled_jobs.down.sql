@@ -0,0 +1,21 @@
+DROP INDEX IF EXISTS idx_jobs_run_at;
+
+ALTER TABLE jobs DROP COLUMN IF EXISTS version;
+ALTER TABLE jobs DROP COLUMN IF EXISTS run_at;
+
+-- Scheduled jobs were never published, so there's nothing to fall back to
+DELETE FROM jobs WHERE status IN ('scheduled', 'cancelled');
+
+-- Postgres can't drop enum values, so recreate the type without 'scheduled' and 'cancelled'
+ALTER TYPE job_status RENAME TO job_status_old;
+CREATE TYPE job_status AS ENUM ('queued', 'running', 'retrying', 'completed', 'failed');
+
+ALTER TABLE jobs ALTER COLUMN status DROP DEFAULT;
+DROP INDEX IF EXISTS idx_jobs_status;
+ALTER TABLE jobs
+    ALTER COLUMN status TYPE job_status
+    USING status::text::job_status;
+ALTER TABLE jobs ALTER COLUMN status SET DEFAULT 'queued';
+CREATE INDEX idx_jobs_status ON jobs(status);
+
+DROP TYPE job_status_old;
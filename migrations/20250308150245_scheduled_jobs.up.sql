@@ -0,0 +1,9 @@
+-- Jobs can be held back until a given time before they are published to the queue
+ALTER TYPE job_status ADD VALUE IF NOT EXISTS 'scheduled' BEFORE 'queued';
+ALTER TYPE job_status ADD VALUE IF NOT EXISTS 'cancelled' AFTER 'failed';
+
+ALTER TABLE jobs ADD COLUMN run_at TIMESTAMPTZ;
+-- Payload version of the job, sent along with it once it's published
+ALTER TABLE jobs ADD COLUMN version INT;
+
+CREATE INDEX idx_jobs_run_at ON jobs(run_at);
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_jobs_dedup_key;
+
+ALTER TABLE jobs DROP COLUMN IF EXISTS dedup_key;
@@ -0,0 +1,4 @@
+-- Identifies jobs with the same subject and payload, used to avoid running duplicates
+ALTER TABLE jobs ADD COLUMN dedup_key TEXT;
+
+CREATE INDEX idx_jobs_dedup_key ON jobs(dedup_key);
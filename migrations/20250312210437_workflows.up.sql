@@ -0,0 +1,44 @@
+-- Create enum types for workflow and step status
+CREATE TYPE workflow_status AS ENUM ('running', 'completed', 'failed', 'cancelled');
+CREATE TYPE workflow_step_status AS ENUM ('pending', 'queued', 'running', 'completed', 'failed', 'cancelled');
+
+-- A set of jobs that run in order of their dependencies
+CREATE TABLE workflows (
+    id UUID PRIMARY KEY,
+    name TEXT NOT NULL,
+    video_id TEXT REFERENCES videos(id) ON DELETE CASCADE,
+    status workflow_status NOT NULL DEFAULT 'running',
+    error TEXT,
+    finished_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- A single job in a workflow, published once every step it depends on has completed
+CREATE TABLE workflow_steps (
+    id UUID PRIMARY KEY,
+    workflow_id UUID NOT NULL REFERENCES workflows(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    version INT,
+    depends_on TEXT[] NOT NULL DEFAULT '{}',
+    status workflow_step_status NOT NULL DEFAULT 'pending',
+    job_id UUID REFERENCES jobs(id) ON DELETE SET NULL,
+    output JSONB,
+    error TEXT,
+    finished_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (workflow_id, name)
+);
+
+CREATE INDEX idx_workflows_video_id ON workflows(video_id);
+CREATE INDEX idx_workflow_steps_job_id ON workflow_steps(job_id);
+
+-- Create triggers using existing function
+CREATE TRIGGER update_workflows_updated_at BEFORE
+UPDATE ON workflows FOR EACH ROW EXECUTE FUNCTION update_updated_at_column ();
+
+CREATE TRIGGER update_workflow_steps_updated_at BEFORE
+UPDATE ON workflow_steps FOR EACH ROW EXECUTE FUNCTION update_updated_at_column ();
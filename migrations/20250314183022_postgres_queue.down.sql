@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS idx_jobs_available_at;
+
+ALTER TABLE jobs DROP COLUMN IF EXISTS deliveries;
+ALTER TABLE jobs DROP COLUMN IF EXISTS available_at;
@@ -0,0 +1,6 @@
+-- Lets the jobs table double as the queue for deployments without NATS
+-- Jobs are delivered once available_at passes, NULL once a runner is done with them
+ALTER TABLE jobs ADD COLUMN available_at TIMESTAMPTZ DEFAULT NOW();
+ALTER TABLE jobs ADD COLUMN deliveries INT NOT NULL DEFAULT 0;
+
+CREATE INDEX idx_jobs_available_at ON jobs(status, available_at);
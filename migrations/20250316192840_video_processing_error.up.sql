@@ -0,0 +1,2 @@
+-- Why processing last failed, shown to the video's owner
+ALTER TABLE videos ADD COLUMN processing_error TEXT;
@@ -0,0 +1,3 @@
+ALTER TABLE videos DROP COLUMN IF EXISTS transcode_profile_id;
+DROP TABLE IF EXISTS transcode_profiles;
+DROP TYPE IF EXISTS video_codec;
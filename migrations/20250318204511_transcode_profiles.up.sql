@@ -0,0 +1,25 @@
+CREATE TYPE video_codec AS ENUM ('h264', 'h265');
+
+-- Named encoding ladders, videos without one use the default profile or the built in ladder
+CREATE TABLE transcode_profiles (
+    id UUID PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    description TEXT,
+    -- Every rendition of the ladder, with its resolution, bitrate, CRF and fps cap
+    renditions JSONB NOT NULL,
+    codec video_codec NOT NULL DEFAULT 'h264',
+    preset TEXT NOT NULL DEFAULT 'faster',
+    segment_duration INT NOT NULL DEFAULT 6,
+    is_default BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Only one profile can be the default
+CREATE UNIQUE INDEX idx_transcode_profiles_default ON transcode_profiles(is_default) WHERE is_default;
+
+-- The profile picked when the video was uploaded
+ALTER TABLE videos ADD COLUMN transcode_profile_id UUID REFERENCES transcode_profiles(id) ON DELETE SET NULL;
+
+CREATE TRIGGER update_transcode_profiles_updated_at BEFORE
+UPDATE ON transcode_profiles FOR EACH ROW EXECUTE FUNCTION update_updated_at_column ();
@@ -0,0 +1,19 @@
+-- What ffprobe found in a video's raw upload
+CREATE TABLE video_metadata (
+    video_id TEXT PRIMARY KEY REFERENCES videos(id) ON DELETE CASCADE,
+    duration DOUBLE PRECISION,
+    container TEXT NOT NULL,
+    video_codec TEXT NOT NULL,
+    audio_codec TEXT,
+    width INT NOT NULL,
+    height INT NOT NULL,
+    frame_rate DOUBLE PRECISION,
+    bit_rate BIGINT,
+    audio_channels INT,
+    file_size BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TRIGGER update_video_metadata_updated_at BEFORE
+UPDATE ON video_metadata FOR EACH ROW EXECUTE FUNCTION update_updated_at_column ();
@@ -0,0 +1,16 @@
+-- Stills generated for a video, stored beside its stream files
+CREATE TABLE video_images (
+    video_id TEXT PRIMARY KEY REFERENCES videos(id) ON DELETE CASCADE,
+    poster_path TEXT NOT NULL,
+    -- Frames picked from through the video for the owner to choose between
+    thumbnail_candidates TEXT[] NOT NULL DEFAULT '{}',
+    -- The thumbnail shown for the video, a candidate or one the owner uploaded
+    thumbnail_path TEXT NOT NULL,
+    -- WebVTT thumbnails track pointing into the storyboard sprite sheets
+    storyboard_path TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TRIGGER update_video_images_updated_at BEFORE
+UPDATE ON video_images FOR EACH ROW EXECUTE FUNCTION update_updated_at_column ();
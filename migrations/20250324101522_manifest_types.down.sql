@@ -0,0 +1,3 @@
+ALTER TABLE videos DROP COLUMN IF EXISTS dash_manifest_path;
+ALTER TABLE transcode_profiles DROP COLUMN IF EXISTS manifest_type;
+DROP TYPE IF EXISTS manifest_type;
@@ -0,0 +1,7 @@
+CREATE TYPE manifest_type AS ENUM ('hls', 'cmaf');
+
+-- Plain HLS with MPEG-TS segments, or CMAF segments shared by HLS and DASH manifests
+ALTER TABLE transcode_profiles ADD COLUMN manifest_type manifest_type NOT NULL DEFAULT 'hls';
+
+-- Where the DASH manifest was published, only set for videos converted to CMAF
+ALTER TABLE videos ADD COLUMN dash_manifest_path TEXT;
@@ -0,0 +1,2 @@
+ALTER TABLE transcode_profiles DROP COLUMN IF EXISTS encoding_mode;
+DROP TYPE IF EXISTS encoding_mode;
@@ -0,0 +1,4 @@
+CREATE TYPE encoding_mode AS ENUM ('per_rendition', 'single_pass');
+
+-- Whether each rendition decodes the source on its own or one ffmpeg writes all of them
+ALTER TABLE transcode_profiles ADD COLUMN encoding_mode encoding_mode NOT NULL DEFAULT 'per_rendition';
@@ -0,0 +1,2 @@
+-- What a job produced once it completed, like the report of a garbage collection
+ALTER TABLE jobs ADD COLUMN output JSONB;
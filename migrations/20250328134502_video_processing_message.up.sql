@@ -0,0 +1,3 @@
+-- A short reason processing failed, shown to anyone who can see the video
+-- processing_error keeps the full error for the video's owner and admins
+ALTER TABLE videos ADD COLUMN processing_message TEXT;
@@ -0,0 +1,15 @@
+-- Only one unfinished job can have a dedup key, so concurrent publishes can't both create one
+-- Duplicates created before this keep running, but lose their dedup key
+UPDATE jobs SET dedup_key = NULL
+WHERE id IN (
+    SELECT id FROM (
+        SELECT id, ROW_NUMBER() OVER (PARTITION BY dedup_key ORDER BY created_at) AS position
+        FROM jobs
+        WHERE dedup_key IS NOT NULL
+            AND status IN ('scheduled', 'queued', 'running', 'retrying')
+    ) duplicates
+    WHERE position > 1
+);
+
+CREATE UNIQUE INDEX idx_jobs_active_dedup_key ON jobs(dedup_key)
+WHERE status IN ('scheduled', 'queued', 'running', 'retrying');
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_workflows_job_id;
+
+ALTER TABLE workflows DROP COLUMN IF EXISTS job_id;
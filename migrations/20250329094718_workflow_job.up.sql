@@ -0,0 +1,4 @@
+-- The job that started a workflow, so a retried job picks its workflow back up instead of starting another
+ALTER TABLE workflows ADD COLUMN job_id UUID REFERENCES jobs(id) ON DELETE SET NULL;
+
+CREATE UNIQUE INDEX idx_workflows_job_id ON workflows(job_id);
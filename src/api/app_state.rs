@@ -1,17 +1,23 @@
 use super::config::Config;
 use crate::{
-    db::connect_to_database, event::Stream, nats::create_nats_client, queue::Queue,
-    storage::s3::create_s3_client,
+    db::connect_to_database,
+    event::Stream,
+    nats::create_nats_client_if_enabled,
+    queue::{DeadLetterQueue, Queue},
+    storage::{self, Storage},
 };
 use sqlx::PgPool;
+use std::sync::Arc;
 
 /// Shared state available to the API
 pub struct AppState {
     pub db: PgPool,
     pub job_queue: Queue,
-    pub event_stream: Stream,
+    /// Dead letters and events need NATS, which deployments on the Postgres queue can leave out
+    pub dead_letters: Option<DeadLetterQueue>,
+    pub event_stream: Option<Stream>,
     pub config: Config,
-    pub s3_client: aws_sdk_s3::Client,
+    pub storage: Arc<dyn Storage>,
 }
 
 impl AppState {
@@ -21,28 +27,44 @@ impl AppState {
             .await
             .expect("Could not connect to database");
 
-        // Create the S3 Client
-        let s3_client = create_s3_client().await;
+        // Connect to the object storage backend
+        let storage = storage::connect().await?;
 
-        // Create a NATS client
-        let nats_client = create_nats_client().await?;
+        // Create a NATS client if anything uses it
+        let nats_client = create_nats_client_if_enabled().await?;
 
         // Connect to the job queue
-        let job_queue = Queue::connect(nats_client.clone())
+        let job_queue = Queue::connect(nats_client.clone(), db.clone())
             .await
             .expect("Failed to create worker queue");
 
-        // Connect to the event stream
-        let event_stream = Stream::connect(nats_client.clone())
-            .await
-            .expect("Failed to connect to event stream");
+        let (dead_letters, event_stream) = match nats_client {
+            Some(nats_client) => {
+                // Connect to the dead letter queue
+                let dead_letters = DeadLetterQueue::connect(nats_client.clone())
+                    .await
+                    .expect("Failed to connect to dead letter queue");
+
+                // Connect to the event stream
+                let event_stream = Stream::connect(nats_client)
+                    .await
+                    .expect("Failed to connect to event stream");
+
+                (Some(dead_letters), Some(event_stream))
+            }
+            None => {
+                tracing::info!("NATS is disabled, dead letters and events are unavailable");
+                (None, None)
+            }
+        };
 
         Ok(Self {
             config,
             db,
             job_queue,
+            dead_letters,
             event_stream,
-            s3_client,
+            storage,
         })
     }
 }
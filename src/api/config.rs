@@ -4,7 +4,6 @@ pub const DEFAULT_PORT: &str = "3000"; // This is stored as a string to match en
 pub struct Config {
     pub port: String,
     pub upload_dir: Option<String>,
-    pub upload_bucket: Option<String>,
 }
 
 impl Config {
@@ -12,7 +11,6 @@ impl Config {
         Config {
             port: Self::get_port(),
             upload_dir: Self::get_upload_dir(),
-            upload_bucket: Self::get_upload_bucket(),
         }
     }
     /// Gets the port from environment variables
@@ -36,8 +34,4 @@ impl Config {
             Err(_) => None,
         }
     }
-    /// Gets the Cloudflare R2 upload bucket to use from environment
-    pub fn get_upload_bucket() -> Option<String> {
-        std::env::var("UPLOAD_BUCKET").ok()
-    }
 }
@@ -0,0 +1,174 @@
+//! Admin functionality for inspecting and replaying jobs that exhausted their attempts
+use crate::{
+    api::app_state::AppState,
+    db::{users::UserRole, User},
+    error::QueueError,
+    queue::{dead_letter::DeadLetter, DeadLetterQueue},
+};
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    Extension, Json,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+const DEFAULT_LIST_LIMIT: usize = 100;
+
+#[derive(Deserialize)]
+pub struct DeadLetterQuery {
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct DeadLetterResponse {
+    dead_letters: Vec<DeadLetter>,
+}
+
+#[derive(Serialize)]
+pub struct PurgeResponse {
+    purged: u64,
+}
+
+/// Makes sure the requesting user is an admin
+fn require_admin(user: Option<User>) -> Result<User, (StatusCode, &'static str)> {
+    let Some(user) = user else {
+        return Err((StatusCode::UNAUTHORIZED, "User not found"));
+    };
+    if user.role != UserRole::Admin {
+        return Err((StatusCode::FORBIDDEN, "Forbidden"));
+    }
+    Ok(user)
+}
+
+/// Gets the dead letter queue, which is only available when NATS is enabled
+fn dead_letter_queue(state: &AppState) -> Result<&DeadLetterQueue, (StatusCode, &'static str)> {
+    state.dead_letters.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "Dead letter queue is not enabled",
+    ))
+}
+
+/// Converts a queue error into an API response
+fn queue_error_response(err: QueueError) -> axum::response::Response {
+    match err {
+        QueueError::NotFound(_) => (StatusCode::NOT_FOUND, "Dead letter not found").into_response(),
+        err => {
+            tracing::error!("Dead letter queue error: {}", err);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error").into_response()
+        }
+    }
+}
+
+/// Lists dead lettered jobs, admin role required
+pub async fn list_dead_letters(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<Option<User>>,
+    Query(query): Query<DeadLetterQuery>,
+) -> impl IntoResponse {
+    if let Err(err) = require_admin(user) {
+        return err.into_response();
+    }
+
+    let dead_letters = match dead_letter_queue(&state) {
+        Ok(dead_letters) => dead_letters,
+        Err(err) => return err.into_response(),
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_LIST_LIMIT);
+    match dead_letters.list(limit).await {
+        Ok(dead_letters) => Json(DeadLetterResponse { dead_letters }).into_response(),
+        Err(err) => queue_error_response(err),
+    }
+}
+
+/// Gets a single dead lettered job, admin role required
+pub async fn get_dead_letter(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<Option<User>>,
+    Path(sequence): Path<u64>,
+) -> impl IntoResponse {
+    if let Err(err) = require_admin(user) {
+        return err.into_response();
+    }
+
+    let dead_letters = match dead_letter_queue(&state) {
+        Ok(dead_letters) => dead_letters,
+        Err(err) => return err.into_response(),
+    };
+    match dead_letters.get(sequence).await {
+        Ok(dead_letter) => Json(dead_letter).into_response(),
+        Err(err) => queue_error_response(err),
+    }
+}
+
+/// Publishes a dead lettered job back onto the job queue, admin role required
+pub async fn replay_dead_letter(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<Option<User>>,
+    Path(sequence): Path<u64>,
+) -> impl IntoResponse {
+    let user = match require_admin(user) {
+        Ok(user) => user,
+        Err(err) => return err.into_response(),
+    };
+
+    let dead_letters = match dead_letter_queue(&state) {
+        Ok(dead_letters) => dead_letters,
+        Err(err) => return err.into_response(),
+    };
+    match dead_letters.replay(sequence, &state.job_queue).await {
+        Ok(_) => {
+            tracing::info!("User {} replayed dead letter {}", user.id, sequence);
+            StatusCode::ACCEPTED.into_response()
+        }
+        Err(err) => queue_error_response(err),
+    }
+}
+
+/// Removes a single dead lettered job, admin role required
+pub async fn delete_dead_letter(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<Option<User>>,
+    Path(sequence): Path<u64>,
+) -> impl IntoResponse {
+    let user = match require_admin(user) {
+        Ok(user) => user,
+        Err(err) => return err.into_response(),
+    };
+
+    let dead_letters = match dead_letter_queue(&state) {
+        Ok(dead_letters) => dead_letters,
+        Err(err) => return err.into_response(),
+    };
+    match dead_letters.remove(sequence).await {
+        Ok(_) => {
+            tracing::info!("User {} deleted dead letter {}", user.id, sequence);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(err) => queue_error_response(err),
+    }
+}
+
+/// Removes every dead lettered job, admin role required
+pub async fn purge_dead_letters(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<Option<User>>,
+) -> impl IntoResponse {
+    let user = match require_admin(user) {
+        Ok(user) => user,
+        Err(err) => return err.into_response(),
+    };
+
+    let dead_letters = match dead_letter_queue(&state) {
+        Ok(dead_letters) => dead_letters,
+        Err(err) => return err.into_response(),
+    };
+    match dead_letters.purge().await {
+        Ok(purged) => {
+            tracing::info!("User {} purged {} dead letters", user.id, purged);
+            Json(PurgeResponse { purged }).into_response()
+        }
+        Err(err) => queue_error_response(err),
+    }
+}
@@ -39,8 +39,15 @@ pub async fn get_events(
         return (StatusCode::NOT_FOUND, "User not found").into_response();
     };
 
-    let Ok(events) = state
-        .event_stream
+    let Some(event_stream) = &state.event_stream else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Event stream is not enabled",
+        )
+            .into_response();
+    };
+
+    let Ok(events) = event_stream
         .get_user_events(
             target_user.username,
             stream_query.start_time,
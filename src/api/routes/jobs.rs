@@ -0,0 +1,283 @@
+use crate::{
+    api::app_state::AppState,
+    db::{users::UserRole, JobRecord, User, Video, WorkflowRecord, WorkflowStepRecord},
+    error::QueueError,
+    queue::garbage_collect::GarbageCollectPayload,
+};
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    Extension, Json,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+const DEFAULT_SCHEDULED_LIMIT: i64 = 100;
+
+#[derive(Serialize)]
+pub struct JobsResponse {
+    jobs: Vec<JobRecord>,
+}
+
+#[derive(Serialize)]
+pub struct WorkflowWithSteps {
+    #[serde(flatten)]
+    workflow: WorkflowRecord,
+    steps: Vec<WorkflowStepRecord>,
+}
+
+#[derive(Serialize)]
+pub struct WorkflowsResponse {
+    workflows: Vec<WorkflowWithSteps>,
+}
+
+#[derive(Deserialize)]
+pub struct ScheduledJobsQuery {
+    limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct GarbageCollectQuery {
+    /// Only report what would be cleaned up, true unless it's turned off
+    dry_run: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct GarbageCollectResponse {
+    /// The report is the output of this job once it's done, available from the job endpoint
+    job_id: Uuid,
+}
+
+/// Checks whether a user can see the jobs for a video
+async fn can_view_video_jobs(state: &AppState, user: &User, video_id: &str) -> bool {
+    if user.role == UserRole::Admin {
+        return true;
+    }
+    match Video::by_id(&state.db, video_id).await {
+        Ok(video) => video.user_id == user.id,
+        Err(_) => false,
+    }
+}
+
+/// Gets a single job by ID, only available to the owner of the job's video
+pub async fn get_job(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<Option<User>>,
+    Path(job_id): Path<Uuid>,
+) -> impl IntoResponse {
+    let Some(user) = user else {
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    };
+
+    let Ok(job) = JobRecord::by_id(&state.db, job_id).await else {
+        return (StatusCode::NOT_FOUND, "Job not found").into_response();
+    };
+
+    // Jobs that aren't tied to a video are only visible to admins
+    let allowed = match &job.video_id {
+        Some(video_id) => can_view_video_jobs(&state, &user, video_id).await,
+        None => user.role == UserRole::Admin,
+    };
+    if !allowed {
+        return (StatusCode::FORBIDDEN, "You do not own this job").into_response();
+    }
+
+    Json(job).into_response()
+}
+
+/// Cancels a job that hasn't finished yet, only available to the owner of the job's video
+pub async fn cancel_job(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<Option<User>>,
+    Path(job_id): Path<Uuid>,
+) -> impl IntoResponse {
+    let Some(user) = user else {
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    };
+
+    let Ok(job) = JobRecord::by_id(&state.db, job_id).await else {
+        return (StatusCode::NOT_FOUND, "Job not found").into_response();
+    };
+
+    let allowed = match &job.video_id {
+        Some(video_id) => can_view_video_jobs(&state, &user, video_id).await,
+        None => user.role == UserRole::Admin,
+    };
+    if !allowed {
+        return (StatusCode::FORBIDDEN, "You do not own this job").into_response();
+    }
+
+    match state.job_queue.cancel(job_id).await {
+        Ok(_) => {
+            tracing::info!("User {} cancelled job {}", user.id, job_id);
+            StatusCode::ACCEPTED.into_response()
+        }
+        Err(QueueError::NotFound(_)) => {
+            (StatusCode::CONFLICT, "Job has already finished").into_response()
+        }
+        Err(e) => {
+            tracing::error!("Could not cancel job {}: {}", job_id, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Could not cancel job").into_response()
+        }
+    }
+}
+
+/// Gets every job for a video, newest first
+pub async fn get_video_jobs(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<Option<User>>,
+    Path(video_id): Path<String>,
+) -> impl IntoResponse {
+    let Some(user) = user else {
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    };
+
+    if !can_view_video_jobs(&state, &user, &video_id).await {
+        return (StatusCode::FORBIDDEN, "You do not own this video").into_response();
+    }
+
+    match JobRecord::by_video_id(&state.db, &video_id).await {
+        Ok(jobs) => Json(JobsResponse { jobs }).into_response(),
+        Err(e) => {
+            tracing::error!("Could not get jobs for video {}: {}", video_id, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Could not get jobs").into_response()
+        }
+    }
+}
+
+/// Gets the processing workflows for a video along with their steps, newest first
+pub async fn get_video_workflows(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<Option<User>>,
+    Path(video_id): Path<String>,
+) -> impl IntoResponse {
+    let Some(user) = user else {
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    };
+
+    if !can_view_video_jobs(&state, &user, &video_id).await {
+        return (StatusCode::FORBIDDEN, "You do not own this video").into_response();
+    }
+
+    let workflows = match WorkflowRecord::by_video_id(&state.db, &video_id).await {
+        Ok(workflows) => workflows,
+        Err(e) => {
+            tracing::error!("Could not get workflows for video {}: {}", video_id, e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Could not get workflows").into_response();
+        }
+    };
+
+    let mut response = WorkflowsResponse {
+        workflows: Vec::with_capacity(workflows.len()),
+    };
+    for workflow in workflows {
+        match WorkflowStepRecord::by_workflow_id(&state.db, workflow.id).await {
+            Ok(steps) => response
+                .workflows
+                .push(WorkflowWithSteps { workflow, steps }),
+            Err(e) => {
+                tracing::error!("Could not get steps for workflow {}: {}", workflow.id, e);
+                return (StatusCode::INTERNAL_SERVER_ERROR, "Could not get workflows")
+                    .into_response();
+            }
+        }
+    }
+
+    Json(response).into_response()
+}
+
+/// Lists jobs waiting for their scheduled time, admin role required
+pub async fn get_scheduled_jobs(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<Option<User>>,
+    Query(query): Query<ScheduledJobsQuery>,
+) -> impl IntoResponse {
+    let Some(user) = user else {
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    };
+    if user.role != UserRole::Admin {
+        return (StatusCode::FORBIDDEN, "Forbidden").into_response();
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_SCHEDULED_LIMIT);
+    match state.job_queue.scheduled(limit).await {
+        Ok(jobs) => Json(JobsResponse { jobs }).into_response(),
+        Err(e) => {
+            tracing::error!("Could not get scheduled jobs: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Could not get jobs").into_response()
+        }
+    }
+}
+
+/// Cancels a job before its scheduled time, admin role required
+pub async fn cancel_scheduled_job(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<Option<User>>,
+    Path(job_id): Path<Uuid>,
+) -> impl IntoResponse {
+    let Some(user) = user else {
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    };
+    if user.role != UserRole::Admin {
+        return (StatusCode::FORBIDDEN, "Forbidden").into_response();
+    }
+
+    match state.job_queue.cancel_scheduled(job_id).await {
+        Ok(_) => {
+            tracing::info!("User {} cancelled scheduled job {}", user.id, job_id);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(QueueError::NotFound(_)) => {
+            (StatusCode::NOT_FOUND, "Scheduled job not found").into_response()
+        }
+        Err(e) => {
+            tracing::error!("Could not cancel scheduled job {}: {}", job_id, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Could not cancel job").into_response()
+        }
+    }
+}
+
+/// Queues a garbage collection right away, admin role required
+/// Runs as a dry run unless asked not to, so what would be deleted can be checked first
+pub async fn run_garbage_collection(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<Option<User>>,
+    Query(query): Query<GarbageCollectQuery>,
+) -> impl IntoResponse {
+    let Some(user) = user else {
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    };
+    if user.role != UserRole::Admin {
+        return (StatusCode::FORBIDDEN, "Forbidden").into_response();
+    }
+
+    let payload = GarbageCollectPayload {
+        scheduled_for: chrono::Utc::now().timestamp(),
+        dry_run: query.dry_run.unwrap_or(true),
+    };
+    match state.job_queue.enqueue(&payload).await {
+        Ok(job_id) => {
+            tracing::info!(
+                "User {} queued garbage collection job {}, dry run {}",
+                user.id,
+                job_id,
+                payload.dry_run
+            );
+            (
+                StatusCode::ACCEPTED,
+                Json(GarbageCollectResponse { job_id }),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            tracing::error!("Could not queue garbage collection: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Could not queue garbage collection",
+            )
+                .into_response()
+        }
+    }
+}
@@ -1,6 +1,10 @@
 pub mod auth;
+pub mod dead_letters;
 pub mod events;
 pub mod health;
+pub mod jobs;
+pub mod profiles;
+pub mod storage;
 pub mod streams;
 pub mod upload;
 pub mod user;
@@ -0,0 +1,216 @@
+use crate::{
+    api::app_state::AppState,
+    db::{users::UserRole, NewTranscodeProfile, TranscodeProfile, User},
+    vod::{
+        ladder::{EncodingLadder, EncodingMode, EncodingSettings, ManifestType, VideoCodec},
+        stream::Quality,
+    },
+};
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Extension, Json,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Serialize)]
+pub struct ProfilesResponse {
+    profiles: Vec<TranscodeProfile>,
+}
+
+#[derive(Deserialize)]
+pub struct ProfileRequest {
+    name: String,
+    description: Option<String>,
+    renditions: Vec<Quality>,
+    codec: Option<VideoCodec>,
+    preset: Option<String>,
+    segment_duration: Option<u32>,
+    manifest_type: Option<ManifestType>,
+    encoding_mode: Option<EncodingMode>,
+    #[serde(default)]
+    is_default: bool,
+}
+
+impl ProfileRequest {
+    /// Builds the ladder from the request, filling in the default settings that weren't given
+    fn ladder(&self) -> Result<EncodingLadder, String> {
+        let defaults = EncodingSettings::default();
+        let ladder = EncodingLadder {
+            qualities: self.renditions.clone(),
+            settings: EncodingSettings {
+                codec: self.codec.unwrap_or(defaults.codec),
+                preset: self.preset.clone().unwrap_or(defaults.preset),
+                segment_duration: self.segment_duration.unwrap_or(defaults.segment_duration),
+                manifest: self.manifest_type.unwrap_or(defaults.manifest),
+                mode: self.encoding_mode.unwrap_or(defaults.mode),
+            },
+        };
+        if self.name.trim().is_empty() {
+            return Err("A profile name is required".to_string());
+        }
+        ladder.validate()?;
+        Ok(ladder)
+    }
+}
+
+/// Checks whether a database error is from a profile name that's already taken
+fn is_duplicate_name(err: &sqlx::Error) -> bool {
+    err.as_database_error()
+        .and_then(|e| e.code())
+        .filter(|c| c == "23505")
+        .is_some()
+}
+
+/// Lists every transcode profile, for picking one at upload time
+pub async fn get_profiles(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<Option<User>>,
+) -> impl IntoResponse {
+    if user.is_none() {
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    }
+
+    match TranscodeProfile::all(&state.db).await {
+        Ok(profiles) => Json(ProfilesResponse { profiles }).into_response(),
+        Err(e) => {
+            tracing::error!("Could not list transcode profiles: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Could not list profiles").into_response()
+        }
+    }
+}
+
+/// Adds a transcode profile, only available to admins
+pub async fn create_profile(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<Option<User>>,
+    Json(request): Json<ProfileRequest>,
+) -> impl IntoResponse {
+    let Some(user) = user else {
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    };
+    if user.role != UserRole::Admin {
+        return (StatusCode::FORBIDDEN, "Forbidden").into_response();
+    }
+    let ladder = match request.ladder() {
+        Ok(ladder) => ladder,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
+    let new_profile = NewTranscodeProfile {
+        name: request.name.trim(),
+        description: request.description.as_deref(),
+        ladder: &ladder,
+        is_default: request.is_default,
+    };
+    match TranscodeProfile::create(&state.db, &new_profile).await {
+        Ok(profile) => {
+            tracing::info!(
+                "User {} created transcode profile {}",
+                user.id,
+                profile.name
+            );
+            (StatusCode::CREATED, Json(profile)).into_response()
+        }
+        Err(e) if is_duplicate_name(&e) => (
+            StatusCode::CONFLICT,
+            "A profile with that name already exists",
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Could not create transcode profile: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Could not create profile",
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Replaces a transcode profile, only available to admins
+/// Videos that are already converted keep their renditions
+pub async fn update_profile(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<Option<User>>,
+    Path(profile_id): Path<Uuid>,
+    Json(request): Json<ProfileRequest>,
+) -> impl IntoResponse {
+    let Some(user) = user else {
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    };
+    if user.role != UserRole::Admin {
+        return (StatusCode::FORBIDDEN, "Forbidden").into_response();
+    }
+    let ladder = match request.ladder() {
+        Ok(ladder) => ladder,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
+    let new_profile = NewTranscodeProfile {
+        name: request.name.trim(),
+        description: request.description.as_deref(),
+        ladder: &ladder,
+        is_default: request.is_default,
+    };
+    match TranscodeProfile::update(&state.db, profile_id, &new_profile).await {
+        Ok(profile) => {
+            tracing::info!(
+                "User {} updated transcode profile {}",
+                user.id,
+                profile.name
+            );
+            Json(profile).into_response()
+        }
+        Err(sqlx::Error::RowNotFound) => {
+            (StatusCode::NOT_FOUND, "Profile not found").into_response()
+        }
+        Err(e) if is_duplicate_name(&e) => (
+            StatusCode::CONFLICT,
+            "A profile with that name already exists",
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Could not update transcode profile {}: {}", profile_id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Could not update profile",
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Deletes a transcode profile, only available to admins
+/// Videos that picked it are converted with the default from then on
+pub async fn delete_profile(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<Option<User>>,
+    Path(profile_id): Path<Uuid>,
+) -> impl IntoResponse {
+    let Some(user) = user else {
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    };
+    if user.role != UserRole::Admin {
+        return (StatusCode::FORBIDDEN, "Forbidden").into_response();
+    }
+
+    match TranscodeProfile::delete(&state.db, profile_id).await {
+        Ok(true) => {
+            tracing::info!("User {} deleted transcode profile {}", user.id, profile_id);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => (StatusCode::NOT_FOUND, "Profile not found").into_response(),
+        Err(e) => {
+            tracing::error!("Could not delete transcode profile {}: {}", profile_id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Could not delete profile",
+            )
+                .into_response()
+        }
+    }
+}
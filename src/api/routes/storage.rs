@@ -0,0 +1,98 @@
+//! Serves the presigned URLs of the local filesystem storage backend
+//! Other backends hand out URLs to their own service, so these routes are only found locally
+use crate::{
+    api::app_state::AppState,
+    error::StorageError,
+    storage::{LocalStorage, ObjectHeaders, Storage},
+};
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue},
+    response::IntoResponse,
+};
+use futures::TryStreamExt;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+pub struct SignedQuery {
+    expires: i64,
+    signature: String,
+}
+
+/// Gets the local storage backend, which is the only one that needs the API to serve it
+fn local_storage(state: &AppState) -> Result<&LocalStorage, (StatusCode, &'static str)> {
+    state
+        .storage
+        .as_local()
+        .ok_or((StatusCode::NOT_FOUND, "Not Found"))
+}
+
+/// Converts a storage error into an API response
+fn storage_error_response(err: StorageError) -> axum::response::Response {
+    match err {
+        StorageError::NotFound(_) => (StatusCode::NOT_FOUND, "Not Found").into_response(),
+        StorageError::InvalidSignature(_) => (StatusCode::FORBIDDEN, "Forbidden").into_response(),
+        StorageError::InvalidKey(_) => (StatusCode::BAD_REQUEST, "Invalid key").into_response(),
+        err => {
+            tracing::error!("Local storage error: {}", err);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error").into_response()
+        }
+    }
+}
+
+/// Downloads an object through a URL from `presign_get`
+pub async fn get_object(
+    State(state): State<Arc<AppState>>,
+    Path(key): Path<String>,
+    Query(query): Query<SignedQuery>,
+) -> impl IntoResponse {
+    let storage = match local_storage(&state) {
+        Ok(storage) => storage,
+        Err(err) => return err.into_response(),
+    };
+    if let Err(e) = storage.verify_get(&key, query.expires, &query.signature) {
+        return storage_error_response(e);
+    }
+    let object_headers = ObjectHeaders::for_key(&key);
+    let mut headers = HeaderMap::new();
+    if let Some(content_type) = object_headers.content_type {
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    }
+    if let Some(cache_control) = object_headers.cache_control {
+        headers.insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static(cache_control),
+        );
+    }
+    match storage.get(&key).await {
+        Ok(body) => (headers, Body::from_stream(body)).into_response(),
+        Err(e) => storage_error_response(e),
+    }
+}
+
+/// Uploads a part of a multipart upload through a URL from `presign_upload_part`
+/// Responds with the part's ETag, which the client sends back to complete the upload
+pub async fn upload_part(
+    State(state): State<Arc<AppState>>,
+    Path((upload_id, part_number)): Path<(String, i32)>,
+    Query(query): Query<SignedQuery>,
+    body: Body,
+) -> impl IntoResponse {
+    let storage = match local_storage(&state) {
+        Ok(storage) => storage,
+        Err(err) => return err.into_response(),
+    };
+    if let Err(e) =
+        storage.verify_upload_part(&upload_id, part_number, query.expires, &query.signature)
+    {
+        return storage_error_response(e);
+    }
+    let body = body.into_data_stream().map_err(std::io::Error::other);
+    match storage.write_part(&upload_id, part_number, body).await {
+        Ok(etag) => (StatusCode::OK, [(header::ETAG, format!("\"{}\"", etag))]).into_response(),
+        Err(e) => storage_error_response(e),
+    }
+}
@@ -1,14 +1,14 @@
 use std::sync::Arc;
 
-use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
 use axum::{extract::State, Extension, Json};
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    api::app_state::AppState,
-    db::{User, Video},
+    api::{app_state::AppState, routes::video::queue_video_processing},
+    db::{TranscodeProfile, User, Video},
     prelude::get_storage_dir,
+    storage::CompletedPart,
 };
 
 #[derive(Deserialize)]
@@ -17,6 +17,8 @@ pub struct InitUploadRequest {
     key: String,
     content_type: String,
     title: Option<String>,
+    /// Name of the transcode profile to convert the video with, the default when unset
+    profile: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -34,7 +36,7 @@ pub struct InitUploadResponse {
     part_urls: Vec<PartUrl>,
 }
 
-/// Initializes a multipart upload to R2
+/// Initializes a multipart upload to storage
 pub async fn init_upload(
     State(state): State<Arc<AppState>>,
     Extension(user): Extension<Option<User>>,
@@ -43,14 +45,20 @@ pub async fn init_upload(
     // User required
     tracing::trace!("Checking for user authorization on upload");
     let user = user.ok_or(StatusCode::UNAUTHORIZED)?;
-    // First, let R2 know we've completed the upload
-    tracing::trace!("Grabbing bucket");
-    let bucket = state
-        .config
-        .upload_bucket
-        .clone()
-        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
-    tracing::trace!("Bucket found {}", &bucket);
+    // Make sure the profile exists before starting the upload
+    let profile_id = match &request.profile {
+        Some(name) => {
+            let profile = TranscodeProfile::by_name(&state.db, name)
+                .await
+                .map_err(|e| {
+                    tracing::error!("Could not get transcode profile {} {}", name, e);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                })?
+                .ok_or(StatusCode::BAD_REQUEST)?;
+            Some(profile.id)
+        }
+        None => None,
+    };
     let video_id = Video::gen_id();
     // Get the file extension from the original key
     let extension = request.key.split('.').last().unwrap_or("");
@@ -58,56 +66,30 @@ pub async fn init_upload(
     let storage_path = format!("{}/{}", storage_root, video_id);
     let key = format!("{}/raw.{}", storage_path, extension);
     tracing::debug!("Full parsed key: {key}");
-    // Start multipart upload on R2's side
-    let start_upload_output = state
-        .s3_client
-        .create_multipart_upload()
-        .bucket(&bucket)
-        .key(&key)
-        .content_type(&request.content_type)
-        .send()
+    // Start the multipart upload on the storage side
+    let upload_id = state
+        .storage
+        .create_multipart_upload(&key, &request.content_type)
         .await
         .map_err(|e| {
-            tracing::error!("Could not start multipart upload {:?}", e);
+            tracing::error!("Could not start multipart upload {}", e);
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
 
-    // Parse the upload ID out of the R2 output for downstream use while uploading
-    let upload_id = start_upload_output
-        .upload_id()
-        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?
-        .to_string();
-
     // Generate presigned links for each part
+    let expires_in = std::time::Duration::from_secs(3600);
     let mut part_urls = Vec::new();
     for part_number in 1..=request.parts {
-        // Construct Duration
-        let expires_in = std::time::Duration::from_secs(3600);
-        // Construct presigning config
-        let config =
-            aws_sdk_s3::presigning::PresigningConfig::expires_in(expires_in).map_err(|e| {
-                tracing::error!("Could not construct presigning config {}", e);
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?;
-        // Generate URL
-        let presigned_url = state
-            .s3_client
-            .upload_part()
-            .bucket(&bucket)
-            .key(&key)
-            .upload_id(&upload_id)
-            .part_number(part_number)
-            .presigned(config)
+        let url = state
+            .storage
+            .presign_upload_part(&key, &upload_id, part_number, expires_in)
             .await
             .map_err(|e| {
                 tracing::error!("Could not generate presigned url {}", e);
                 StatusCode::INTERNAL_SERVER_ERROR
             })?;
 
-        part_urls.push(PartUrl {
-            part_number,
-            url: presigned_url.uri().to_string(),
-        });
+        part_urls.push(PartUrl { part_number, url });
     }
 
     // Initialize the video in the database
@@ -117,6 +99,7 @@ pub async fn init_upload(
         user.id,
         request.title.unwrap_or("Untitled".to_string()),
         Some(key.clone()),
+        profile_id,
     )
     .await
     .map_err(|e| {
@@ -139,7 +122,6 @@ pub struct Parts {
 }
 
 #[derive(Deserialize)]
-#[allow(dead_code)]
 pub struct CompleteUploadRequest {
     upload_id: String,
     video_id: String,
@@ -147,8 +129,7 @@ pub struct CompleteUploadRequest {
     completed_parts: Vec<Parts>,
 }
 
-// TODO: Write route for completing multipart upload
-/// Completes a multipart upload to R2
+/// Completes a multipart upload to storage and queues the video for processing
 pub async fn complete_upload(
     State(state): State<Arc<AppState>>,
     Extension(user): Extension<Option<User>>,
@@ -156,44 +137,53 @@ pub async fn complete_upload(
 ) -> Result<StatusCode, StatusCode> {
     // User required
     tracing::trace!("Checking for user authorization on upload");
-    let _user = user.ok_or(StatusCode::UNAUTHORIZED)?;
-    // First, let R2 know we've completed the upload
-    tracing::trace!("Grabbing bucket");
-    let bucket = state
-        .config
-        .upload_bucket
-        .clone()
-        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
-    tracing::trace!("Bucket found {}", bucket);
-
-    let serialized_completed_parts = request
+    let user = user.ok_or(StatusCode::UNAUTHORIZED)?;
+    // Make sure the upload belongs to a video the user owns
+    let video = Video::by_id(&state.db, &request.video_id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Could not find video {} {}", request.video_id, e);
+            StatusCode::NOT_FOUND
+        })?;
+    if video.user_id != user.id {
+        tracing::warn!(
+            "User {} attempted to complete upload for video {} owned by {}",
+            user.id,
+            video.id,
+            video.user_id
+        );
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if video.raw_video_path != request.key {
+        tracing::error!(
+            "Upload key {} does not match video {} raw path {}",
+            request.key,
+            video.id,
+            video.raw_video_path
+        );
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    // Let storage know we've completed the upload
+    let completed_parts: Vec<CompletedPart> = request
         .completed_parts
         .iter()
-        .map(|part| {
-            CompletedPart::builder()
-                .e_tag(part.etag.clone())
-                .part_number(part.number)
-                .build()
+        .map(|part| CompletedPart {
+            part_number: part.number,
+            etag: part.etag.clone(),
         })
         .collect();
 
-    let completed_upload = CompletedMultipartUpload::builder()
-        .set_parts(Some(serialized_completed_parts))
-        .build();
-
-    let _completed_parts = state
-        .s3_client
-        .complete_multipart_upload()
-        .bucket(&bucket)
-        .key(&request.key)
-        .upload_id(&request.upload_id)
-        .multipart_upload(completed_upload)
-        .send()
+    state
+        .storage
+        .complete_multipart_upload(&request.key, &request.upload_id, &completed_parts)
         .await
         .map_err(|e| {
             tracing::error!("Could not complete multipart upload {}", e);
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
 
+    // Now that the raw video is in storage, kick off processing
+    queue_video_processing(&state, &video.id).await?;
+
     Ok(StatusCode::ACCEPTED)
 }
@@ -1,15 +1,27 @@
 use crate::{
     api::app_state::AppState,
-    db::{ProcessingStatus, User, Video},
+    db::{users::UserRole, ProcessingStatus, User, Video, VideoImages, VideoMetadata},
+    error::StorageError,
+    prelude::get_storage_dir,
+    queue::{
+        delete_assets::{delete_video_assets, DeleteAssetsPayload},
+        hls_stream::VideoToStreamPayload,
+    },
+    storage::{ObjectHeaders, Storage},
+    vod::probe::MediaInfo,
 };
 use axum::{
-    extract::{Query, State},
-    http::StatusCode,
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
     response::IntoResponse,
     Extension, Json,
 };
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+/// How long the image URLs handed out with a video keep working
+const IMAGE_URL_EXPIRY: Duration = Duration::from_secs(6 * 60 * 60);
 
 #[derive(Deserialize, Debug)]
 pub struct VideoByID {
@@ -26,11 +38,114 @@ pub struct SanitizedVideoData {
     id: String,
     title: String,
     processing_status: ProcessingStatus,
+    /// A short reason processing failed
+    processing_message: Option<String>,
+    /// The full error behind the message, only for the video's owner and admins
+    #[serde(skip_serializing_if = "Option::is_none")]
+    processing_error: Option<String>,
     video_path: Option<String>,
+    /// Only set for videos converted to CMAF
+    dash_manifest_path: Option<String>,
+    /// What ffprobe found in the upload, missing until the video has been probed
+    metadata: Option<MediaInfo>,
+    /// Stills for the video, missing until they've been generated
+    images: Option<SanitizedVideoImages>,
     created_at: chrono::DateTime<chrono::Utc>,
     updated_at: chrono::DateTime<chrono::Utc>,
 }
 
+/// Signed URLs of the video's stills, the storage keys stay private
+#[derive(Serialize)]
+pub struct SanitizedVideoImages {
+    poster_path: String,
+    thumbnail_path: String,
+    thumbnail_candidates: Vec<String>,
+    /// WebVTT thumbnails track for the scrub bar
+    storyboard_path: String,
+}
+
+impl SanitizedVideoImages {
+    /// Signs a URL for each of the images, so clients can load them straight from storage
+    async fn resolve(storage: &dyn Storage, images: VideoImages) -> Result<Self, StorageError> {
+        let mut thumbnail_candidates = Vec::with_capacity(images.thumbnail_candidates.len());
+        for key in &images.thumbnail_candidates {
+            thumbnail_candidates.push(storage.presign_get(key, IMAGE_URL_EXPIRY).await?);
+        }
+        Ok(Self {
+            poster_path: storage
+                .presign_get(&images.poster_path, IMAGE_URL_EXPIRY)
+                .await?,
+            thumbnail_path: storage
+                .presign_get(&images.thumbnail_path, IMAGE_URL_EXPIRY)
+                .await?,
+            thumbnail_candidates,
+            storyboard_path: storage
+                .presign_get(&images.storyboard_path, IMAGE_URL_EXPIRY)
+                .await?,
+        })
+    }
+}
+
+/// Strips videos down to what's safe to show the user, along with their metadata and images
+async fn sanitize_videos(
+    state: &AppState,
+    videos: Vec<Video>,
+    user: Option<&User>,
+) -> Vec<SanitizedVideoData> {
+    let video_ids: Vec<String> = videos.iter().map(|video| video.id.clone()).collect();
+    let mut metadata: HashMap<String, MediaInfo> =
+        match VideoMetadata::by_video_ids(&state.db, &video_ids).await {
+            Ok(metadata) => metadata
+                .into_iter()
+                .map(|metadata| (metadata.video_id, metadata.info))
+                .collect(),
+            Err(e) => {
+                tracing::error!("Error getting video metadata {e}");
+                HashMap::new()
+            }
+        };
+    let stored_images = match VideoImages::by_video_ids(&state.db, &video_ids).await {
+        Ok(images) => images,
+        Err(e) => {
+            tracing::error!("Error getting video images {e}");
+            Vec::new()
+        }
+    };
+    let mut images: HashMap<String, SanitizedVideoImages> = HashMap::new();
+    for stored in stored_images {
+        let video_id = stored.video_id.clone();
+        match SanitizedVideoImages::resolve(&*state.storage, stored).await {
+            Ok(resolved) => {
+                images.insert(video_id, resolved);
+            }
+            Err(e) => tracing::error!("Error signing image URLs for video {video_id}: {e}"),
+        }
+    }
+
+    videos
+        .into_iter()
+        .map(|video| SanitizedVideoData {
+            // Errors can hold internal details like file paths, so only owners and admins see them
+            processing_error: match user {
+                Some(user) if user.role == UserRole::Admin || user.id == video.user_id => {
+                    video.processing_error
+                }
+                _ => None,
+            },
+            processing_message: video.processing_message,
+            metadata: metadata.remove(&video.id),
+            images: images.remove(&video.id),
+            id: video.id,
+            title: video.title,
+            processing_status: video.processing_status,
+            video_path: video.processed_video_path,
+            dash_manifest_path: video.dash_manifest_path,
+            created_at: video.created_at,
+            updated_at: video.updated_at,
+        })
+        .collect()
+}
+
 #[derive(Serialize)]
 pub struct VideoResponse {
     videos: Vec<SanitizedVideoData>,
@@ -39,7 +154,7 @@ pub struct VideoResponse {
 /// A function for getting videos based on video id, user id, username, or combinations thereof
 pub async fn get_videos(
     State(state): State<Arc<AppState>>,
-    Extension(_user): Extension<Option<User>>,
+    Extension(user): Extension<Option<User>>,
     video_query: Option<Query<VideoByID>>,
     username_query: Option<Query<VideoByUserName>>,
 ) -> impl IntoResponse {
@@ -54,17 +169,8 @@ pub async fn get_videos(
             let video = Video::by_id(&state.db, &video_query.id)
                 .await
                 .map_err(|_e| StatusCode::BAD_REQUEST)?;
-            let res_video = SanitizedVideoData {
-                id: video.id,
-                processing_status: video.processing_status,
-                title: video.title,
-                video_path: video.processed_video_path,
-                created_at: video.created_at,
-                updated_at: video.updated_at,
-            };
-            Ok(Json(VideoResponse {
-                videos: vec![res_video],
-            }))
+            let videos = sanitize_videos(&state, vec![video], user.as_ref()).await;
+            Ok(Json(VideoResponse { videos }))
         }
         // Videos by user name
         (None, Some(username_query)) => {
@@ -76,17 +182,7 @@ pub async fn get_videos(
                 })?;
 
             if !videos.is_empty() {
-                let videos = videos
-                    .into_iter()
-                    .map(|video| SanitizedVideoData {
-                        id: video.id,
-                        title: video.title,
-                        processing_status: video.processing_status,
-                        video_path: video.processed_video_path,
-                        created_at: video.created_at,
-                        updated_at: video.updated_at,
-                    })
-                    .collect();
+                let videos = sanitize_videos(&state, videos, user.as_ref()).await;
                 Ok(Json(VideoResponse { videos }))
             } else {
                 Err(StatusCode::NOT_FOUND)
@@ -105,17 +201,7 @@ pub async fn get_videos(
                 }
             };
 
-            let videos = videos
-                .into_iter()
-                .map(|video| SanitizedVideoData {
-                    id: video.id,
-                    title: video.title,
-                    processing_status: video.processing_status,
-                    video_path: video.processed_video_path,
-                    created_at: video.created_at,
-                    updated_at: video.updated_at,
-                })
-                .collect();
+            let videos = sanitize_videos(&state, videos, user.as_ref()).await;
             Ok(Json(VideoResponse { videos }))
         }
     }
@@ -124,9 +210,20 @@ pub async fn get_videos(
 #[derive(Serialize)]
 pub struct DeleteVideoResponse {
     deleted_videos: Vec<String>,
+    /// Videos whose assets couldn't all be deleted, they're kept until cleanup succeeds
+    failed_videos: Vec<FailedVideoDeletion>,
 }
 
-// TODO: Refactor to delete from R2 instead
+#[derive(Serialize)]
+pub struct FailedVideoDeletion {
+    video_id: String,
+    error: String,
+    /// Whether cleanup is being retried in the background
+    retrying: bool,
+}
+
+/// Deletes videos along with everything they have in storage
+/// Videos whose assets can't be deleted are left in place and cleaned up in the background
 pub async fn delete_videos(
     State(state): State<Arc<AppState>>,
     Extension(user): Extension<Option<User>>,
@@ -156,8 +253,9 @@ pub async fn delete_videos(
     };
 
     let mut successfully_deleted_ids = Vec::new();
+    let mut failed_videos = Vec::new();
 
-    // Delete the physical video files
+    // Delete the video assets from storage
     for video in &videos {
         // Only allow deletion if the user owns the video
         if video.user_id != user.id {
@@ -170,98 +268,272 @@ pub async fn delete_videos(
             continue; // Skip this video and continue with others
         }
 
-        let mut deletion_successful = true;
-
-        // Delete raw video file
-        tracing::debug!(
-            "Attempting to delete raw video file {}",
-            video.raw_video_path
-        );
-        if let Err(e) = tokio::fs::remove_file(&video.raw_video_path).await {
-            if e.kind() == std::io::ErrorKind::NotFound {
-                tracing::debug!("Raw video file {} already deleted", video.raw_video_path);
-            } else {
-                tracing::error!(
-                    "Failed to delete raw video file {}: {}",
-                    video.raw_video_path,
-                    e
-                );
-                deletion_successful = false;
+        // Stop any processing for the video before its files go away
+        match state.job_queue.cancel_for_video(&video.id).await {
+            Ok(cancelled) if !cancelled.is_empty() => {
+                tracing::info!("Cancelled jobs {:?} for video {}", cancelled, video.id);
             }
-        } else {
-            tracing::debug!(
-                "Successfully deleted raw video file {}",
-                video.raw_video_path
-            );
+            Ok(_) => {}
+            Err(e) => tracing::error!("Failed to cancel jobs for video {}: {}", video.id, e),
         }
 
-        // For the processed video folder:
-        if let Some(processed_path) = &video.processed_video_path {
-            tracing::debug!("Attempting to delete processed video folder");
-            let path = std::path::PathBuf::from(processed_path);
-
-            if let Some(parent_dir) = path.parent() {
-                if let Some(folder_name) = parent_dir.file_name() {
-                    if let Some(folder_str) = folder_name.to_str() {
-                        if folder_str == video.id {
-                            if let Err(e) = tokio::fs::remove_dir_all(parent_dir).await {
-                                if e.kind() == std::io::ErrorKind::NotFound {
-                                    tracing::debug!(
-                                        "Processed video folder {} already deleted",
-                                        parent_dir.display()
-                                    );
-                                } else {
-                                    tracing::error!(
-                                        "Failed to delete processed video folder {}: {}",
-                                        parent_dir.display(),
-                                        e
-                                    );
-                                    deletion_successful = false;
-                                }
-                            } else {
-                                tracing::debug!(
-                                    "Successfully deleted processed video folder {}",
-                                    parent_dir.display()
-                                );
-                            }
-                        } else {
-                            tracing::error!(
-                                "Video folder name '{}' doesn't match video ID '{}'. Skipping deletion.",
-                                folder_str,
-                                video.id
-                            );
-                            deletion_successful = false;
-                        }
-                    } else {
-                        tracing::error!("Invalid folder name encoding");
-                        deletion_successful = false;
+        match delete_video_assets(&*state.storage, &video.id).await {
+            Ok(_) => successfully_deleted_ids.push(video.id.clone()),
+            Err(e) => {
+                tracing::error!("Failed to delete assets of video {}: {}", video.id, e);
+                // The cleanup job deletes the video once it gets the assets out of storage
+                let payload = DeleteAssetsPayload {
+                    video_id: video.id.clone(),
+                    user_id: user.id,
+                };
+                let retrying = match state.job_queue.enqueue(&payload).await {
+                    Ok(_) => true,
+                    Err(e) => {
+                        tracing::error!(
+                            "Could not queue asset cleanup for video {}: {}",
+                            video.id,
+                            e
+                        );
+                        false
                     }
-                } else {
-                    tracing::error!("Couldn't get folder name from path");
-                    deletion_successful = false;
-                }
-            } else {
-                tracing::error!("Couldn't get parent directory from path");
-                deletion_successful = false;
+                };
+                failed_videos.push(FailedVideoDeletion {
+                    video_id: video.id.clone(),
+                    error: e.to_string(),
+                    retrying,
+                });
             }
         }
+    }
 
-        // Only add to successfully deleted list if all files were deleted
-        if deletion_successful {
-            successfully_deleted_ids.push(video.id.clone());
+    // Only delete videos from database once their assets are gone
+    if !successfully_deleted_ids.is_empty() {
+        if let Err(e) = Video::delete(&state.db, user.id, successfully_deleted_ids.clone()).await {
+            tracing::error!(
+                "Failed to delete videos {:?}: {}",
+                successfully_deleted_ids,
+                e
+            );
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
     }
+    if successfully_deleted_ids.is_empty() && failed_videos.is_empty() {
+        // None of the videos could be deleted
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    Ok(Json(DeleteVideoResponse {
+        deleted_videos: successfully_deleted_ids,
+        failed_videos,
+    }))
+}
 
-    // Only delete videos from database if we successfully deleted their files
-    if !successfully_deleted_ids.is_empty() {
-        match Video::delete(&state.db, user.id, successfully_deleted_ids.clone()).await {
-            Ok(_) => Ok(Json(DeleteVideoResponse {
-                deleted_videos: successfully_deleted_ids,
-            })),
-            Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+/// Marks a video as queued and publishes the job to convert it into a stream
+pub async fn queue_video_processing(state: &AppState, video_id: &str) -> Result<(), StatusCode> {
+    Video::update_status(&state.db, video_id.to_string(), ProcessingStatus::Queued)
+        .await
+        .map_err(|e| {
+            tracing::error!("Could not mark video {} as queued {}", video_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let payload = VideoToStreamPayload {
+        video_id: video_id.to_string(),
+    };
+    if let Err(e) = state.job_queue.enqueue(&payload).await {
+        tracing::error!(
+            "Could not publish processing job for video {} {}",
+            video_id,
+            e
+        );
+        // Put the video back to pending so it can be reprocessed later
+        if let Err(e) =
+            Video::update_status(&state.db, video_id.to_string(), ProcessingStatus::Pending).await
+        {
+            tracing::error!("Could not reset status of video {} {}", video_id, e);
+        }
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    tracing::info!("Queued processing job for video {}", video_id);
+    Ok(())
+}
+
+/// Re-publishes the processing job for a video, used for failed or outdated videos
+pub async fn reprocess_video(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<Option<User>>,
+    Path(video_id): Path<String>,
+) -> impl IntoResponse {
+    let Some(user) = user else {
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    };
+
+    let Ok(video) = Video::by_id(&state.db, &video_id).await else {
+        return (StatusCode::NOT_FOUND, "Video not found").into_response();
+    };
+
+    // Only the owner or an admin can reprocess a video
+    if video.user_id != user.id && user.role != UserRole::Admin {
+        return (StatusCode::FORBIDDEN, "You do not own this video").into_response();
+    }
+
+    // Don't publish a second job while one is already in flight
+    if matches!(
+        video.processing_status,
+        ProcessingStatus::Queued | ProcessingStatus::Processing
+    ) {
+        return (StatusCode::CONFLICT, "Video is already being processed").into_response();
+    }
+
+    // Make sure there's actually a raw video to process
+    match state.storage.exists(&video.raw_video_path).await {
+        Ok(true) => {}
+        Ok(false) => {
+            tracing::error!("Raw video for {} is not available", video.id);
+            return (StatusCode::CONFLICT, "Raw video is not available").into_response();
+        }
+        Err(e) => {
+            tracing::error!("Could not check for raw video of {} {}", video.id, e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Could not check for raw video",
+            )
+                .into_response();
+        }
+    }
+
+    match queue_video_processing(&state, &video.id).await {
+        Ok(_) => StatusCode::ACCEPTED.into_response(),
+        Err(status) => (status, "Could not queue video processing").into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SelectThumbnailRequest {
+    /// Index of the generated candidate to show
+    candidate: usize,
+}
+
+/// Loads a video for its owner, along with its generated images
+async fn owned_video_images(
+    state: &AppState,
+    user: Option<User>,
+    video_id: &str,
+) -> Result<VideoImages, (StatusCode, &'static str)> {
+    let user = user.ok_or((StatusCode::UNAUTHORIZED, "Unauthorized"))?;
+    let video = Video::by_id(&state.db, video_id)
+        .await
+        .map_err(|_| (StatusCode::NOT_FOUND, "Video not found"))?;
+    if video.user_id != user.id && user.role != UserRole::Admin {
+        return Err((StatusCode::FORBIDDEN, "You do not own this video"));
+    }
+    match VideoImages::by_video_id(&state.db, video_id).await {
+        Ok(Some(images)) => Ok(images),
+        Ok(None) => Err((
+            StatusCode::CONFLICT,
+            "Thumbnails haven't been generated yet",
+        )),
+        Err(e) => {
+            tracing::error!("Could not get images for video {}: {}", video_id, e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Could not get thumbnails",
+            ))
+        }
+    }
+}
+
+/// Shows one of the generated candidates as the video's thumbnail
+pub async fn select_thumbnail(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<Option<User>>,
+    Path(video_id): Path<String>,
+    Json(request): Json<SelectThumbnailRequest>,
+) -> impl IntoResponse {
+    let images = match owned_video_images(&state, user, &video_id).await {
+        Ok(images) => images,
+        Err(e) => return e.into_response(),
+    };
+    let Some(thumbnail) = images.thumbnail_candidates.get(request.candidate) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "No thumbnail candidate at that index",
+        )
+            .into_response();
+    };
+
+    match VideoImages::set_thumbnail(&state.db, &video_id, thumbnail).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            tracing::error!("Could not set thumbnail for video {}: {}", video_id, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Could not set thumbnail").into_response()
+        }
+    }
+}
+
+/// Uploads an image of the owner's own as the video's thumbnail
+/// The image is the request body, with its type in the Content-Type header
+pub async fn upload_thumbnail(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<Option<User>>,
+    Path(video_id): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> impl IntoResponse {
+    if let Err(e) = owned_video_images(&state, user, &video_id).await {
+        return e.into_response();
+    }
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let extension = match content_type {
+        "image/jpeg" => "jpg",
+        "image/png" => "png",
+        "image/webp" => "webp",
+        _ => {
+            return (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "Thumbnails must be JPEG, PNG or WebP",
+            )
+                .into_response()
+        }
+    };
+    if body.is_empty() {
+        return (StatusCode::BAD_REQUEST, "Thumbnail is empty").into_response();
+    }
+
+    // A new name for every upload keeps caches from holding on to the old thumbnail
+    let key = format!(
+        "{}/{}/thumbnails/custom_{}.{}",
+        get_storage_dir(),
+        video_id,
+        chrono::Utc::now().timestamp(),
+        extension
+    );
+    if let Err(e) = state
+        .storage
+        .put(&key, body, ObjectHeaders::for_key(&key))
+        .await
+    {
+        tracing::error!("Could not upload thumbnail {}: {}", key, e);
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Could not upload thumbnail",
+        )
+            .into_response();
+    }
+
+    if let Err(e) = VideoImages::set_thumbnail(&state.db, &video_id, &key).await {
+        tracing::error!("Could not set thumbnail for video {}: {}", video_id, e);
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Could not set thumbnail").into_response();
+    }
+    // Responds with where the new thumbnail can be loaded from, like the video's images
+    match state.storage.presign_get(&key, IMAGE_URL_EXPIRY).await {
+        Ok(url) => (StatusCode::CREATED, url).into_response(),
+        Err(e) => {
+            tracing::error!("Could not sign thumbnail URL {}: {}", key, e);
+            StatusCode::CREATED.into_response()
         }
-    } else {
-        // No videos were successfully deleted
-        Err(StatusCode::INTERNAL_SERVER_ERROR)
     }
 }
@@ -132,15 +132,16 @@ pub async fn handle_webhook(
                         )
                             .into_response();
                     };
-                    state
-                        .event_stream
-                        .publish(subject, payload)
-                        .await
-                        .map_err(|e| {
-                            tracing::error!("Failed to publish stream status event: {}", e);
-                            StatusCode::INTERNAL_SERVER_ERROR
-                        })
-                        .expect("Failed to publish stream status event");
+                    if let Some(event_stream) = &state.event_stream {
+                        event_stream
+                            .publish(subject, payload)
+                            .await
+                            .map_err(|e| {
+                                tracing::error!("Failed to publish stream status event: {}", e);
+                                StatusCode::INTERNAL_SERVER_ERROR
+                            })
+                            .expect("Failed to publish stream status event");
+                    }
                 }
                 "stream.offline" => {
                     let Some(raw_payload) = notification.event else {
@@ -207,15 +208,16 @@ pub async fn handle_webhook(
                         )
                             .into_response();
                     };
-                    state
-                        .event_stream
-                        .publish(subject, payload)
-                        .await
-                        .map_err(|e| {
-                            tracing::error!("Failed to publish stream status event: {}", e);
-                            StatusCode::INTERNAL_SERVER_ERROR
-                        })
-                        .expect("Failed to publish stream status event");
+                    if let Some(event_stream) = &state.event_stream {
+                        event_stream
+                            .publish(subject, payload)
+                            .await
+                            .map_err(|e| {
+                                tracing::error!("Failed to publish stream status event: {}", e);
+                                StatusCode::INTERNAL_SERVER_ERROR
+                            })
+                            .expect("Failed to publish stream status event");
+                    }
                 }
                 "channel.follow" => {
                     return (
@@ -252,15 +254,16 @@ pub async fn handle_webhook(
                         tracing::error!("Failed to parse channel.chat.message notification");
                         return (StatusCode::BAD_REQUEST, "Invalid event data").into_response();
                     };
-                    state
-                        .event_stream
-                        .publish(subject.to_string(), payload)
-                        .await
-                        .map_err(|e| {
-                            tracing::error!("Failed to publish chat message job: {}", e);
-                            StatusCode::INTERNAL_SERVER_ERROR
-                        })
-                        .expect("Failed to publish chat message job");
+                    if let Some(event_stream) = &state.event_stream {
+                        event_stream
+                            .publish(subject.to_string(), payload)
+                            .await
+                            .map_err(|e| {
+                                tracing::error!("Failed to publish chat message job: {}", e);
+                                StatusCode::INTERNAL_SERVER_ERROR
+                            })
+                            .expect("Failed to publish chat message job");
+                    }
                 }
                 _ => {
                     tracing::warn!("Unhandled notification event type: {}", notification_type);
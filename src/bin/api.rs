@@ -1,4 +1,5 @@
 use axum::{
+    extract::DefaultBodyLimit,
     middleware as axum_mw,
     response::IntoResponse,
     routing::{delete, get, post, put},
@@ -100,11 +101,87 @@ async fn main() {
             Router::new()
                 .route("/", get(routes::video::get_videos))
                 .route("/", delete(routes::video::delete_videos))
+                .route("/:id/reprocess", post(routes::video::reprocess_video))
+                .route("/:id/jobs", get(routes::jobs::get_video_jobs))
+                .route("/:id/workflows", get(routes::jobs::get_video_workflows))
+                .route("/:id/thumbnail", put(routes::video::select_thumbnail))
+                .route("/:id/thumbnail", post(routes::video::upload_thumbnail))
                 .layer(axum_mw::from_fn_with_state(
                     state.clone(),
                     middleware::auth::auth_middleware,
                 )),
         )
+        .nest(
+            "/jobs",
+            Router::new()
+                .route("/:id", get(routes::jobs::get_job))
+                .route("/:id/cancel", post(routes::jobs::cancel_job))
+                .layer(axum_mw::from_fn_with_state(
+                    state.clone(),
+                    middleware::auth::auth_middleware,
+                )),
+        )
+        .nest(
+            "/admin/jobs",
+            Router::new()
+                .route("/scheduled", get(routes::jobs::get_scheduled_jobs))
+                .route("/scheduled/:id", delete(routes::jobs::cancel_scheduled_job))
+                .route("/gc", post(routes::jobs::run_garbage_collection))
+                .layer(axum_mw::from_fn_with_state(
+                    state.clone(),
+                    middleware::auth::auth_middleware,
+                )),
+        )
+        .nest(
+            "/profiles",
+            Router::new()
+                .route("/", get(routes::profiles::get_profiles))
+                .layer(axum_mw::from_fn_with_state(
+                    state.clone(),
+                    middleware::auth::auth_middleware,
+                )),
+        )
+        .nest(
+            "/admin/profiles",
+            Router::new()
+                .route("/", post(routes::profiles::create_profile))
+                .route("/:id", put(routes::profiles::update_profile))
+                .route("/:id", delete(routes::profiles::delete_profile))
+                .layer(axum_mw::from_fn_with_state(
+                    state.clone(),
+                    middleware::auth::auth_middleware,
+                )),
+        )
+        .nest(
+            "/admin/dlq",
+            Router::new()
+                .route("/", get(routes::dead_letters::list_dead_letters))
+                .route("/", delete(routes::dead_letters::purge_dead_letters))
+                .route("/:sequence", get(routes::dead_letters::get_dead_letter))
+                .route(
+                    "/:sequence",
+                    delete(routes::dead_letters::delete_dead_letter),
+                )
+                .route(
+                    "/:sequence/replay",
+                    post(routes::dead_letters::replay_dead_letter),
+                )
+                .layer(axum_mw::from_fn_with_state(
+                    state.clone(),
+                    middleware::auth::auth_middleware,
+                )),
+        )
+        .nest(
+            "/storage",
+            Router::new()
+                .route("/objects/*key", get(routes::storage::get_object))
+                .route(
+                    "/parts/:upload_id/:part_number",
+                    put(routes::storage::upload_part),
+                )
+                // Parts are streamed to disk, so they can be as large as clients make them
+                .layer(DefaultBodyLimit::disable()),
+        )
         .nest_service("/videos", tower_http::services::ServeDir::new("videos"))
         .route("/health", get(routes::health::health_check))
         .with_state(state)
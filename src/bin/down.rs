@@ -1,5 +1,10 @@
 use anyhow::Result;
-use farmhand::{db, event::Stream, nats::create_nats_client, queue::Queue};
+use farmhand::{
+    db,
+    event::Stream,
+    nats::{create_nats_client, nats_enabled},
+    queue::{DeadLetterQueue, NatsQueue},
+};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
@@ -34,6 +39,12 @@ async fn down_db() -> Result<()> {
 }
 
 async fn down_nats() -> Result<()> {
+    // Deployments on the Postgres queue backend might not run NATS at all
+    if !nats_enabled()? {
+        tracing::info!("NATS is disabled, skipping stream deletion");
+        return Ok(());
+    }
+
     // Delete all streams
     tracing::debug!("Deleting all streams");
 
@@ -42,7 +53,10 @@ async fn down_nats() -> Result<()> {
 
     // Delete the job queue
     tracing::debug!("Deleting job queue");
-    Queue::delete(nats_client.clone()).await?;
+    NatsQueue::delete(nats_client.clone()).await?;
+    // Delete the dead letter queue
+    tracing::debug!("Deleting dead letter queue");
+    DeadLetterQueue::delete(nats_client.clone()).await?;
     // Delete the event stream
     tracing::debug!("Deleting event stream");
     Stream::delete(nats_client.clone()).await?;
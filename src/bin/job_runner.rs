@@ -1,12 +1,44 @@
 use anyhow::Result;
-use async_nats::jetstream::AckKind;
+use bytes::Bytes;
 use farmhand::{
-    event::MESSAGE_PREFIX,
-    nats::create_nats_client,
-    queue::{process_message, Queue},
+    error::JobError,
+    nats::create_nats_client_if_enabled,
+    queue::{
+        archive_raw::ArchiveRawRunner,
+        delete_assets::DeleteAssetsRunner,
+        garbage_collect::{GarbageCollectRunner, GcConfig},
+        generate_images::GenerateImagesRunner,
+        hls_stream::HlsStreamRunner,
+        probe_media::ProbeMediaRunner,
+        publish_stream::PublishStreamRunner,
+        transcode_audio::TranscodeAudioRunner,
+        transcode_ladder::TranscodeLadderRunner,
+        transcode_rendition::TranscodeRenditionRunner,
+        Ack, Delivery, JobContext, RunnerConfig, RunnerRegistry, RunnerState, StepOutcome,
+        ACK_WAIT,
+    },
 };
-use futures::StreamExt;
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::Semaphore,
+};
+use tokio_util::sync::CancellationToken;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use uuid::Uuid;
+
+/// How often scheduled jobs are checked for ones that are due
+const SCHEDULER_INTERVAL: Duration = Duration::from_secs(5);
+/// The most scheduled jobs published in one pass
+const SCHEDULER_BATCH_SIZE: i64 = 100;
+/// How often a running job tells the queue it's still being worked on
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(ACK_WAIT.as_secs() / 4);
+/// How often a running job checks whether it was cancelled
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// How long to wait before fetching jobs again after a fetch fails
+const FETCH_BACKOFF: Duration = Duration::from_secs(5);
+/// How long running jobs get to finish after a shutdown signal before they're returned to the queue
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(25);
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -16,55 +48,292 @@ async fn main() -> Result<()> {
         )
         .with(tracing_subscriber::fmt::layer())
         .init();
-    // Connect to the stream
+    // Connect to NATS, unless the Postgres queue backend is used without it
     tracing::debug!("Connecting to NATS server");
-    let nats_client = create_nats_client().await?;
-    tracing::debug!("Connecting to queue");
-    let queue = Queue::connect(nats_client)
-        .await
-        .expect("Failed to create worker queue");
+    let nats_client = create_nats_client_if_enabled().await?;
+    // Create the state shared between all runners
+    tracing::debug!("Creating runner state");
+    let state = Arc::new(RunnerState::new(nats_client).await?);
+    // Get when garbage is collected
+    let gc_config = GcConfig::new();
+    // Register a runner for every job type this binary handles
+    let registry = Arc::new(
+        RunnerRegistry::new()
+            .register(HlsStreamRunner::new(state.clone()))
+            .register(ProbeMediaRunner::new(state.clone()))
+            .register(GenerateImagesRunner::new(state.clone()))
+            .register(TranscodeRenditionRunner::new(state.clone()))
+            .register(TranscodeAudioRunner::new(state.clone()))
+            .register(TranscodeLadderRunner::new(state.clone()))
+            .register(PublishStreamRunner::new(state.clone()))
+            .register(ArchiveRawRunner::new(state.clone()))
+            .register(DeleteAssetsRunner::new(state.clone()))
+            .register(GarbageCollectRunner::new(state.clone(), &gc_config)),
+    );
+    // Refuse to start if there are jobs waiting that nothing can process
+    registry.verify(&state.job_queue).await?;
+    tracing::debug!("Registered runners for {:?}", registry.subjects());
+
+    // Publish scheduled jobs onto the queue once they're due
+    let scheduler_state = state.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SCHEDULER_INTERVAL);
+        loop {
+            interval.tick().await;
+            match scheduler_state
+                .job_queue
+                .publish_due(SCHEDULER_BATCH_SIZE)
+                .await
+            {
+                Ok(0) => {}
+                Ok(published) => tracing::info!("Published {} scheduled jobs", published),
+                Err(e) => tracing::error!("Failed to publish scheduled jobs: {}", e),
+            }
+        }
+    });
 
-    // Get all jobs from the stream
-    let subject = format!("{}.jobs.>", MESSAGE_PREFIX); // All farmhand jobs
+    // Keep the next garbage collection scheduled, runners all pick the same one
+    let gc_state = state.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(gc_config.interval);
+        loop {
+            interval.tick().await;
+            if let Err(e) = gc_config.schedule_next(&gc_state.job_queue).await {
+                tracing::error!("Failed to schedule garbage collection: {}", e);
+            }
+        }
+    });
 
-    // TODO: Make this ID dynamic so we can run more than one runner at a time
-    // Make sure not too make it too dynamic, as they are intended to be re-used
-    let runner_name = "farmhand_runner_1".to_string();
-    tracing::info!("Listening for jobs {} on {}", subject, runner_name);
+    // Get the jobs this runner is responsible for
+    let config = RunnerConfig::new();
+    tracing::info!("Starting job runner {}", config.describe());
     // Create the consumer to listen for jobs
-    let consumer = queue.create_consumer(Some(runner_name), subject).await?;
+    let consumer = state
+        .job_queue
+        .create_consumer(&config.name, &config.describe(), config.subjects.clone())
+        .await?;
+    // Stop fetching jobs on shutdown, then give in-flight jobs until the deadline to finish
+    let shutdown = CancellationToken::new();
+    let abort = CancellationToken::new();
+    tokio::spawn(watch_for_shutdown(shutdown.clone(), abort.clone()));
+
+    // Every running job holds a slot, so only fetch as many jobs as there are free slots
+    let slots = Arc::new(Semaphore::new(config.concurrency));
     // Start consuming jobs
-    loop {
-        // TODO: Make this max_messages dynamic
-        let mut jobs = consumer.fetch().max_messages(3).messages().await?;
-        // Start processing jobs
-        let mut handles = Vec::new();
-        while let Some(job) = jobs.next().await {
-            // Make sure the job is good to go
-            let Ok(job) = job else {
-                tracing::error!("Failed to receive job");
-                continue;
-            };
-            // Process the message itself, ack on success, nack on failure
-            let handle = tokio::spawn(async move {
-                match process_message(&job.message).await {
-                    Ok(_) => job.ack().await.expect("Failed to ack job"),
-                    Err(err) => {
-                        tracing::error!("Failed to process job: {}", err);
-                        job.ack_with(AckKind::Nak(None))
-                            .await
-                            .expect("Failed to nack job");
+    while !shutdown.is_cancelled() {
+        let available = slots.available_permits();
+        if available > 0 {
+            let jobs = match consumer.fetch(available).await {
+                Ok(jobs) => jobs,
+                // Keep running so in-flight jobs still finish, the queue is likely only briefly down
+                Err(e) => {
+                    tracing::error!(
+                        "Failed to fetch jobs, retrying in {:?}: {}",
+                        FETCH_BACKOFF,
+                        e
+                    );
+                    tokio::select! {
+                        _ = tokio::time::sleep(FETCH_BACKOFF) => {}
+                        _ = shutdown.cancelled() => {}
                     }
+                    continue;
                 }
-            });
-            handles.push(handle);
-        }
-
-        for handle in handles {
-            handle.await.expect("Task failed");
+            };
+            for job in jobs {
+                let slot = slots
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("Job slots closed");
+                let state = state.clone();
+                let registry = registry.clone();
+                let abort = abort.clone();
+                tokio::spawn(async move {
+                    handle_job(job, state, registry, abort).await;
+                    drop(slot);
+                });
+            }
         }
 
         // Add a small delay to prevent tight loops when there are no jobs
         tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
     }
+
+    // Wait for every running job to give its slot back
+    let _ = slots.acquire_many(config.concurrency as u32).await;
+    tracing::info!("Job runner shut down");
+    Ok(())
+}
+
+/// Waits for a shutdown signal, then cancels the shutdown token
+/// Once the shutdown timeout passes the abort token is cancelled too
+async fn watch_for_shutdown(shutdown: CancellationToken, abort: CancellationToken) {
+    let mut terminate =
+        signal(SignalKind::terminate()).expect("Failed to listen for terminate signal");
+    tokio::select! {
+        _ = terminate.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+    tracing::info!(
+        "Shutting down, waiting up to {:?} for running jobs",
+        SHUTDOWN_TIMEOUT
+    );
+    shutdown.cancel();
+
+    tokio::time::sleep(SHUTDOWN_TIMEOUT).await;
+    tracing::warn!("Shutdown timeout reached, returning running jobs to the queue");
+    abort.cancel();
+}
+
+/// Processes a job, acking on success and nacking or dead lettering on failure
+/// Heartbeats are sent while it runs so the job isn't redelivered to another runner
+async fn handle_job(
+    job: Delivery,
+    state: Arc<RunnerState>,
+    registry: Arc<RunnerRegistry>,
+    abort: CancellationToken,
+) {
+    let context = JobContext::new(&job, state.db.clone());
+    // Jobs cancelled while waiting in the queue never start
+    if context.check_cancelled().await {
+        tracing::info!("Skipping cancelled job on {}", job.subject);
+        job.ack(Ack::Term).await.expect("Failed to terminate job");
+        return;
+    }
+    // Runners that keep stopping mid job never get to fail it themselves, so fail it here
+    if job.is_exhausted() {
+        let reason = format!(
+            "Job was delivered {} times without finishing",
+            context.attempt - 1
+        );
+        tracing::error!("Job on {} failed: {}", job.subject, reason);
+        context.failed(&reason).await;
+        finish_step(&state, &context, StepOutcome::Failed(reason.clone())).await;
+        dead_letter(
+            &state,
+            context.id,
+            job.subject.as_str(),
+            job.payload.clone(),
+            &reason,
+            context.attempt - 1,
+        )
+        .await;
+        job.ack(Ack::Term).await.expect("Failed to terminate job");
+        return;
+    }
+    context.started().await;
+
+    let work = registry.process(&job, &context);
+    tokio::pin!(work);
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    let mut cancel_check = tokio::time::interval(CANCEL_CHECK_INTERVAL);
+    // The first ticks complete right away, nothing needs extending or checking yet
+    heartbeat.tick().await;
+    cancel_check.tick().await;
+    let result = loop {
+        tokio::select! {
+            result = &mut work => break result,
+            _ = heartbeat.tick() => {
+                if let Err(e) = job.ack(Ack::Progress).await {
+                    tracing::warn!("Failed to send job heartbeat: {}", e);
+                }
+            }
+            // Runners watch the context to stop early once the job is cancelled
+            _ = cancel_check.tick(), if !context.is_cancelled() => {
+                context.check_cancelled().await;
+            }
+            _ = abort.cancelled() => {
+                // Hand the job to another runner rather than dropping it
+                tracing::warn!("Returning unfinished job on {} to the queue", job.subject);
+                context.deferred().await;
+                job.ack(Ack::Retry(None))
+                    .await
+                    .expect("Failed to nack job");
+                return;
+            }
+        }
+    };
+
+    match result {
+        Ok(_) => {
+            context.completed().await;
+            job.ack(Ack::Done).await.expect("Failed to ack job");
+            finish_step(&state, &context, StepOutcome::Completed(context.output())).await;
+        }
+        // Cancelled jobs end without retrying, their record already says why
+        Err(err) if context.is_cancelled() => {
+            tracing::info!("Job on {} cancelled: {}", job.subject, err);
+            job.ack(Ack::Term).await.expect("Failed to terminate job");
+            finish_step(&state, &context, StepOutcome::Cancelled).await;
+        }
+        Err(err) => {
+            let policy = registry.retry_policy(job.subject.as_str());
+            let retryable = JobError::is_retryable(&err);
+            if retryable && policy.should_retry(context.attempt) {
+                let delay = policy.delay(context.attempt);
+                tracing::warn!(
+                    "Job failed on attempt {}, retrying in {:?}: {}",
+                    context.attempt,
+                    delay,
+                    err
+                );
+                context.retrying(&err.to_string()).await;
+                job.ack(Ack::Retry(Some(delay)))
+                    .await
+                    .expect("Failed to nack job");
+                return;
+            }
+
+            if retryable {
+                tracing::error!("Job failed after {} attempts: {}", context.attempt, err);
+            } else {
+                tracing::error!("Job failed permanently: {}", err);
+            }
+            // No more attempts, keep a copy in the dead letter queue before dropping it
+            context.failed(&err.to_string()).await;
+            finish_step(&state, &context, StepOutcome::Failed(err.to_string())).await;
+            dead_letter(
+                &state,
+                context.id,
+                job.subject.as_str(),
+                job.payload.clone(),
+                &err.to_string(),
+                context.attempt,
+            )
+            .await;
+            // The failure is already recorded, so the job ends even if it can't be dead lettered
+            job.ack(Ack::Term).await.expect("Failed to terminate job");
+        }
+    }
+}
+
+/// Moves the workflow the job belongs to along, if it's part of one
+async fn finish_step(state: &RunnerState, context: &JobContext, outcome: StepOutcome) {
+    let Some(id) = context.id else {
+        return;
+    };
+    if let Err(e) = state.job_queue.finish_step(id, outcome).await {
+        tracing::error!("Failed to finish workflow step for job {}: {}", id, e);
+    }
+}
+
+/// Keeps a copy of a failed job in the dead letter queue, if there is one
+/// Without a dead letter queue the failed job record is all that's kept
+async fn dead_letter(
+    state: &RunnerState,
+    id: Option<Uuid>,
+    subject: &str,
+    payload: Bytes,
+    reason: &str,
+    attempts: i64,
+) {
+    let Some(dead_letters) = &state.dead_letters else {
+        return;
+    };
+    if let Err(e) = dead_letters
+        .publish(id, subject, payload, reason, attempts)
+        .await
+    {
+        tracing::error!("Failed to dead letter job: {}", e);
+    }
 }
@@ -2,8 +2,8 @@ use anyhow::Result;
 use farmhand::{
     db,
     event::{Stream, EVENT_PREFIX, EVENT_STREAM, JOB_PREFIX, JOB_STREAM, MESSAGE_PREFIX},
-    nats::create_nats_client,
-    queue::Queue,
+    nats::{create_nats_client, nats_enabled},
+    queue::{DeadLetterQueue, NatsQueue, QueueBackend},
 };
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
@@ -43,6 +43,11 @@ async fn init_project_db() {
 
 /// Function for initializing project-wide nats dependencies
 async fn init_project_nats() {
+    // Deployments on the Postgres queue backend might not run NATS at all
+    if !nats_enabled().expect("Invalid queue backend") {
+        tracing::info!("NATS is disabled, skipping NATS initialization");
+        return;
+    }
     tracing::debug!("Starting NATS initialization");
 
     // Connect to the NATS server
@@ -62,16 +67,23 @@ async fn init_project_nats() {
     .await
     .expect("Failed to create worker queue");
 
-    // Create the job stream
-    let all_jobs_subject = format!("{}.{}.>", MESSAGE_PREFIX, JOB_PREFIX);
-    Queue::new(
-        JOB_STREAM.to_string(),
-        Some("All Farmhand jobs".to_string()),
-        vec![all_jobs_subject],
-        nats_client.clone(),
-    )
-    .await
-    .expect("Failed to create job queue");
+    // Create the job stream, jobs live in the database on the Postgres backend
+    if QueueBackend::from_env().expect("Invalid queue backend") == QueueBackend::Nats {
+        let all_jobs_subject = format!("{}.{}.>", MESSAGE_PREFIX, JOB_PREFIX);
+        NatsQueue::create(
+            JOB_STREAM.to_string(),
+            Some("All Farmhand jobs".to_string()),
+            vec![all_jobs_subject],
+            nats_client.clone(),
+        )
+        .await
+        .expect("Failed to create job queue");
+    }
+
+    // Create the dead letter queue for jobs that exhaust their attempts
+    DeadLetterQueue::new(nats_client.clone())
+        .await
+        .expect("Failed to create dead letter queue");
 
     tracing::info!("Successfully initialized NATS worker queue");
 }
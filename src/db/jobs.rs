@@ -0,0 +1,432 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Uuid, PgPool, Postgres, Transaction};
+
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone)]
+pub struct JobRecord {
+    pub id: Uuid,
+    pub subject: String,
+    pub payload: serde_json::Value,
+    pub video_id: Option<String>,
+    pub status: JobStatus,
+    pub attempts: i32,
+    pub progress: f32,
+    pub error: Option<String>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// When a scheduled job gets published to the queue
+    pub run_at: Option<DateTime<Utc>>,
+    /// The payload version of a typed job
+    pub version: Option<i32>,
+    /// Jobs with the same subject and payload share a dedup key
+    pub dedup_key: Option<String>,
+    /// What the job produced, once it completed
+    pub output: Option<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::Type, Clone, PartialEq)]
+#[sqlx(type_name = "job_status", rename_all = "lowercase")]
+pub enum JobStatus {
+    Scheduled,
+    Queued,
+    Running,
+    Retrying,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// A job claimed from the jobs table by a runner, when it doubles as the queue
+#[derive(sqlx::FromRow, Debug)]
+pub struct ClaimedJob {
+    pub id: Uuid,
+    pub subject: String,
+    pub payload: serde_json::Value,
+    pub version: Option<i32>,
+    /// How many times the job has been claimed, including this time
+    pub deliveries: i32,
+}
+
+/// The fields needed to insert a new job
+pub struct NewJob<'a> {
+    pub id: Uuid,
+    pub subject: &'a str,
+    pub payload: &'a serde_json::Value,
+    pub video_id: Option<&'a str>,
+    pub version: Option<i32>,
+    pub dedup_key: Option<&'a str>,
+}
+
+impl JobRecord {
+    /// Creates a new queued job in the database
+    /// Returns none when an unfinished job with the same dedup key already exists
+    pub async fn create(pool: &PgPool, job: &NewJob<'_>) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as::<_, JobRecord>(
+            "INSERT INTO jobs (id, subject, payload, video_id, version, dedup_key)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT DO NOTHING
+            RETURNING *",
+        )
+        .bind(job.id)
+        .bind(job.subject)
+        .bind(job.payload)
+        .bind(job.video_id)
+        .bind(job.version)
+        .bind(job.dedup_key)
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Creates a new job in the database that is held back until run_at
+    /// Returns none when an unfinished job with the same dedup key already exists
+    pub async fn schedule(
+        pool: &PgPool,
+        job: &NewJob<'_>,
+        run_at: DateTime<Utc>,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as::<_, JobRecord>(
+            "INSERT INTO jobs (id, subject, payload, video_id, version, dedup_key, status, run_at)
+            VALUES ($1, $2, $3, $4, $5, $6, 'scheduled', $7)
+            ON CONFLICT DO NOTHING
+            RETURNING *",
+        )
+        .bind(job.id)
+        .bind(job.subject)
+        .bind(job.payload)
+        .bind(job.video_id)
+        .bind(job.version)
+        .bind(job.dedup_key)
+        .bind(run_at)
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Deletes a job, used for jobs that turned out to be duplicates
+    pub async fn delete(pool: &PgPool, id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM jobs WHERE id = $1")
+            .bind(id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Finds the newest job with the dedup key
+    pub async fn by_dedup_key(pool: &PgPool, dedup_key: &str) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>(
+            "SELECT * FROM jobs
+            WHERE dedup_key = $1
+            ORDER BY created_at DESC
+            LIMIT 1",
+        )
+        .bind(dedup_key)
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Counts the finished jobs with the dedup key
+    pub async fn finished_count(pool: &PgPool, dedup_key: &str) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT COUNT(*) FROM jobs
+            WHERE dedup_key = $1 AND status IN ('completed', 'failed', 'cancelled')",
+        )
+        .bind(dedup_key)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Finds scheduled jobs that haven't been published yet, soonest first
+    pub async fn scheduled(pool: &PgPool, limit: i64) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>(
+            "SELECT * FROM jobs
+            WHERE status = 'scheduled'
+            ORDER BY run_at ASC
+            LIMIT $1",
+        )
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Locks scheduled jobs that are due so only one scheduler publishes them
+    /// The lock is held until the transaction ends
+    pub async fn lock_due(
+        tx: &mut Transaction<'_, Postgres>,
+        limit: i64,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>(
+            "SELECT * FROM jobs
+            WHERE status = 'scheduled' AND run_at <= NOW()
+            ORDER BY run_at ASC
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED",
+        )
+        .bind(limit)
+        .fetch_all(&mut **tx)
+        .await
+    }
+
+    /// Marks a locked scheduled job as published to the queue
+    pub async fn mark_published(
+        tx: &mut Transaction<'_, Postgres>,
+        id: Uuid,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE jobs SET status = 'queued', available_at = NOW() WHERE id = $1")
+            .bind(id)
+            .execute(&mut **tx)
+            .await?;
+        Ok(())
+    }
+
+    /// Cancels a scheduled job before it gets published
+    /// Returns false if the job isn't scheduled, including when it was already published
+    pub async fn cancel_scheduled(pool: &PgPool, id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE jobs
+            SET status = 'cancelled',
+                finished_at = NOW()
+            WHERE id = $1 AND status = 'scheduled'",
+        )
+        .bind(id)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Finds a job by ID
+    pub async fn by_id(pool: &PgPool, id: Uuid) -> Result<Self, sqlx::Error> {
+        sqlx::query_as::<_, Self>("SELECT * FROM jobs WHERE id = $1")
+            .bind(id)
+            .fetch_one(pool)
+            .await
+    }
+
+    /// Finds all jobs for a video, newest first
+    pub async fn by_video_id(pool: &PgPool, video_id: &str) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>("SELECT * FROM jobs WHERE video_id = $1 ORDER BY created_at DESC")
+            .bind(video_id)
+            .fetch_all(pool)
+            .await
+    }
+
+    /// Cancels a job that hasn't finished yet, runners stop working on it once they notice
+    /// Returns false if the job already finished
+    pub async fn cancel(pool: &PgPool, id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE jobs
+            SET status = 'cancelled',
+                finished_at = NOW()
+            WHERE id = $1 AND status IN ('scheduled', 'queued', 'running', 'retrying')",
+        )
+        .bind(id)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Cancels every unfinished job for a video, returning the IDs of the cancelled jobs
+    pub async fn cancel_for_video(pool: &PgPool, video_id: &str) -> Result<Vec<Uuid>, sqlx::Error> {
+        sqlx::query_scalar(
+            "UPDATE jobs
+            SET status = 'cancelled',
+                finished_at = NOW()
+            WHERE video_id = $1 AND status IN ('scheduled', 'queued', 'running', 'retrying')
+            RETURNING id",
+        )
+        .bind(video_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Checks whether a job was cancelled, jobs deleted along with their video count as cancelled
+    pub async fn is_cancelled(pool: &PgPool, id: Uuid) -> Result<bool, sqlx::Error> {
+        let status = sqlx::query_scalar::<_, JobStatus>("SELECT status FROM jobs WHERE id = $1")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?;
+        Ok(status.map_or(true, |status| status == JobStatus::Cancelled))
+    }
+
+    /// Puts an existing job back in the queue, clearing out its previous run
+    pub async fn requeue(pool: &PgPool, id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE jobs
+            SET status = 'queued',
+                attempts = 0,
+                progress = 0,
+                error = NULL,
+                output = NULL,
+                started_at = NULL,
+                finished_at = NULL,
+                available_at = NOW(),
+                deliveries = 0
+            WHERE id = $1",
+        )
+        .bind(id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Marks a job as picked up by a runner
+    pub async fn mark_running(pool: &PgPool, id: Uuid, attempts: i32) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE jobs
+            SET status = 'running',
+                attempts = $1,
+                started_at = COALESCE(started_at, NOW())
+            WHERE id = $2 AND status != 'cancelled'",
+        )
+        .bind(attempts)
+        .bind(id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Marks a job as waiting in the queue
+    pub async fn mark_queued(pool: &PgPool, id: Uuid) -> Result<(), sqlx::Error> {
+        Self::update_status(pool, id, JobStatus::Queued, None).await
+    }
+
+    /// Marks a job as failed with another attempt coming
+    pub async fn mark_retrying(pool: &PgPool, id: Uuid, error: &str) -> Result<(), sqlx::Error> {
+        Self::update_status(pool, id, JobStatus::Retrying, Some(error)).await
+    }
+
+    /// Marks a job as successfully finished along with what it produced
+    pub async fn mark_completed(
+        pool: &PgPool,
+        id: Uuid,
+        output: Option<&serde_json::Value>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE jobs
+            SET status = 'completed',
+                progress = 100,
+                error = NULL,
+                output = $1,
+                finished_at = NOW()
+            WHERE id = $2 AND status != 'cancelled'",
+        )
+        .bind(output)
+        .bind(id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Marks a job as failed with no more attempts coming
+    pub async fn mark_failed(pool: &PgPool, id: Uuid, error: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE jobs
+            SET status = 'failed',
+                error = $1,
+                finished_at = NOW()
+            WHERE id = $2 AND status != 'cancelled'",
+        )
+        .bind(error)
+        .bind(id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Updates the status of a job
+    async fn update_status(
+        pool: &PgPool,
+        id: Uuid,
+        status: JobStatus,
+        error: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE jobs SET status = $1, error = $2 WHERE id = $3 AND status != 'cancelled'",
+        )
+        .bind(status)
+        .bind(error)
+        .bind(id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Records how far along a job is, as a percentage
+    pub async fn set_progress(pool: &PgPool, id: Uuid, progress: f32) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE jobs SET progress = $1 WHERE id = $2")
+            .bind(progress)
+            .bind(id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Claims jobs on the subjects that are waiting to be delivered, hiding them for lock_for
+    /// Jobs claimed by a runner that stopped responding become available again once that passes
+    /// Subjects are matched against regex patterns, skipping jobs delivered max_deliveries times
+    pub async fn claim_available(
+        pool: &PgPool,
+        subject_patterns: &[String],
+        limit: i64,
+        lock_for: std::time::Duration,
+        max_deliveries: i64,
+    ) -> Result<Vec<ClaimedJob>, sqlx::Error> {
+        sqlx::query_as::<_, ClaimedJob>(
+            "UPDATE jobs
+            SET available_at = NOW() + make_interval(secs => $1),
+                deliveries = deliveries + 1
+            WHERE id IN (
+                SELECT id FROM jobs
+                WHERE status IN ('queued', 'running', 'retrying')
+                    AND available_at <= NOW()
+                    AND deliveries < $2
+                    AND subject ~ ANY($3)
+                ORDER BY available_at ASC
+                LIMIT $4
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, subject, payload, version, deliveries",
+        )
+        .bind(lock_for.as_secs_f64())
+        .bind(max_deliveries)
+        .bind(subject_patterns)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Makes a claimed job available to runners again once the delay has passed
+    pub async fn release(
+        pool: &PgPool,
+        id: Uuid,
+        delay: std::time::Duration,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE jobs SET available_at = NOW() + make_interval(secs => $1) WHERE id = $2",
+        )
+        .bind(delay.as_secs_f64())
+        .bind(id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Stops a claimed job from ever being delivered again
+    pub async fn settle(pool: &PgPool, id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE jobs SET available_at = NULL WHERE id = $1")
+            .bind(id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Finds every subject with jobs that are waiting or being worked on
+    pub async fn pending_subjects(pool: &PgPool) -> Result<Vec<String>, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT DISTINCT subject FROM jobs
+            WHERE status IN ('queued', 'running', 'retrying') AND available_at IS NOT NULL",
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
@@ -1,10 +1,20 @@
 pub mod accounts;
+pub mod jobs;
 pub mod streams;
+pub mod transcode_profiles;
 pub mod users;
+pub mod video_images;
+pub mod video_metadata;
 pub mod videos;
+pub mod workflows;
 
+pub use jobs::{ClaimedJob, JobRecord, JobStatus, NewJob};
+pub use transcode_profiles::{NewTranscodeProfile, TranscodeProfile};
 pub use users::User;
-pub use videos::{ProcessingStatus, Video};
+pub use video_images::{NewVideoImages, VideoImages};
+pub use video_metadata::VideoMetadata;
+pub use videos::{CompressionStatus, ProcessingStatus, Video};
+pub use workflows::{WorkflowRecord, WorkflowStatus, WorkflowStepRecord, WorkflowStepStatus};
 
 use sqlx::{postgres::PgPool, Pool, Postgres};
 
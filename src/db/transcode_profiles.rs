@@ -0,0 +1,174 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    types::{Json, Uuid},
+    PgPool,
+};
+
+use crate::vod::{
+    ladder::{EncodingLadder, EncodingMode, EncodingSettings, ManifestType, VideoCodec},
+    stream::Quality,
+};
+
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone)]
+pub struct TranscodeProfile {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub renditions: Json<Vec<Quality>>,
+    pub codec: VideoCodec,
+    pub preset: String,
+    /// Length of each HLS segment in seconds
+    pub segment_duration: i32,
+    /// Whether videos get plain HLS or CMAF segments with a DASH manifest too
+    pub manifest_type: ManifestType,
+    /// Whether the renditions are encoded one at a time or all in a single pass
+    pub encoding_mode: EncodingMode,
+    /// Used for videos uploaded without picking a profile
+    pub is_default: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The fields needed to insert or update a profile
+pub struct NewTranscodeProfile<'a> {
+    pub name: &'a str,
+    pub description: Option<&'a str>,
+    pub ladder: &'a EncodingLadder,
+    pub is_default: bool,
+}
+
+impl TranscodeProfile {
+    /// The encoding ladder the profile describes
+    pub fn ladder(&self) -> EncodingLadder {
+        EncodingLadder {
+            qualities: self.renditions.0.clone(),
+            settings: EncodingSettings {
+                codec: self.codec,
+                preset: self.preset.clone(),
+                segment_duration: self.segment_duration as u32,
+                manifest: self.manifest_type,
+                mode: self.encoding_mode,
+            },
+        }
+    }
+
+    /// Creates a new profile, taking over as the default if it's marked as one
+    pub async fn create(
+        pool: &PgPool,
+        profile: &NewTranscodeProfile<'_>,
+    ) -> Result<Self, sqlx::Error> {
+        let mut tx = pool.begin().await?;
+        if profile.is_default {
+            sqlx::query("UPDATE transcode_profiles SET is_default = FALSE WHERE is_default")
+                .execute(&mut *tx)
+                .await?;
+        }
+        let created = sqlx::query_as::<_, Self>(
+            "INSERT INTO transcode_profiles
+                (id, name, description, renditions, codec, preset, segment_duration,
+                 manifest_type, encoding_mode, is_default)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            RETURNING *",
+        )
+        .bind(Uuid::new_v4())
+        .bind(profile.name)
+        .bind(profile.description)
+        .bind(Json(&profile.ladder.qualities))
+        .bind(profile.ladder.settings.codec)
+        .bind(&profile.ladder.settings.preset)
+        .bind(profile.ladder.settings.segment_duration as i32)
+        .bind(profile.ladder.settings.manifest)
+        .bind(profile.ladder.settings.mode)
+        .bind(profile.is_default)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(created)
+    }
+
+    /// Replaces everything about a profile, taking over as the default if it's marked as one
+    pub async fn update(
+        pool: &PgPool,
+        id: Uuid,
+        profile: &NewTranscodeProfile<'_>,
+    ) -> Result<Self, sqlx::Error> {
+        let mut tx = pool.begin().await?;
+        if profile.is_default {
+            sqlx::query(
+                "UPDATE transcode_profiles SET is_default = FALSE WHERE is_default AND id != $1",
+            )
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        let updated = sqlx::query_as::<_, Self>(
+            "UPDATE transcode_profiles
+            SET name = $1,
+                description = $2,
+                renditions = $3,
+                codec = $4,
+                preset = $5,
+                segment_duration = $6,
+                manifest_type = $7,
+                encoding_mode = $8,
+                is_default = $9
+            WHERE id = $10
+            RETURNING *",
+        )
+        .bind(profile.name)
+        .bind(profile.description)
+        .bind(Json(&profile.ladder.qualities))
+        .bind(profile.ladder.settings.codec)
+        .bind(&profile.ladder.settings.preset)
+        .bind(profile.ladder.settings.segment_duration as i32)
+        .bind(profile.ladder.settings.manifest)
+        .bind(profile.ladder.settings.mode)
+        .bind(profile.is_default)
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(updated)
+    }
+
+    /// Deletes a profile, videos using it fall back to the default
+    /// Returns false when there was no profile to delete
+    pub async fn delete(pool: &PgPool, id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM transcode_profiles WHERE id = $1")
+            .bind(id)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Finds every profile, sorted by name
+    pub async fn all(pool: &PgPool) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>("SELECT * FROM transcode_profiles ORDER BY name ASC")
+            .fetch_all(pool)
+            .await
+    }
+
+    /// Finds a profile by its name
+    pub async fn by_name(pool: &PgPool, name: &str) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>("SELECT * FROM transcode_profiles WHERE name = $1")
+            .bind(name)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Finds the profile a video should be converted with, the one picked at upload or the default
+    pub async fn for_video(pool: &PgPool, video_id: &str) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>(
+            "SELECT profile.* FROM transcode_profiles profile
+            LEFT JOIN videos video
+                ON video.transcode_profile_id = profile.id AND video.id = $1
+            WHERE video.id IS NOT NULL OR profile.is_default
+            ORDER BY video.id IS NOT NULL DESC
+            LIMIT 1",
+        )
+        .bind(video_id)
+        .fetch_optional(pool)
+        .await
+    }
+}
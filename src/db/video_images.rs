@@ -0,0 +1,97 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone)]
+pub struct VideoImages {
+    pub video_id: String,
+    pub poster_path: String,
+    /// Frames from through the video the owner can pick the thumbnail from
+    pub thumbnail_candidates: Vec<String>,
+    /// The thumbnail shown for the video, a candidate or one the owner uploaded
+    pub thumbnail_path: String,
+    /// WebVTT thumbnails track pointing into the storyboard sprite sheets
+    pub storyboard_path: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The fields saved once a video's images are generated
+pub struct NewVideoImages<'a> {
+    pub poster_path: &'a str,
+    pub thumbnail_candidates: &'a [String],
+    pub storyboard_path: &'a str,
+}
+
+impl VideoImages {
+    /// Saves the images generated for a video, replacing ones from an earlier run
+    /// The thumbnail that was picked is kept as long as it's still around, otherwise the middle
+    /// candidate is picked
+    pub async fn upsert(
+        pool: &PgPool,
+        video_id: &str,
+        images: &NewVideoImages<'_>,
+    ) -> Result<Self, sqlx::Error> {
+        let default_thumbnail = images
+            .thumbnail_candidates
+            .get(images.thumbnail_candidates.len() / 2)
+            .map(String::as_str)
+            .unwrap_or(images.poster_path);
+        sqlx::query_as::<_, Self>(
+            "INSERT INTO video_images
+                (video_id, poster_path, thumbnail_candidates, thumbnail_path, storyboard_path)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (video_id) DO UPDATE
+            SET poster_path = EXCLUDED.poster_path,
+                thumbnail_candidates = EXCLUDED.thumbnail_candidates,
+                thumbnail_path = CASE
+                    WHEN video_images.thumbnail_path = ANY(video_images.thumbnail_candidates)
+                        AND NOT video_images.thumbnail_path = ANY(EXCLUDED.thumbnail_candidates)
+                        THEN EXCLUDED.thumbnail_path
+                    ELSE video_images.thumbnail_path
+                END,
+                storyboard_path = EXCLUDED.storyboard_path
+            RETURNING *",
+        )
+        .bind(video_id)
+        .bind(images.poster_path)
+        .bind(images.thumbnail_candidates)
+        .bind(default_thumbnail)
+        .bind(images.storyboard_path)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Finds the images for a video, if they've been generated
+    pub async fn by_video_id(pool: &PgPool, video_id: &str) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>("SELECT * FROM video_images WHERE video_id = $1")
+            .bind(video_id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Finds the images for every video out of the IDs that has them
+    pub async fn by_video_ids(
+        pool: &PgPool,
+        video_ids: &[String],
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>("SELECT * FROM video_images WHERE video_id = ANY($1)")
+            .bind(video_ids)
+            .fetch_all(pool)
+            .await
+    }
+
+    /// Changes the thumbnail shown for a video
+    pub async fn set_thumbnail(
+        pool: &PgPool,
+        video_id: &str,
+        thumbnail_path: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE video_images SET thumbnail_path = $1 WHERE video_id = $2")
+            .bind(thumbnail_path)
+            .bind(video_id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::vod::probe::MediaInfo;
+
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone)]
+pub struct VideoMetadata {
+    pub video_id: String,
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub info: MediaInfo,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl VideoMetadata {
+    /// Saves the metadata for a video, replacing what was there from an earlier probe
+    pub async fn upsert(
+        pool: &PgPool,
+        video_id: &str,
+        info: &MediaInfo,
+    ) -> Result<Self, sqlx::Error> {
+        sqlx::query_as::<_, Self>(
+            "INSERT INTO video_metadata
+                (video_id, duration, container, video_codec, audio_codec, width, height,
+                 frame_rate, bit_rate, audio_channels, file_size)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            ON CONFLICT (video_id) DO UPDATE
+            SET duration = EXCLUDED.duration,
+                container = EXCLUDED.container,
+                video_codec = EXCLUDED.video_codec,
+                audio_codec = EXCLUDED.audio_codec,
+                width = EXCLUDED.width,
+                height = EXCLUDED.height,
+                frame_rate = EXCLUDED.frame_rate,
+                bit_rate = EXCLUDED.bit_rate,
+                audio_channels = EXCLUDED.audio_channels,
+                file_size = EXCLUDED.file_size
+            RETURNING *",
+        )
+        .bind(video_id)
+        .bind(info.duration)
+        .bind(&info.container)
+        .bind(&info.video_codec)
+        .bind(&info.audio_codec)
+        .bind(info.width)
+        .bind(info.height)
+        .bind(info.frame_rate)
+        .bind(info.bit_rate)
+        .bind(info.audio_channels)
+        .bind(info.file_size)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Finds the metadata for a video, if it's been probed
+    pub async fn by_video_id(pool: &PgPool, video_id: &str) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>("SELECT * FROM video_metadata WHERE video_id = $1")
+            .bind(video_id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Finds the metadata for every video that's been probed out of the IDs
+    pub async fn by_video_ids(
+        pool: &PgPool,
+        video_ids: &[String],
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>("SELECT * FROM video_metadata WHERE video_id = ANY($1)")
+            .bind(video_ids)
+            .fetch_all(pool)
+            .await
+    }
+}
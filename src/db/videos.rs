@@ -11,6 +11,12 @@ pub struct Video {
     pub raw_video_path: String,
     pub processed_video_path: Option<String>,
     pub processing_status: ProcessingStatus,
+    /// Why processing last failed, only for the video's owner and admins
+    pub processing_error: Option<String>,
+    /// A short reason processing last failed, safe to show to anyone
+    pub processing_message: Option<String>,
+    /// The DASH manifest beside the master playlist, for videos converted to CMAF
+    pub dash_manifest_path: Option<String>,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub updated_at: chrono::DateTime<chrono::Utc>,
 }
@@ -19,37 +25,50 @@ pub struct Video {
 #[sqlx(type_name = "processing_status", rename_all = "lowercase")]
 pub enum ProcessingStatus {
     Pending,
+    Queued,
     Processing,
     Completed,
     Failed,
 }
 
+#[derive(Debug, Serialize, Deserialize, sqlx::Type, Clone)]
+#[sqlx(type_name = "compression_status", rename_all = "lowercase")]
+pub enum CompressionStatus {
+    Pending,
+    Compressing,
+    Completed,
+    Failed,
+}
+
 impl Video {
     /// A function for generating a video id
     pub fn gen_id() -> String {
         nanoid!(10)
     }
     /// A function for creating new video data in the db
+    /// The video is converted with the transcode profile when one is given
     pub async fn create(
         pool: &PgPool,
         video_id: Option<String>,
         user_id: Uuid,
         title: String,
         raw_video_path: Option<String>,
+        transcode_profile_id: Option<Uuid>,
     ) -> Result<Self, sqlx::Error> {
         let video_id = video_id.unwrap_or(Self::gen_id());
         sqlx::query_as::<_, Video>(
             r#"
-            INSERT INTO videos (id, user_id, title, raw_video_path, processing_status)
-            VALUES ($1, $2, $3, $4, 'pending')
+            INSERT INTO videos (id, user_id, title, raw_video_path, processing_status, transcode_profile_id)
+            VALUES ($1, $2, $3, $4, 'pending', $5)
             RETURNING id, user_id, title, raw_video_path, processed_video_path,
-                      processing_status, created_at, updated_at
+                      processing_status, processing_error, processing_message, dash_manifest_path, created_at, updated_at
             "#,
         )
         .bind(video_id)
         .bind(user_id)
         .bind(title)
         .bind(raw_video_path)
+        .bind(transcode_profile_id)
         .fetch_one(pool)
         .await
     }
@@ -58,7 +77,7 @@ impl Video {
         sqlx::query_as::<_, Video>(
             r#"
             SELECT id, user_id, title, raw_video_path, processed_video_path,
-                   processing_status, created_at, updated_at
+                   processing_status, processing_error, processing_message, dash_manifest_path, created_at, updated_at
             FROM videos
             WHERE id = ANY($1)
             "#,
@@ -72,7 +91,7 @@ impl Video {
         sqlx::query_as::<_, Video>(
             r#"
             SELECT id, user_id, title, raw_video_path, processed_video_path,
-                   processing_status, created_at, updated_at
+                   processing_status, processing_error, processing_message, dash_manifest_path, created_at, updated_at
             FROM videos
             WHERE id = $1
             "#,
@@ -86,7 +105,7 @@ impl Video {
         sqlx::query_as::<_, Video>(
             r#"
             SELECT id, user_id, title, raw_video_path, processed_video_path,
-                   processing_status, created_at, updated_at
+                   processing_status, processing_error, processing_message, dash_manifest_path, created_at, updated_at
             FROM videos
             WHERE user_id = $1
             ORDER BY created_at DESC
@@ -101,7 +120,7 @@ impl Video {
         sqlx::query_as::<_, Video>(
             r#"
             SELECT v.id, v.user_id, v.title, v.raw_video_path, v.processed_video_path,
-                   v.processing_status, v.created_at, v.updated_at
+                   v.processing_status, v.processing_error, v.processing_message, v.dash_manifest_path, v.created_at, v.updated_at
             FROM videos v
             JOIN users u ON u.id = v.user_id
             WHERE u.name = $1
@@ -117,7 +136,7 @@ impl Video {
         sqlx::query_as::<_, Video>(
             r#"
                 SELECT id, user_id, title, raw_video_path, processed_video_path,
-                       processing_status, created_at, updated_at
+                       processing_status, processing_error, processing_message, dash_manifest_path, created_at, updated_at
                 FROM videos
                 ORDER BY created_at DESC
                 "#,
@@ -125,6 +144,37 @@ impl Video {
         .fetch_all(pool)
         .await
     }
+    /// Gets videos still waiting on their upload that haven't changed since the cutoff
+    pub async fn stale_pending(
+        pool: &PgPool,
+        cutoff: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Video>(
+            r#"
+                SELECT id, user_id, title, raw_video_path, processed_video_path,
+                       processing_status, processing_error, processing_message, dash_manifest_path, created_at, updated_at
+                FROM videos
+                WHERE processing_status = 'pending'
+                AND updated_at < $1
+                ORDER BY created_at
+                "#,
+        )
+        .bind(cutoff)
+        .fetch_all(pool)
+        .await
+    }
+    /// Gets which of the video IDs belong to videos that still exist
+    pub async fn existing_ids(pool: &PgPool, ids: &[String]) -> Result<Vec<String>, sqlx::Error> {
+        sqlx::query_scalar(
+            r#"
+                SELECT id FROM videos
+                WHERE id = ANY($1)
+                "#,
+        )
+        .bind(ids)
+        .fetch_all(pool)
+        .await
+    }
     /// A function for deleting videos by ID
     /// NOTE: Only a video owner can delete their video
     pub async fn delete(
@@ -146,6 +196,7 @@ impl Video {
         Ok(())
     }
     /// A function for updating a videos processing status
+    /// The last processing error is cleared unless the video failed again
     pub async fn update_status(
         pool: &PgPool,
         id: String,
@@ -154,7 +205,10 @@ impl Video {
         sqlx::query(
             r#"
                 UPDATE videos
-                SET processing_status = $1
+                SET processing_status = $1,
+                    processing_error = CASE WHEN $1 = 'failed' THEN processing_error END,
+                    processing_message = CASE WHEN $1 = 'failed' THEN processing_message END,
+                    updated_at = NOW()
                 WHERE id = $2
             "#,
         )
@@ -164,4 +218,116 @@ impl Video {
         .await?;
         Ok(())
     }
+    /// A function for marking a video as failed along with why it failed
+    /// The message is shown to anyone, the error only to the owner as it can hold internal details
+    pub async fn set_failed(
+        pool: &PgPool,
+        id: &str,
+        message: &str,
+        error: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+                UPDATE videos
+                SET processing_status = 'failed',
+                    processing_message = $1,
+                    processing_error = $2,
+                    updated_at = NOW()
+                WHERE id = $3
+            "#,
+        )
+        .bind(message)
+        .bind(error)
+        .bind(id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+    /// A function for marking a video as completed along with its processed video path
+    pub async fn set_processed(
+        pool: &PgPool,
+        id: &str,
+        processed_video_path: &str,
+        dash_manifest_path: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+                UPDATE videos
+                SET processing_status = 'completed',
+                    processed_video_path = $1,
+                    dash_manifest_path = $2,
+                    processing_error = NULL,
+                    processing_message = NULL,
+                    updated_at = NOW()
+                WHERE id = $3
+            "#,
+        )
+        .bind(processed_video_path)
+        .bind(dash_manifest_path)
+        .bind(id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+    /// A function for updating a videos compression status
+    pub async fn update_compression_status(
+        pool: &PgPool,
+        id: &str,
+        status: CompressionStatus,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+                UPDATE videos
+                SET compression_status = $1,
+                    updated_at = NOW()
+                WHERE id = $2
+            "#,
+        )
+        .bind(status)
+        .bind(id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+    /// A function for marking a video as compressed along with its compressed video path
+    pub async fn set_compressed(
+        pool: &PgPool,
+        id: &str,
+        compressed_video_path: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+                UPDATE videos
+                SET compression_status = 'completed',
+                    compressed_video_path = $1,
+                    updated_at = NOW()
+                WHERE id = $2
+            "#,
+        )
+        .bind(compressed_video_path)
+        .bind(id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+    /// A function for pointing a video at a new source, once the raw upload is replaced
+    pub async fn set_raw_video_path(
+        pool: &PgPool,
+        id: &str,
+        raw_video_path: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+                UPDATE videos
+                SET raw_video_path = $1,
+                    updated_at = NOW()
+                WHERE id = $2
+            "#,
+        )
+        .bind(raw_video_path)
+        .bind(id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
 }
@@ -0,0 +1,312 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Uuid, PgPool, Postgres, Transaction};
+
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone)]
+pub struct WorkflowRecord {
+    pub id: Uuid,
+    pub name: String,
+    pub video_id: Option<String>,
+    /// The job that started the workflow
+    pub job_id: Option<Uuid>,
+    pub status: WorkflowStatus,
+    pub error: Option<String>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::Type, Clone, PartialEq)]
+#[sqlx(type_name = "workflow_status", rename_all = "lowercase")]
+pub enum WorkflowStatus {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone)]
+pub struct WorkflowStepRecord {
+    pub id: Uuid,
+    pub workflow_id: Uuid,
+    pub name: String,
+    pub subject: String,
+    pub payload: serde_json::Value,
+    pub version: Option<i32>,
+    /// Names of the steps in the same workflow that have to complete first
+    pub depends_on: Vec<String>,
+    pub status: WorkflowStepStatus,
+    /// The job running the step, set once the step is published
+    pub job_id: Option<Uuid>,
+    /// What the step produced, available to the steps that depend on it
+    pub output: Option<serde_json::Value>,
+    pub error: Option<String>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::Type, Clone, PartialEq)]
+#[sqlx(type_name = "workflow_step_status", rename_all = "lowercase")]
+pub enum WorkflowStepStatus {
+    Pending,
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// The fields needed to insert a new workflow step
+pub struct NewWorkflowStep<'a> {
+    pub id: Uuid,
+    pub workflow_id: Uuid,
+    pub name: &'a str,
+    pub subject: &'a str,
+    pub payload: &'a serde_json::Value,
+    pub version: Option<i32>,
+    pub depends_on: &'a [String],
+}
+
+impl WorkflowRecord {
+    /// Creates a new running workflow in the database
+    pub async fn create(
+        tx: &mut Transaction<'_, Postgres>,
+        id: Uuid,
+        name: &str,
+        video_id: Option<&str>,
+        job_id: Option<Uuid>,
+    ) -> Result<Self, sqlx::Error> {
+        sqlx::query_as::<_, WorkflowRecord>(
+            "INSERT INTO workflows (id, name, video_id, job_id)
+            VALUES ($1, $2, $3, $4)
+            RETURNING *",
+        )
+        .bind(id)
+        .bind(name)
+        .bind(video_id)
+        .bind(job_id)
+        .fetch_one(&mut **tx)
+        .await
+    }
+
+    /// Finds a workflow by ID
+    pub async fn by_id(pool: &PgPool, id: Uuid) -> Result<Self, sqlx::Error> {
+        sqlx::query_as::<_, Self>("SELECT * FROM workflows WHERE id = $1")
+            .bind(id)
+            .fetch_one(pool)
+            .await
+    }
+
+    /// Finds the workflow started by a job, if there is one
+    pub async fn by_job_id(pool: &PgPool, job_id: Uuid) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>("SELECT * FROM workflows WHERE job_id = $1")
+            .bind(job_id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Finds all workflows for a video, newest first
+    pub async fn by_video_id(pool: &PgPool, video_id: &str) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>(
+            "SELECT * FROM workflows WHERE video_id = $1 ORDER BY created_at DESC",
+        )
+        .bind(video_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Marks a running workflow as finished with the given status
+    pub async fn finish(
+        pool: &PgPool,
+        id: Uuid,
+        status: WorkflowStatus,
+        error: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE workflows
+            SET status = $1,
+                error = $2,
+                finished_at = NOW()
+            WHERE id = $3 AND status = 'running'",
+        )
+        .bind(status)
+        .bind(error)
+        .bind(id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
+
+impl WorkflowStepRecord {
+    /// Creates a new pending step in the database
+    pub async fn create(
+        tx: &mut Transaction<'_, Postgres>,
+        step: &NewWorkflowStep<'_>,
+    ) -> Result<Self, sqlx::Error> {
+        sqlx::query_as::<_, WorkflowStepRecord>(
+            "INSERT INTO workflow_steps (id, workflow_id, name, subject, payload, version, depends_on)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING *",
+        )
+        .bind(step.id)
+        .bind(step.workflow_id)
+        .bind(step.name)
+        .bind(step.subject)
+        .bind(step.payload)
+        .bind(step.version)
+        .bind(step.depends_on)
+        .fetch_one(&mut **tx)
+        .await
+    }
+
+    /// Finds every step in a workflow, in the order they were defined
+    pub async fn by_workflow_id(
+        pool: &PgPool,
+        workflow_id: Uuid,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>(
+            "SELECT * FROM workflow_steps WHERE workflow_id = $1 ORDER BY created_at ASC, name ASC",
+        )
+        .bind(workflow_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Finds the step a job is running, if it's part of a workflow
+    pub async fn by_job_id(pool: &PgPool, job_id: Uuid) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>("SELECT * FROM workflow_steps WHERE job_id = $1")
+            .bind(job_id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Claims pending steps whose dependencies have all completed, marking them as queued
+    /// Steps are only ever claimed once, even when their dependencies finish at the same time
+    pub async fn claim_ready(pool: &PgPool, workflow_id: Uuid) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>(
+            "UPDATE workflow_steps step
+            SET status = 'queued'
+            WHERE step.workflow_id = $1
+                AND step.status = 'pending'
+                AND NOT EXISTS (
+                    SELECT 1 FROM workflow_steps dependency
+                    WHERE dependency.workflow_id = step.workflow_id
+                        AND dependency.name = ANY(step.depends_on)
+                        AND dependency.status != 'completed'
+                )
+            RETURNING *",
+        )
+        .bind(workflow_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Puts claimed steps that couldn't be published back to pending, so they're claimed again later
+    pub async fn release(pool: &PgPool, ids: &[Uuid]) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE workflow_steps
+            SET status = 'pending',
+                job_id = NULL
+            WHERE id = ANY($1) AND status = 'queued'",
+        )
+        .bind(ids)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Links a step to the job that runs it
+    pub async fn set_job(pool: &PgPool, id: Uuid, job_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE workflow_steps SET job_id = $1 WHERE id = $2")
+            .bind(job_id)
+            .bind(id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Marks the step run by a job as running
+    pub async fn mark_running(pool: &PgPool, job_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE workflow_steps SET status = 'running' WHERE job_id = $1 AND status = 'queued'",
+        )
+        .bind(job_id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Marks a step as completed, storing what it produced
+    pub async fn mark_completed(
+        pool: &PgPool,
+        id: Uuid,
+        output: Option<&serde_json::Value>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE workflow_steps
+            SET status = 'completed',
+                output = $1,
+                error = NULL,
+                finished_at = NOW()
+            WHERE id = $2 AND status IN ('queued', 'running')",
+        )
+        .bind(output)
+        .bind(id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Marks a step as finished without completing
+    pub async fn mark_unsuccessful(
+        pool: &PgPool,
+        id: Uuid,
+        status: WorkflowStepStatus,
+        error: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE workflow_steps
+            SET status = $1,
+                error = $2,
+                finished_at = NOW()
+            WHERE id = $3 AND status IN ('pending', 'queued', 'running')",
+        )
+        .bind(status)
+        .bind(error)
+        .bind(id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Cancels every unfinished step in a workflow
+    /// Returns the jobs of steps that were already published, so they can be cancelled as well
+    pub async fn cancel_unfinished(
+        pool: &PgPool,
+        workflow_id: Uuid,
+    ) -> Result<Vec<Uuid>, sqlx::Error> {
+        let job_ids: Vec<Option<Uuid>> = sqlx::query_scalar(
+            "UPDATE workflow_steps
+            SET status = 'cancelled',
+                finished_at = NOW()
+            WHERE workflow_id = $1 AND status IN ('pending', 'queued', 'running')
+            RETURNING job_id",
+        )
+        .bind(workflow_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(job_ids.into_iter().flatten().collect())
+    }
+
+    /// Counts the steps in a workflow that haven't completed
+    pub async fn incomplete_count(pool: &PgPool, workflow_id: Uuid) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT COUNT(*) FROM workflow_steps WHERE workflow_id = $1 AND status != 'completed'",
+        )
+        .bind(workflow_id)
+        .fetch_one(pool)
+        .await
+    }
+}
@@ -0,0 +1,41 @@
+use std::{process::ExitStatus, time::Duration};
+use thiserror::Error;
+
+/// Errors from running ffmpeg or ffprobe, carrying the last lines the process wrote to stderr
+#[derive(Error, Debug)]
+pub enum FfmpegError {
+    #[error("Failed to start {program}: {source}")]
+    Spawn {
+        program: String,
+        source: std::io::Error,
+    },
+    #[error("Failed to read output of {program}: {source}")]
+    Io {
+        program: String,
+        source: std::io::Error,
+    },
+    #[error("{program} exited with {status}: {stderr}")]
+    Failed {
+        program: String,
+        status: ExitStatus,
+        stderr: String,
+    },
+    #[error("{program} timed out after {timeout:?}: {stderr}")]
+    TimedOut {
+        program: String,
+        timeout: Duration,
+        stderr: String,
+    },
+    #[error("{program} was cancelled")]
+    Cancelled { program: String },
+}
+
+impl FfmpegError {
+    /// The tail of stderr from the process, empty when it never ran
+    pub fn stderr(&self) -> &str {
+        match self {
+            Self::Failed { stderr, .. } | Self::TimedOut { stderr, .. } => stderr,
+            _ => "",
+        }
+    }
+}
@@ -1,3 +1,9 @@
+pub mod ffmpeg;
 pub mod queue;
+pub mod storage;
+pub mod video;
 
-pub use queue::{QueueError, StreamError};
+pub use ffmpeg::FfmpegError;
+pub use queue::{JobError, QueueError, StreamError};
+pub use storage::StorageError;
+pub use video::VideoFormatError;
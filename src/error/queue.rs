@@ -7,6 +7,51 @@ use thiserror::Error;
 pub enum QueueError {
     #[error("Invalid Connection: {0}")]
     InvalidConnection(String),
+    #[error("Job not found: {0}")]
+    NotFound(String),
+    #[error("Invalid workflow: {0}")]
+    InvalidWorkflow(String),
+    #[error("Invalid job payload: {0}")]
+    InvalidPayload(String),
+    #[error("Job record error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+#[derive(Error, Debug)]
+pub enum JobError {
+    /// A failure that may succeed on another attempt, like a timeout
+    #[error("{0}")]
+    Retryable(anyhow::Error),
+    /// A failure that will never succeed, the job isn't attempted again
+    #[error("{0}")]
+    Fatal(anyhow::Error),
+    /// The job was cancelled while it was running
+    #[error("Job cancelled")]
+    Cancelled,
+}
+
+impl JobError {
+    pub fn retryable(err: impl Into<anyhow::Error>) -> Self {
+        Self::Retryable(err.into())
+    }
+    pub fn fatal(err: impl Into<anyhow::Error>) -> Self {
+        Self::Fatal(err.into())
+    }
+    /// Checks whether a job that failed with this error can be attempted again
+    /// Errors that aren't a `JobError` are assumed to be retryable
+    pub fn is_retryable(err: &anyhow::Error) -> bool {
+        !matches!(
+            err.downcast_ref::<JobError>(),
+            Some(JobError::Fatal(_) | JobError::Cancelled)
+        )
+    }
+    /// Marks errors from a database row that doesn't exist as fatal, it won't show up on a retry
+    pub fn fatal_if_not_found(err: anyhow::Error) -> anyhow::Error {
+        match err.downcast_ref::<sqlx::Error>() {
+            Some(sqlx::Error::RowNotFound) => Self::Fatal(err).into(),
+            _ => err,
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -0,0 +1,19 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum StorageError {
+    #[error("Invalid storage configuration: {0}")]
+    InvalidConfig(String),
+    #[error("Object not found: {0}")]
+    NotFound(String),
+    /// Keys that would reach outside of the storage root, like ones with `..` in them
+    #[error("Invalid object key: {0}")]
+    InvalidKey(String),
+    /// A presigned URL that was tampered with or has expired
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(String),
+    #[error("Storage request failed: {0}")]
+    Request(String),
+    #[error("Storage IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
@@ -0,0 +1,12 @@
+use thiserror::Error;
+
+/// Reasons an uploaded video can't be converted, none of them go away on a retry
+#[derive(Error, Debug)]
+pub enum VideoFormatError {
+    #[error("Unsupported container {0}, upload an MP4, MOV, MKV, WebM, FLV, AVI or TS file")]
+    UnsupportedContainer(String),
+    #[error("Unsupported {kind} codec {codec}")]
+    UnsupportedCodec { kind: String, codec: String },
+    #[error("The file has no video stream")]
+    NoVideoStream,
+}
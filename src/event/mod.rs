@@ -10,6 +10,8 @@ pub const MESSAGE_PREFIX: &str = "farmhand";
 pub const EVENT_PREFIX: &str = "events";
 pub const JOB_PREFIX: &str = "jobs";
 pub const JOB_STREAM: &str = "FARMHAND_JOBS";
+pub const DLQ_PREFIX: &str = "dlq";
+pub const DLQ_STREAM: &str = "FARMHAND_JOBS_DLQ";
 
 #[derive(Serialize, Deserialize)]
 pub struct Event {
@@ -1,10 +1,18 @@
 use anyhow::Result;
 
+use crate::queue::QueueBackend;
+
 /// Get the NATS URL from the environment variable or default to "nats://localhost:4222"
 pub fn get_nats_url() -> String {
     std::env::var("NATS_URL").unwrap_or_else(|_| "nats://localhost:4222".to_string())
 }
 
+/// Whether NATS is used at all, either as the queue backend or because NATS_URL is set
+/// Deployments on the Postgres queue backend can leave NATS out entirely
+pub fn nats_enabled() -> Result<bool> {
+    Ok(QueueBackend::from_env()? == QueueBackend::Nats || std::env::var("NATS_URL").is_ok())
+}
+
 /// Create a NATS client using the provided URL
 pub async fn create_nats_client() -> Result<async_nats::Client> {
     let url = get_nats_url();
@@ -12,3 +20,11 @@ pub async fn create_nats_client() -> Result<async_nats::Client> {
         .await
         .map_err(|e| anyhow::Error::new(e))
 }
+
+/// Creates a NATS client if NATS is enabled
+pub async fn create_nats_client_if_enabled() -> Result<Option<async_nats::Client>> {
+    match nats_enabled()? {
+        true => Ok(Some(create_nats_client().await?)),
+        false => Ok(None),
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{Job, JobContext, RetryPolicy, Runner, RunnerState};
+use crate::{
+    db::{CompressionStatus, Video},
+    error::JobError,
+    prelude::get_storage_dir,
+    storage::ObjectHeaders,
+    vod::{archive::ArchiveConverter, stream::get_ffmpeg_location, DownloadSettings, Vod},
+};
+
+/// How long after processing succeeds before the raw video gets archived
+pub const ARCHIVE_DELAY: chrono::Duration = chrono::Duration::hours(24);
+
+#[derive(Serialize, Deserialize)]
+pub struct ArchiveRawPayload {
+    pub video_id: String,
+}
+
+impl Job for ArchiveRawPayload {
+    const NAME: &'static str = "archive_raw";
+    // Nobody is waiting on archives, so they can take their time
+    const RETRY_POLICY: RetryPolicy = RetryPolicy::new(
+        5,
+        Duration::from_secs(5 * 60),
+        Duration::from_secs(2 * 60 * 60),
+    );
+
+    fn video_id(&self) -> Option<&str> {
+        Some(&self.video_id)
+    }
+}
+
+pub struct ArchiveRawRunner {
+    state: Arc<RunnerState>,
+}
+
+impl ArchiveRawRunner {
+    pub fn new(state: Arc<RunnerState>) -> Self {
+        Self { state }
+    }
+    /// Archives the raw video and replaces the raw upload in storage with it
+    /// Every step can be run again, so retries pick up wherever a failed attempt stopped
+    async fn archive(&self, context: &JobContext, video_id: &str) -> Result<()> {
+        let working_dir = PathBuf::from(get_storage_dir())
+            .join(video_id)
+            .join("archive");
+        tokio::fs::create_dir_all(&working_dir).await?;
+        let vod = Vod::by_id(&self.state.db, video_id.to_string(), working_dir.clone())
+            .await
+            .map_err(JobError::fatal_if_not_found)?;
+        let archive_key = format!("{}/archive.mkv", vod.get_remote_storage_prefix());
+        // Videos archived before, and processed again since, already have the archive as their source
+        if vod.video.raw_video_path == archive_key {
+            tracing::info!("Video {} is already archived", video_id);
+            Video::set_compressed(&self.state.db, video_id, &archive_key).await?;
+            return Ok(());
+        }
+
+        // An earlier attempt may have uploaded the archive and even deleted the raw video already
+        let archived = self
+            .state
+            .storage
+            .exists(&archive_key)
+            .await
+            .map_err(|e| anyhow!("Could not check for archive {}: {}", archive_key, e))?;
+        if archived {
+            tracing::info!("Video {} was archived by an earlier attempt", video_id);
+        } else {
+            self.convert(context, &vod, &working_dir, &archive_key)
+                .await?;
+        }
+        // Keep track of the archive before the raw video goes, so it's never lost
+        Video::set_compressed(&self.state.db, video_id, &archive_key).await?;
+
+        // The archive replaces the raw upload, so remove it from storage
+        self.state
+            .storage
+            .delete(&vod.video.raw_video_path)
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "Could not delete raw video {}: {}",
+                    vod.video.raw_video_path,
+                    e
+                )
+            })?;
+        Video::set_raw_video_path(&self.state.db, video_id, &archive_key).await?;
+
+        if let Err(e) = tokio::fs::remove_dir_all(&working_dir).await {
+            tracing::warn!("Could not clean up {:?}: {}", working_dir, e);
+        }
+
+        Ok(())
+    }
+    /// Downloads the raw video, archives it and uploads the archive to archive_key
+    async fn convert(
+        &self,
+        context: &JobContext,
+        vod: &Vod,
+        working_dir: &Path,
+        archive_key: &str,
+    ) -> Result<()> {
+        // Download the raw video if it isn't available locally
+        let download_settings = DownloadSettings {
+            storage: &*self.state.storage,
+        };
+        let raw_video_path = vod
+            .get_raw_video(working_dir.to_path_buf(), Some(download_settings))
+            .await?
+            .ok_or_else(|| {
+                JobError::fatal(anyhow!("Raw video for {} could not be found", vod.video.id))
+            })?;
+
+        let archive_path = working_dir.join("archive.mkv");
+        let converter = ArchiveConverter::new(get_ffmpeg_location())?;
+        converter
+            .archive(&raw_video_path, &archive_path, &context.cancellation())
+            .await?;
+
+        // Upload the archive next to the rest of the videos files
+        self.state
+            .storage
+            .put_file(
+                archive_key,
+                &archive_path,
+                ObjectHeaders::for_key(archive_key),
+            )
+            .await
+            .map_err(|e| anyhow!("Could not upload archive {}: {}", archive_key, e))?;
+        Ok(())
+    }
+}
+
+impl Runner for ArchiveRawRunner {
+    type Job = ArchiveRawPayload;
+
+    /// Archives a raw video once it has been processed
+    async fn process_job(&self, context: &JobContext, payload: Self::Job) -> Result<()> {
+        tracing::debug!(
+            "Processing job with runner ArchiveRawRunner for video ID {video_id}",
+            video_id = payload.video_id,
+        );
+        let video_id = payload.video_id;
+
+        Video::update_compression_status(&self.state.db, &video_id, CompressionStatus::Compressing)
+            .await?;
+
+        match self.archive(context, &video_id).await {
+            Ok(_) => {
+                tracing::info!("Successfully archived video {}", video_id);
+                Ok(())
+            }
+            Err(_) if context.is_cancelled() => {
+                tracing::info!("Cancelled archiving video {}", video_id);
+                Video::update_compression_status(
+                    &self.state.db,
+                    &video_id,
+                    CompressionStatus::Pending,
+                )
+                .await?;
+                Err(JobError::Cancelled.into())
+            }
+            Err(err) => {
+                tracing::error!("Failed to archive video {}: {}", video_id, err);
+                Video::update_compression_status(
+                    &self.state.db,
+                    &video_id,
+                    CompressionStatus::Failed,
+                )
+                .await?;
+                Err(err)
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+pub mod nats;
+pub mod postgres;
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use uuid::Uuid;
+
+pub use self::nats::NatsQueue;
+pub use self::postgres::PostgresQueue;
+use crate::error::QueueError;
+
+/// The most times any job is attempted, job types set their own limit with a `RetryPolicy`
+pub const MAX_DELIVER: i64 = 10;
+
+/// How many times backends deliver a job, one more than it's attempted
+/// The extra delivery is never run, it fails jobs whose runners kept stopping before they finished
+const DELIVERY_LIMIT: i64 = MAX_DELIVER + 1;
+
+/// How long a job can go without an ack or heartbeat before it's redelivered
+pub const ACK_WAIT: Duration = Duration::from_secs(60);
+
+/// Which service jobs are delivered through
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QueueBackend {
+    /// A NATS JetStream work queue
+    Nats,
+    /// The jobs table, for deployments that only run Postgres
+    Postgres,
+}
+
+impl QueueBackend {
+    /// Reads the backend from QUEUE_BACKEND, defaulting to NATS
+    pub fn from_env() -> Result<Self, QueueError> {
+        match std::env::var("QUEUE_BACKEND") {
+            Err(_) => Ok(Self::Nats),
+            Ok(backend) => match backend.trim().to_lowercase().as_str() {
+                "" | "nats" => Ok(Self::Nats),
+                "postgres" | "postgresql" => Ok(Self::Postgres),
+                other => Err(QueueError::InvalidConnection(format!(
+                    "Unknown QUEUE_BACKEND {}, expected nats or postgres",
+                    other
+                ))),
+            },
+        }
+    }
+}
+
+/// A job message, sent once its record is in the database
+pub struct OutgoingJob<'a> {
+    pub id: Uuid,
+    pub subject: &'a str,
+    pub message: &'a str,
+    pub version: Option<u32>,
+    /// Backends drop messages with an ID they've already seen recently
+    pub message_id: Option<&'a str>,
+}
+
+/// Delivers job messages to runners, jobs are recorded in the database either way
+#[async_trait]
+pub trait JobQueue: Send + Sync {
+    /// Publishes a job, returning false when it was dropped as a duplicate
+    async fn publish(&self, job: OutgoingJob<'_>) -> Result<bool, QueueError>;
+    /// Gets every subject that currently has jobs waiting
+    async fn pending_subjects(&self) -> Result<Vec<String>, QueueError>;
+    /// Creates a consumer for jobs on the subjects, runners sharing a name split its jobs
+    async fn consumer(
+        &self,
+        name: &str,
+        description: &str,
+        subjects: Vec<String>,
+    ) -> Result<Box<dyn JobConsumer>, QueueError>;
+}
+
+/// Pulls jobs off of a queue for a runner
+#[async_trait]
+pub trait JobConsumer: Send + Sync {
+    /// Fetches up to max jobs, returning none when nothing is waiting
+    async fn fetch(&self, max: usize) -> Result<Vec<Delivery>, QueueError>;
+}
+
+/// How a runner settles a delivered job
+#[derive(Debug, Clone, Copy)]
+pub enum Ack {
+    /// The job is done with, successfully or not
+    Done,
+    /// The job is still being worked on, don't redeliver it yet
+    Progress,
+    /// Deliver the job again, after the delay if there is one
+    Retry(Option<Duration>),
+    /// Never deliver the job again
+    Term,
+}
+
+/// Settles a delivery with the backend it came from
+#[async_trait]
+trait Acker: Send + Sync {
+    async fn ack(&self, ack: Ack) -> Result<(), QueueError>;
+}
+
+/// A job handed to a runner by a consumer
+pub struct Delivery {
+    pub subject: String,
+    pub payload: Bytes,
+    /// The ID of the job record, missing for messages published without one
+    pub job_id: Option<Uuid>,
+    /// The payload version of a typed job
+    pub version: Option<u32>,
+    /// Which delivery of the job this is, starting at 1
+    pub attempt: i64,
+    acker: Box<dyn Acker>,
+}
+
+impl Delivery {
+    /// Checks whether the job was already attempted as many times as any job can be
+    pub fn is_exhausted(&self) -> bool {
+        self.attempt > MAX_DELIVER
+    }
+    /// Settles the delivery, the backend decides what happens to the job from there
+    pub async fn ack(&self, ack: Ack) -> Result<(), QueueError> {
+        self.acker.ack(ack).await
+    }
+}
@@ -0,0 +1,233 @@
+use async_nats::{
+    header::NATS_MESSAGE_ID,
+    jetstream::{
+        self,
+        consumer::{pull::Config, Consumer},
+        stream::RetentionPolicy,
+        AckKind, Context, Message,
+    },
+    Client, HeaderMap,
+};
+use async_trait::async_trait;
+use futures::{StreamExt, TryStreamExt};
+use std::time::Duration;
+
+use super::{Ack, Acker, Delivery, JobConsumer, JobQueue, OutgoingJob, ACK_WAIT, DELIVERY_LIMIT};
+use crate::{error::QueueError, event::JOB_STREAM};
+
+/// Header carrying the ID of the job record for a message
+pub const JOB_ID_HEADER: &str = "Farmhand-Job-Id";
+
+/// Header carrying the payload version of a typed job
+pub const JOB_VERSION_HEADER: &str = "Farmhand-Job-Version";
+
+/// How long the stream remembers message IDs to drop duplicate jobs
+pub const DUPLICATE_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// Delivers jobs through a NATS JetStream work queue
+pub struct NatsQueue {
+    name: String,
+    jetstream: Context,
+}
+
+impl NatsQueue {
+    /// Connects to an existing queue
+    pub async fn connect(nats_client: Client) -> Result<Self, QueueError> {
+        let jetstream = Self::create_jetstream(nats_client);
+        jetstream
+            .get_stream(JOB_STREAM)
+            .await
+            .map_err(|e| QueueError::InvalidConnection(e.to_string()))?;
+        Ok(NatsQueue {
+            name: JOB_STREAM.to_string(),
+            jetstream,
+        })
+    }
+    /// Creates the stream backing the queue
+    pub async fn create(
+        name: String,
+        description: Option<String>,
+        subjects: Vec<String>,
+        nats_client: Client,
+    ) -> Result<(), QueueError> {
+        let jetstream = Self::create_jetstream(nats_client);
+        jetstream
+            .create_stream(jetstream::stream::Config {
+                name: name.clone(),
+                subjects,
+                description,
+                retention: RetentionPolicy::WorkQueue,
+                duplicate_window: DUPLICATE_WINDOW,
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| QueueError::InvalidConnection(e.to_string()))?;
+        Ok(())
+    }
+    /// Deletes the queue
+    pub async fn delete(nats_client: Client) -> Result<(), QueueError> {
+        let jetstream = Self::create_jetstream(nats_client);
+
+        // Check if stream exists first
+        if jetstream.get_stream(JOB_STREAM).await.is_ok() {
+            jetstream
+                .delete_stream(JOB_STREAM)
+                .await
+                .map_err(|e| QueueError::InvalidConnection(e.to_string()))?;
+        } else {
+            tracing::warn!("Stream {} does not exist", JOB_STREAM);
+        }
+        Ok(())
+    }
+    /// Creates a new jetstream context
+    fn create_jetstream(nats_client: Client) -> Context {
+        jetstream::new(nats_client)
+    }
+}
+
+#[async_trait]
+impl JobQueue for NatsQueue {
+    /// Sends a job message to the stream, tagged with its job ID and payload version
+    /// Messages with the same message ID are only stored once within the duplicate window
+    async fn publish(&self, job: OutgoingJob<'_>) -> Result<bool, QueueError> {
+        tracing::debug!("Publishing job {} to subject {}", job.id, job.subject);
+        let mut headers = HeaderMap::new();
+        headers.insert(JOB_ID_HEADER, job.id.to_string().as_str());
+        if let Some(version) = job.version {
+            headers.insert(JOB_VERSION_HEADER, version.to_string().as_str());
+        }
+        if let Some(message_id) = job.message_id {
+            headers.insert(NATS_MESSAGE_ID, message_id);
+        }
+        let ack = self
+            .jetstream
+            .publish_with_headers(
+                job.subject.to_string(),
+                headers,
+                job.message.to_string().into(),
+            )
+            .await
+            .map_err(|e| QueueError::InvalidConnection(e.to_string()))?
+            .await
+            .map_err(|e| QueueError::InvalidConnection(e.to_string()))?;
+        Ok(!ack.duplicate)
+    }
+    async fn pending_subjects(&self) -> Result<Vec<String>, QueueError> {
+        let stream = self
+            .jetstream
+            .get_stream(&self.name)
+            .await
+            .map_err(|e| QueueError::InvalidConnection(e.to_string()))?;
+        let subjects = stream
+            .info_with_subjects(">")
+            .await
+            .map_err(|e| QueueError::InvalidConnection(e.to_string()))?
+            .map_ok(|(subject, _)| subject)
+            .try_collect()
+            .await
+            .map_err(|e| QueueError::InvalidConnection(e.to_string()))?;
+
+        Ok(subjects)
+    }
+    async fn consumer(
+        &self,
+        name: &str,
+        description: &str,
+        mut subjects: Vec<String>,
+    ) -> Result<Box<dyn JobConsumer>, QueueError> {
+        // Multiple filters need a newer server, so only use them when needed
+        let (filter_subject, filter_subjects) = match subjects.len() {
+            1 => (subjects.remove(0), Vec::new()),
+            _ => (String::new(), subjects),
+        };
+        let config = jetstream::consumer::pull::Config {
+            durable_name: Some(name.to_string()),
+            description: Some(description.to_string()),
+            filter_subject,
+            filter_subjects,
+            max_deliver: DELIVERY_LIMIT,
+            ack_wait: ACK_WAIT,
+            ..Default::default()
+        };
+        let consumer = self
+            .jetstream
+            .create_consumer_on_stream(config, self.name.to_string())
+            .await
+            .map_err(|e| QueueError::InvalidConnection(e.to_string()))?;
+        Ok(Box::new(NatsConsumer { consumer }))
+    }
+}
+
+/// Pulls jobs from a durable consumer on the stream
+struct NatsConsumer {
+    consumer: Consumer<Config>,
+}
+
+#[async_trait]
+impl JobConsumer for NatsConsumer {
+    async fn fetch(&self, max: usize) -> Result<Vec<Delivery>, QueueError> {
+        let mut messages = self
+            .consumer
+            .fetch()
+            .max_messages(max)
+            .messages()
+            .await
+            .map_err(|e| QueueError::InvalidConnection(e.to_string()))?;
+
+        let mut deliveries = Vec::new();
+        while let Some(message) = messages.next().await {
+            // Make sure the job is good to go
+            let Ok(message) = message else {
+                tracing::error!("Failed to receive job");
+                continue;
+            };
+            deliveries.push(Self::delivery(message));
+        }
+        Ok(deliveries)
+    }
+}
+
+impl NatsConsumer {
+    /// Reads the job details off of a message's headers
+    fn delivery(message: Message) -> Delivery {
+        let header = |name: &str| {
+            message
+                .headers
+                .as_ref()
+                .and_then(|headers| headers.get(name))
+                .map(|value| value.as_str().to_string())
+        };
+        let job_id = header(JOB_ID_HEADER).and_then(|id| id.parse().ok());
+        let version = header(JOB_VERSION_HEADER).and_then(|version| version.parse().ok());
+        let attempt = message.info().map(|info| info.delivered).unwrap_or(1);
+
+        Delivery {
+            subject: message.subject.to_string(),
+            payload: message.payload.clone(),
+            job_id,
+            version,
+            attempt,
+            acker: Box::new(NatsAcker { message }),
+        }
+    }
+}
+
+struct NatsAcker {
+    message: Message,
+}
+
+#[async_trait]
+impl Acker for NatsAcker {
+    async fn ack(&self, ack: Ack) -> Result<(), QueueError> {
+        let kind = match ack {
+            Ack::Done => AckKind::Ack,
+            Ack::Progress => AckKind::Progress,
+            Ack::Retry(delay) => AckKind::Nak(delay),
+            Ack::Term => AckKind::Term,
+        };
+        self.message
+            .ack_with(kind)
+            .await
+            .map_err(|e| QueueError::InvalidConnection(e.to_string()))
+    }
+}
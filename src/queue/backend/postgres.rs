@@ -0,0 +1,142 @@
+use async_trait::async_trait;
+use std::time::Duration;
+use uuid::Uuid;
+
+use super::{Ack, Acker, Delivery, JobConsumer, JobQueue, OutgoingJob, ACK_WAIT, DELIVERY_LIMIT};
+use crate::{
+    db::{ClaimedJob, DBPool, JobRecord},
+    error::QueueError,
+};
+
+/// How long a consumer waits before returning when no jobs are available
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Delivers jobs straight out of the jobs table, so only Postgres is needed
+/// Runners claim jobs with `FOR UPDATE SKIP LOCKED`, so any number can share the table
+pub struct PostgresQueue {
+    db: DBPool,
+}
+
+impl PostgresQueue {
+    pub fn new(db: DBPool) -> Self {
+        Self { db }
+    }
+    /// Turns a NATS style subject filter into a regex matching the same subjects
+    /// e.g. farmhand.jobs.> -> ^farmhand\.jobs\..+$
+    fn subject_pattern(filter: &str) -> String {
+        let tokens: Vec<String> = filter
+            .split('.')
+            .map(|token| match token {
+                "*" => "[^.]+".to_string(),
+                ">" => ".+".to_string(),
+                token => regex_escape(token),
+            })
+            .collect();
+        format!("^{}$", tokens.join("\\."))
+    }
+}
+
+/// Escapes the characters that mean something in a Postgres regex
+fn regex_escape(token: &str) -> String {
+    token
+        .chars()
+        .flat_map(|c| match c {
+            '\\' | '.' | '+' | '*' | '?' | '(' | ')' | '[' | ']' | '{' | '}' | '|' | '^' | '$' => {
+                vec!['\\', c]
+            }
+            c => vec![c],
+        })
+        .collect()
+}
+
+#[async_trait]
+impl JobQueue for PostgresQueue {
+    /// The job record is the message, so it's available to runners as soon as it's queued
+    /// Duplicates are already caught by the dedup key before the record is created
+    async fn publish(&self, job: OutgoingJob<'_>) -> Result<bool, QueueError> {
+        tracing::debug!("Publishing job {} to subject {}", job.id, job.subject);
+        Ok(true)
+    }
+    async fn pending_subjects(&self) -> Result<Vec<String>, QueueError> {
+        Ok(JobRecord::pending_subjects(&self.db).await?)
+    }
+    async fn consumer(
+        &self,
+        name: &str,
+        _description: &str,
+        subjects: Vec<String>,
+    ) -> Result<Box<dyn JobConsumer>, QueueError> {
+        tracing::debug!("Consuming jobs from the jobs table as {}", name);
+        let subject_patterns = subjects
+            .iter()
+            .map(|subject| Self::subject_pattern(subject))
+            .collect();
+        Ok(Box::new(PostgresConsumer {
+            db: self.db.clone(),
+            subject_patterns,
+        }))
+    }
+}
+
+/// Claims jobs matching the consumer's subjects from the jobs table
+struct PostgresConsumer {
+    db: DBPool,
+    subject_patterns: Vec<String>,
+}
+
+#[async_trait]
+impl JobConsumer for PostgresConsumer {
+    async fn fetch(&self, max: usize) -> Result<Vec<Delivery>, QueueError> {
+        let jobs = JobRecord::claim_available(
+            &self.db,
+            &self.subject_patterns,
+            max as i64,
+            ACK_WAIT,
+            DELIVERY_LIMIT,
+        )
+        .await?;
+
+        // Don't hammer the database while the queue is empty
+        if jobs.is_empty() {
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+
+        Ok(jobs.into_iter().map(|job| self.delivery(job)).collect())
+    }
+}
+
+impl PostgresConsumer {
+    fn delivery(&self, job: ClaimedJob) -> Delivery {
+        Delivery {
+            subject: job.subject,
+            payload: job.payload.to_string().into(),
+            job_id: Some(job.id),
+            version: job.version.map(|version| version as u32),
+            attempt: job.deliveries as i64,
+            acker: Box::new(PostgresAcker {
+                db: self.db.clone(),
+                id: job.id,
+            }),
+        }
+    }
+}
+
+struct PostgresAcker {
+    db: DBPool,
+    id: Uuid,
+}
+
+#[async_trait]
+impl Acker for PostgresAcker {
+    async fn ack(&self, ack: Ack) -> Result<(), QueueError> {
+        match ack {
+            Ack::Done | Ack::Term => JobRecord::settle(&self.db, self.id).await?,
+            // Push the claim back out, same as a heartbeat resetting the ack wait
+            Ack::Progress => JobRecord::release(&self.db, self.id, ACK_WAIT).await?,
+            Ack::Retry(delay) => {
+                JobRecord::release(&self.db, self.id, delay.unwrap_or_default()).await?
+            }
+        }
+        Ok(())
+    }
+}
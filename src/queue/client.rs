@@ -0,0 +1,295 @@
+use async_nats::Client;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use super::{
+    backend::{JobConsumer, JobQueue, NatsQueue, OutgoingJob, PostgresQueue, QueueBackend},
+    Job,
+};
+use crate::{
+    db::{DBPool, JobRecord, JobStatus, NewJob},
+    error::QueueError,
+};
+
+/// Publishes jobs to a backend, recording every job in the database
+pub struct Queue {
+    backend: Box<dyn JobQueue>,
+    pub(super) db: DBPool,
+}
+
+impl Queue {
+    /// Creates a queue that publishes jobs to the backend
+    pub fn new(backend: impl JobQueue + 'static, db: DBPool) -> Self {
+        Self {
+            backend: Box::new(backend),
+            db,
+        }
+    }
+    /// Connects to the queue on the backend set by QUEUE_BACKEND
+    /// The NATS backend needs a client, the Postgres backend only needs the database
+    pub async fn connect(nats_client: Option<Client>, db: DBPool) -> Result<Self, QueueError> {
+        match QueueBackend::from_env()? {
+            QueueBackend::Nats => {
+                let nats_client = nats_client.ok_or_else(|| {
+                    QueueError::InvalidConnection(
+                        "The NATS queue backend needs a NATS connection".to_string(),
+                    )
+                })?;
+                Ok(Self::new(NatsQueue::connect(nats_client).await?, db))
+            }
+            QueueBackend::Postgres => Ok(Self::new(PostgresQueue::new(db.clone()), db)),
+        }
+    }
+    /// Creates a consumer for jobs on the subjects, runners sharing a name split its jobs
+    pub async fn create_consumer(
+        &self,
+        name: &str,
+        description: &str,
+        subjects: Vec<String>,
+    ) -> Result<Box<dyn JobConsumer>, QueueError> {
+        self.backend.consumer(name, description, subjects).await
+    }
+    /// Gets every subject that currently has jobs waiting in the queue
+    pub async fn pending_subjects(&self) -> Result<Vec<String>, QueueError> {
+        self.backend.pending_subjects().await
+    }
+    /// Publishes a typed job to the queue, returning the ID of the job it creates
+    pub async fn enqueue<J: Job>(&self, job: &J) -> Result<Uuid, QueueError> {
+        let message =
+            serde_json::to_string(job).map_err(|e| QueueError::InvalidPayload(e.to_string()))?;
+        self.publish(J::subject(), message, job.video_id(), Some(J::VERSION))
+            .await
+    }
+    /// Schedules a typed job to be published once run_at has passed, returning the ID of the job
+    /// The job is kept in the database until then, so it survives runner restarts
+    pub async fn enqueue_at<J: Job>(
+        &self,
+        job: &J,
+        run_at: DateTime<Utc>,
+    ) -> Result<Uuid, QueueError> {
+        // Nothing to wait on, publish it right away
+        if run_at <= Utc::now() {
+            return self.enqueue(job).await;
+        }
+
+        let message =
+            serde_json::to_string(job).map_err(|e| QueueError::InvalidPayload(e.to_string()))?;
+        let subject = J::subject();
+        let dedup_key = Self::dedup_key(&subject, &message);
+        if let Some(existing) = self.active_duplicate(&dedup_key).await? {
+            return Ok(existing);
+        }
+
+        let id = Uuid::new_v4();
+        let payload =
+            serde_json::to_value(job).map_err(|e| QueueError::InvalidPayload(e.to_string()))?;
+        let new_job = NewJob {
+            id,
+            subject: &subject,
+            payload: &payload,
+            video_id: job.video_id(),
+            version: Some(J::VERSION as i32),
+            dedup_key: Some(&dedup_key),
+        };
+        if JobRecord::schedule(&self.db, &new_job, run_at)
+            .await?
+            .is_none()
+        {
+            return self.created_duplicate(&dedup_key).await;
+        }
+        tracing::debug!("Scheduled job {} on {} for {}", id, subject, run_at);
+
+        Ok(id)
+    }
+    /// Schedules a typed job to be published after the delay, returning the ID of the job
+    pub async fn enqueue_after<J: Job>(
+        &self,
+        job: &J,
+        delay: chrono::Duration,
+    ) -> Result<Uuid, QueueError> {
+        self.enqueue_at(job, Utc::now() + delay).await
+    }
+    /// Gets scheduled jobs that haven't been published yet, soonest first
+    pub async fn scheduled(&self, limit: i64) -> Result<Vec<JobRecord>, QueueError> {
+        Ok(JobRecord::scheduled(&self.db, limit).await?)
+    }
+    /// Cancels a scheduled job so it never gets published
+    pub async fn cancel_scheduled(&self, id: Uuid) -> Result<(), QueueError> {
+        if !JobRecord::cancel_scheduled(&self.db, id).await? {
+            return Err(QueueError::NotFound(format!(
+                "No scheduled job with ID {}",
+                id
+            )));
+        }
+        Ok(())
+    }
+    /// Cancels a job that hasn't finished yet
+    /// Running jobs are stopped once their runner notices, which ends them as cancelled
+    pub async fn cancel(&self, id: Uuid) -> Result<(), QueueError> {
+        if !JobRecord::cancel(&self.db, id).await? {
+            return Err(QueueError::NotFound(format!(
+                "No unfinished job with ID {}",
+                id
+            )));
+        }
+        Ok(())
+    }
+    /// Cancels every unfinished job for a video, returning the IDs of the cancelled jobs
+    pub async fn cancel_for_video(&self, video_id: &str) -> Result<Vec<Uuid>, QueueError> {
+        Ok(JobRecord::cancel_for_video(&self.db, video_id).await?)
+    }
+    /// Publishes scheduled jobs that are due, returning how many were published
+    /// Jobs are locked while publishing so multiple runners can call this at once
+    pub async fn publish_due(&self, limit: i64) -> Result<usize, QueueError> {
+        let mut tx = self.db.begin().await?;
+        let jobs = JobRecord::lock_due(&mut tx, limit).await?;
+
+        let mut published = 0;
+        for job in jobs {
+            let message = job.payload.to_string();
+            let version = job.version.map(|version| version as u32);
+            // The job ID doubles as the message ID, so a job published twice only runs once
+            let message_id = job.id.to_string();
+            let sent = self
+                .send(job.id, &job.subject, &message, version, Some(&message_id))
+                .await;
+            // Leave the job scheduled so the next pass picks it up again
+            if let Err(e) = sent {
+                tracing::error!("Could not publish scheduled job {}: {}", job.id, e);
+                continue;
+            }
+            JobRecord::mark_published(&mut tx, job.id).await?;
+            published += 1;
+        }
+        tx.commit().await?;
+
+        Ok(published)
+    }
+    /// Publishes a raw message to the queue, returning the ID of the job it creates
+    /// Publishing a job that's identical to one still waiting or running returns the existing job
+    pub(crate) async fn publish(
+        &self,
+        subject: String,
+        message: String,
+        video_id: Option<&str>,
+        version: Option<u32>,
+    ) -> Result<Uuid, QueueError> {
+        let dedup_key = Self::dedup_key(&subject, &message);
+        if let Some(existing) = self.active_duplicate(&dedup_key).await? {
+            return Ok(existing);
+        }
+        // Finished jobs can be run again, so they move the message ID on to a new one
+        let finished = JobRecord::finished_count(&self.db, &dedup_key).await?;
+        let message_id = format!("{}:{}", dedup_key, finished);
+
+        let id = Uuid::new_v4();
+        let payload = serde_json::from_str(&message)
+            .unwrap_or_else(|_| serde_json::Value::String(message.clone()));
+        let new_job = NewJob {
+            id,
+            subject: &subject,
+            payload: &payload,
+            video_id,
+            version: version.map(|version| version as i32),
+            dedup_key: Some(&dedup_key),
+        };
+        if JobRecord::create(&self.db, &new_job).await?.is_none() {
+            return self.created_duplicate(&dedup_key).await;
+        }
+
+        let sent = match self
+            .send(id, &subject, &message, version, Some(&message_id))
+            .await
+        {
+            Ok(sent) => sent,
+            Err(e) => {
+                JobRecord::mark_failed(&self.db, id, &e.to_string()).await?;
+                return Err(e);
+            }
+        };
+
+        // Another publish of the same job beat this one to the queue
+        if !sent {
+            JobRecord::delete(&self.db, id).await?;
+            if let Some(existing) = JobRecord::by_dedup_key(&self.db, &dedup_key).await? {
+                tracing::debug!("Job {} is a duplicate of job {}", id, existing.id);
+                return Ok(existing.id);
+            }
+            return Err(QueueError::NotFound(format!(
+                "Job {} was a duplicate, but the original could not be found",
+                id
+            )));
+        }
+
+        Ok(id)
+    }
+    /// Finds an unfinished job with the dedup key
+    async fn active_duplicate(&self, dedup_key: &str) -> Result<Option<Uuid>, QueueError> {
+        let Some(existing) = JobRecord::by_dedup_key(&self.db, dedup_key).await? else {
+            return Ok(None);
+        };
+        match existing.status {
+            JobStatus::Scheduled | JobStatus::Queued | JobStatus::Running | JobStatus::Retrying => {
+                tracing::debug!("Job {} is already on {}", existing.id, existing.subject);
+                Ok(Some(existing.id))
+            }
+            _ => Ok(None),
+        }
+    }
+    /// Finds the unfinished job that a concurrent publish of the same job created first
+    async fn created_duplicate(&self, dedup_key: &str) -> Result<Uuid, QueueError> {
+        self.active_duplicate(dedup_key).await?.ok_or_else(|| {
+            QueueError::NotFound(format!(
+                "Job {} was a duplicate, but the original could not be found",
+                dedup_key
+            ))
+        })
+    }
+    /// Creates a key shared by every job with the same subject and payload
+    fn dedup_key(subject: &str, message: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(subject.as_bytes());
+        hasher.update(b"\n");
+        hasher.update(message.as_bytes());
+        hex::encode(hasher.finalize())
+    }
+    /// Publishes an existing job to the queue again with its payload version, resetting its record
+    pub(crate) async fn republish(
+        &self,
+        id: Uuid,
+        subject: String,
+        message: String,
+    ) -> Result<(), QueueError> {
+        JobRecord::requeue(&self.db, id).await?;
+        let version = JobRecord::by_id(&self.db, id)
+            .await?
+            .version
+            .map(|version| version as u32);
+        // Scheduled jobs are first published with their ID as the message ID, so keep replays apart
+        let message_id = format!("{}:replay", id);
+        self.send(id, &subject, &message, version, Some(&message_id))
+            .await?;
+        Ok(())
+    }
+    /// Sends a job to the backend, returning false when it was dropped as a duplicate
+    /// Messages with the same message ID are only delivered once
+    pub(super) async fn send(
+        &self,
+        id: Uuid,
+        subject: &str,
+        message: &str,
+        version: Option<u32>,
+        message_id: Option<&str>,
+    ) -> Result<bool, QueueError> {
+        self.backend
+            .publish(OutgoingJob {
+                id,
+                subject,
+                message,
+                version,
+                message_id,
+            })
+            .await
+    }
+}
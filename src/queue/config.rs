@@ -0,0 +1,84 @@
+use crate::event::{JOB_PREFIX, MESSAGE_PREFIX};
+
+pub const DEFAULT_RUNNER_NAME: &str = "farmhand_runner_1";
+pub const DEFAULT_CONCURRENCY: usize = 3;
+
+/// Configuration for a pool of job runners
+/// Runners sharing a name share a consumer, so they split the same jobs between them
+/// Pools with different names must consume subjects that don't overlap
+pub struct RunnerConfig {
+    /// The durable consumer the runner pulls jobs from
+    pub name: String,
+    /// The most jobs the runner works on at once
+    pub concurrency: usize,
+    /// The job subjects the runner consumes
+    pub subjects: Vec<String>,
+}
+
+impl RunnerConfig {
+    pub fn new() -> Self {
+        RunnerConfig {
+            name: Self::get_name(),
+            concurrency: Self::get_concurrency(),
+            subjects: Self::get_subjects(),
+        }
+    }
+    /// Gets the consumer name from environment variables
+    pub fn get_name() -> String {
+        std::env::var("RUNNER_NAME").unwrap_or_else(|_| DEFAULT_RUNNER_NAME.to_string())
+    }
+    /// Gets how many jobs can run at once from environment variables
+    pub fn get_concurrency() -> usize {
+        match std::env::var("RUNNER_CONCURRENCY") {
+            Ok(concurrency) => {
+                let concurrency = concurrency
+                    .parse()
+                    .expect("RUNNER_CONCURRENCY must be a number");
+                assert!(concurrency > 0, "RUNNER_CONCURRENCY must be at least 1");
+                concurrency
+            }
+            Err(_) => DEFAULT_CONCURRENCY,
+        }
+    }
+    /// Gets the comma separated job subjects to consume from environment variables
+    /// Job names like `video_to_stream` are expanded into their full subject
+    /// Defaults to every job when unset
+    pub fn get_subjects() -> Vec<String> {
+        let subjects: Vec<String> = std::env::var("RUNNER_SUBJECTS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|subject| !subject.is_empty())
+            .map(Self::expand_subject)
+            .collect();
+
+        if subjects.is_empty() {
+            return vec![Self::expand_subject(">")];
+        }
+        subjects
+    }
+    /// Turns a job name into its full subject, leaving full subjects alone
+    fn expand_subject(subject: &str) -> String {
+        let prefix = format!("{}.{}.", MESSAGE_PREFIX, JOB_PREFIX);
+        if subject.starts_with(&prefix) {
+            subject.to_string()
+        } else {
+            format!("{}{}", prefix, subject)
+        }
+    }
+    /// Describes what the runner consumes, used for the consumer description and logs
+    pub fn describe(&self) -> String {
+        format!(
+            "{} running up to {} jobs from {}",
+            self.name,
+            self.concurrency,
+            self.subjects.join(", ")
+        )
+    }
+}
+
+impl Default for RunnerConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use super::Delivery;
+use crate::db::{DBPool, JobRecord, WorkflowStepRecord};
+
+/// How often progress updates are written to the job record
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Information about the job a runner is working on, used to keep its record up to date
+#[derive(Clone)]
+pub struct JobContext {
+    /// The ID of the job record, missing for messages published without one
+    pub id: Option<Uuid>,
+    /// Which delivery of the job this is, starting at 1
+    pub attempt: i64,
+    db: DBPool,
+    cancellation: CancellationToken,
+    /// What the job produced, saved on its record and handed to the workflow steps that depend on it
+    output: Arc<Mutex<Option<Value>>>,
+}
+
+impl JobContext {
+    /// Creates the context for a job pulled from the job queue
+    pub fn new(delivery: &Delivery, db: DBPool) -> Self {
+        Self {
+            id: delivery.job_id,
+            attempt: delivery.attempt,
+            db,
+            cancellation: CancellationToken::new(),
+            output: Arc::new(Mutex::new(None)),
+        }
+    }
+    /// Logs failures to update the job record, the job itself shouldn't fail because of them
+    fn log_result(&self, result: Result<(), sqlx::Error>) {
+        if let (Some(id), Err(e)) = (self.id, result) {
+            tracing::error!("Failed to update job record {}: {}", id, e);
+        }
+    }
+    /// Marks the job as running
+    pub async fn started(&self) {
+        if let Some(id) = self.id {
+            let result = JobRecord::mark_running(&self.db, id, self.attempt as i32).await;
+            self.log_result(result);
+            let result = WorkflowStepRecord::mark_running(&self.db, id).await;
+            self.log_result(result);
+        }
+    }
+    /// Marks the job as waiting in the queue again
+    pub async fn deferred(&self) {
+        if let Some(id) = self.id {
+            let result = JobRecord::mark_queued(&self.db, id).await;
+            self.log_result(result);
+        }
+    }
+    /// Marks the job as failed with another attempt coming
+    pub async fn retrying(&self, error: &str) {
+        if let Some(id) = self.id {
+            let result = JobRecord::mark_retrying(&self.db, id, error).await;
+            self.log_result(result);
+        }
+    }
+    /// Marks the job as successfully finished, saving whatever it produced
+    pub async fn completed(&self) {
+        if let Some(id) = self.id {
+            let result = JobRecord::mark_completed(&self.db, id, self.output().as_ref()).await;
+            self.log_result(result);
+        }
+    }
+    /// Marks the job as failed with no more attempts coming
+    pub async fn failed(&self, error: &str) {
+        if let Some(id) = self.id {
+            let result = JobRecord::mark_failed(&self.db, id, error).await;
+            self.log_result(result);
+        }
+    }
+    /// Checks the job record for a cancellation, cancelling the context if there was one
+    pub async fn check_cancelled(&self) -> bool {
+        let Some(id) = self.id else {
+            return false;
+        };
+        match JobRecord::is_cancelled(&self.db, id).await {
+            Ok(true) => {
+                self.cancellation.cancel();
+                true
+            }
+            Ok(false) => false,
+            Err(e) => {
+                tracing::error!("Failed to check if job {} was cancelled: {}", id, e);
+                false
+            }
+        }
+    }
+    /// Whether the job has been cancelled, runners should stop working on it when it has
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+    /// Gets a token that is cancelled along with the job, for work that can't poll the context
+    pub fn cancellation(&self) -> CancellationToken {
+        self.cancellation.clone()
+    }
+    /// Records how far along the job is, as a percentage
+    pub async fn report_progress(&self, progress: f32) {
+        if let Some(id) = self.id {
+            let result = JobRecord::set_progress(&self.db, id, progress).await;
+            self.log_result(result);
+        }
+    }
+    /// Spawns a task that periodically writes the latest progress sent on the returned channel
+    /// The task stops once the sender is dropped
+    pub fn progress_reporter(&self) -> watch::Sender<f32> {
+        let (sender, mut receiver) = watch::channel(0.0);
+        let context = self.clone();
+        tokio::spawn(async move {
+            while receiver.changed().await.is_ok() {
+                let progress = *receiver.borrow_and_update();
+                context.report_progress(progress).await;
+                tokio::time::sleep(PROGRESS_INTERVAL).await;
+            }
+        });
+        sender
+    }
+    /// Stores what the job produced, saved on its record once it completes
+    pub fn set_output<T: Serialize>(&self, output: &T) -> Result<(), serde_json::Error> {
+        let value = serde_json::to_value(output)?;
+        *self.output.lock().expect("Job output lock poisoned") = Some(value);
+        Ok(())
+    }
+    /// Gets what the job produced, if it stored anything
+    pub fn output(&self) -> Option<Value> {
+        self.output
+            .lock()
+            .expect("Job output lock poisoned")
+            .clone()
+    }
+    /// Gets the outputs of the workflow steps this job's step depends on, keyed by step name
+    /// Jobs that aren't part of a workflow have no dependencies
+    pub async fn dependency_outputs(&self) -> Result<HashMap<String, Option<Value>>, sqlx::Error> {
+        let Some(id) = self.id else {
+            return Ok(HashMap::new());
+        };
+        let Some(step) = WorkflowStepRecord::by_job_id(&self.db, id).await? else {
+            return Ok(HashMap::new());
+        };
+
+        let outputs = WorkflowStepRecord::by_workflow_id(&self.db, step.workflow_id)
+            .await?
+            .into_iter()
+            .filter(|dependency| step.depends_on.contains(&dependency.name))
+            .map(|dependency| (dependency.name, dependency.output))
+            .collect();
+        Ok(outputs)
+    }
+}
@@ -0,0 +1,276 @@
+use async_nats::{
+    jetstream::{
+        self,
+        consumer::{AckPolicy, DeliverPolicy},
+        stream::RetentionPolicy,
+        Context,
+    },
+    Client, HeaderMap,
+};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use serde::Serialize;
+use std::time::Duration;
+use uuid::Uuid;
+
+use super::{backend::nats::JOB_ID_HEADER, Queue};
+use crate::{
+    error::QueueError,
+    event::{DLQ_PREFIX, DLQ_STREAM, MESSAGE_PREFIX},
+};
+
+pub const FAILURE_REASON_HEADER: &str = "Farmhand-Failure-Reason";
+pub const ATTEMPTS_HEADER: &str = "Farmhand-Attempts";
+pub const ORIGINAL_SUBJECT_HEADER: &str = "Farmhand-Original-Subject";
+/// The longest failure reason kept in the header, longer ones are cut off
+const MAX_REASON_LENGTH: usize = 1024;
+
+/// A job that exhausted its delivery attempts
+#[derive(Serialize)]
+pub struct DeadLetter {
+    pub sequence: u64,
+    pub job_id: Option<Uuid>,
+    pub original_subject: String,
+    pub reason: String,
+    pub attempts: i64,
+    pub failed_at: DateTime<Utc>,
+    pub payload: String,
+}
+
+impl DeadLetter {
+    /// Builds a dead letter out of the message stored in the stream
+    fn from_parts(sequence: u64, headers: Option<&HeaderMap>, payload: &Bytes, time: i64) -> Self {
+        let header = |name: &str| {
+            headers
+                .and_then(|headers| headers.get(name))
+                .map(|value| value.as_str().to_string())
+                .unwrap_or_default()
+        };
+        DeadLetter {
+            sequence,
+            job_id: header(JOB_ID_HEADER).parse().ok(),
+            original_subject: header(ORIGINAL_SUBJECT_HEADER),
+            reason: header(FAILURE_REASON_HEADER),
+            attempts: header(ATTEMPTS_HEADER).parse().unwrap_or_default(),
+            failed_at: DateTime::from_timestamp(time, 0).unwrap_or_default(),
+            payload: String::from_utf8_lossy(payload).to_string(),
+        }
+    }
+}
+
+/// Holds jobs that failed too many times so they can be inspected and replayed
+pub struct DeadLetterQueue {
+    name: String,
+    jetstream: Context,
+}
+
+impl DeadLetterQueue {
+    /// Connects to an existing dead letter queue
+    pub async fn connect(nats_client: Client) -> Result<Self, QueueError> {
+        let jetstream = Self::create_jetstream(nats_client);
+        jetstream
+            .get_stream(DLQ_STREAM)
+            .await
+            .map_err(|e| QueueError::InvalidConnection(e.to_string()))?;
+        Ok(DeadLetterQueue {
+            name: DLQ_STREAM.to_string(),
+            jetstream,
+        })
+    }
+    /// Creates a new dead letter queue
+    pub async fn new(nats_client: Client) -> Result<Self, QueueError> {
+        let jetstream = Self::create_jetstream(nats_client);
+        jetstream
+            .create_stream(jetstream::stream::Config {
+                name: DLQ_STREAM.to_string(),
+                subjects: vec![format!("{}.{}.>", MESSAGE_PREFIX, DLQ_PREFIX)],
+                description: Some("Farmhand jobs that exhausted their attempts".to_string()),
+                retention: RetentionPolicy::Limits,
+                max_age: Duration::from_secs(60 * 60 * 24 * 30), // 1 month
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| QueueError::InvalidConnection(e.to_string()))?;
+        Ok(DeadLetterQueue {
+            name: DLQ_STREAM.to_string(),
+            jetstream,
+        })
+    }
+    /// Deletes the dead letter queue
+    pub async fn delete(nats_client: Client) -> Result<(), QueueError> {
+        let jetstream = Self::create_jetstream(nats_client);
+
+        // Check if stream exists first
+        if jetstream.get_stream(DLQ_STREAM).await.is_ok() {
+            jetstream
+                .delete_stream(DLQ_STREAM)
+                .await
+                .map_err(|e| QueueError::InvalidConnection(e.to_string()))?;
+        } else {
+            tracing::warn!("Stream {} does not exist", DLQ_STREAM);
+        }
+        Ok(())
+    }
+    /// Creates a new jetstream context
+    fn create_jetstream(nats_client: Client) -> Context {
+        jetstream::new(nats_client)
+    }
+    /// Gets the dead letter subject for a job subject
+    /// e.g. farmhand.jobs.video_to_stream -> farmhand.dlq.jobs.video_to_stream
+    fn get_subject(original_subject: &str) -> String {
+        let job_subject = original_subject
+            .strip_prefix(&format!("{}.", MESSAGE_PREFIX))
+            .unwrap_or(original_subject);
+        format!("{}.{}.{}", MESSAGE_PREFIX, DLQ_PREFIX, job_subject)
+    }
+    /// Puts a failure reason on a single line that fits in a header
+    /// Errors like ffmpeg's include its output, whose line breaks would end the header block
+    fn header_reason(reason: &str) -> String {
+        reason
+            .split(['\r', '\n'])
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join(" | ")
+            .chars()
+            .take(MAX_REASON_LENGTH)
+            .collect()
+    }
+    /// Copies a failed job into the dead letter queue
+    pub async fn publish(
+        &self,
+        job_id: Option<Uuid>,
+        original_subject: &str,
+        payload: Bytes,
+        reason: &str,
+        attempts: i64,
+    ) -> Result<(), QueueError> {
+        tracing::debug!("Dead lettering job from subject {}", original_subject);
+        let mut headers = HeaderMap::new();
+        if let Some(job_id) = job_id {
+            headers.insert(JOB_ID_HEADER, job_id.to_string().as_str());
+        }
+        headers.insert(ORIGINAL_SUBJECT_HEADER, original_subject);
+        headers.insert(FAILURE_REASON_HEADER, Self::header_reason(reason).as_str());
+        headers.insert(ATTEMPTS_HEADER, attempts.to_string().as_str());
+        self.jetstream
+            .publish_with_headers(Self::get_subject(original_subject), headers, payload)
+            .await
+            .map_err(|e| QueueError::InvalidConnection(e.to_string()))?
+            .await
+            .map_err(|e| QueueError::InvalidConnection(e.to_string()))?;
+
+        Ok(())
+    }
+    /// Lists dead lettered jobs, oldest first
+    pub async fn list(&self, limit: usize) -> Result<Vec<DeadLetter>, QueueError> {
+        // Read everything in the stream without acknowledging it
+        let consumer_config = jetstream::consumer::pull::Config {
+            deliver_policy: DeliverPolicy::All,
+            ack_policy: AckPolicy::None,
+            ..Default::default()
+        };
+        let consumer = self
+            .jetstream
+            .create_consumer_on_stream(consumer_config, self.name.to_string())
+            .await
+            .map_err(|e| QueueError::InvalidConnection(e.to_string()))?;
+
+        let mut dead_letters = Vec::new();
+        let mut batch = consumer
+            .fetch()
+            .max_messages(limit)
+            .expires(Duration::from_secs(1))
+            .messages()
+            .await
+            .map_err(|e| QueueError::InvalidConnection(e.to_string()))?;
+        while let Some(message) = batch.next().await {
+            let Ok(message) = message else {
+                tracing::error!("Failed to unwrap message: {:?}", message);
+                continue;
+            };
+            let (sequence, published) = match message.info() {
+                Ok(info) => (info.stream_sequence, info.published.unix_timestamp()),
+                Err(e) => {
+                    tracing::error!("Failed to get message info: {:?}", e);
+                    continue;
+                }
+            };
+            dead_letters.push(DeadLetter::from_parts(
+                sequence,
+                message.headers.as_ref(),
+                &message.payload,
+                published,
+            ));
+        }
+        Ok(dead_letters)
+    }
+    /// Gets a single dead lettered job by its sequence in the stream
+    pub async fn get(&self, sequence: u64) -> Result<DeadLetter, QueueError> {
+        let stream = self
+            .jetstream
+            .get_stream(&self.name)
+            .await
+            .map_err(|e| QueueError::InvalidConnection(e.to_string()))?;
+        let message = stream
+            .get_raw_message(sequence)
+            .await
+            .map_err(|e| QueueError::NotFound(e.to_string()))?;
+        Ok(DeadLetter::from_parts(
+            message.sequence,
+            Some(&message.headers),
+            &message.payload,
+            message.time.unix_timestamp(),
+        ))
+    }
+    /// Publishes a dead lettered job back onto the job queue and removes it from the dead letter queue
+    pub async fn replay(&self, sequence: u64, job_queue: &Queue) -> Result<(), QueueError> {
+        let dead_letter = self.get(sequence).await?;
+        match dead_letter.job_id {
+            // Reuse the existing job record so its history stays in one place
+            Some(job_id) => {
+                job_queue
+                    .republish(job_id, dead_letter.original_subject, dead_letter.payload)
+                    .await?
+            }
+            None => {
+                job_queue
+                    .publish(
+                        dead_letter.original_subject,
+                        dead_letter.payload,
+                        None,
+                        None,
+                    )
+                    .await?;
+            }
+        }
+        self.remove(sequence).await
+    }
+    /// Removes a single dead lettered job
+    pub async fn remove(&self, sequence: u64) -> Result<(), QueueError> {
+        let stream = self
+            .jetstream
+            .get_stream(&self.name)
+            .await
+            .map_err(|e| QueueError::InvalidConnection(e.to_string()))?;
+        stream
+            .delete_message(sequence)
+            .await
+            .map_err(|e| QueueError::NotFound(e.to_string()))?;
+        Ok(())
+    }
+    /// Removes every dead lettered job
+    pub async fn purge(&self) -> Result<u64, QueueError> {
+        let stream = self
+            .jetstream
+            .get_stream(&self.name)
+            .await
+            .map_err(|e| QueueError::InvalidConnection(e.to_string()))?;
+        let response = stream
+            .purge()
+            .await
+            .map_err(|e| QueueError::InvalidConnection(e.to_string()))?;
+        Ok(response.purged)
+    }
+}
@@ -0,0 +1,78 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{Job, JobContext, RetryPolicy, Runner, RunnerState};
+use crate::{db::Video, error::StorageError, prelude::get_storage_dir, storage::Storage};
+
+/// Cleans up the assets of a deleted video that couldn't all be removed right away
+/// The video row is only deleted once its assets are gone
+#[derive(Serialize, Deserialize)]
+pub struct DeleteAssetsPayload {
+    pub video_id: String,
+    /// Owner of the video, whose row gets deleted once the assets are
+    pub user_id: Uuid,
+}
+
+impl Job for DeleteAssetsPayload {
+    const NAME: &'static str = "delete_assets";
+    // Storage outages can take a while to clear up, so keep trying for a few hours
+    const RETRY_POLICY: RetryPolicy =
+        RetryPolicy::new(10, Duration::from_secs(60), Duration::from_secs(60 * 60));
+
+    // Not linked to the video, deleting the video would delete the record of this job too
+}
+
+/// Removes every object under the video's storage prefix, returning how many there were
+/// Uploads still open for the video are aborted first, so none can complete afterwards
+pub async fn delete_video_assets(
+    storage: &dyn Storage,
+    video_id: &str,
+) -> Result<usize, StorageError> {
+    let prefix = format!("{}/{}/", get_storage_dir(), video_id);
+    for upload in storage.list_multipart_uploads(&prefix).await? {
+        tracing::debug!("Aborting upload {} of {}", upload.upload_id, upload.key);
+        storage
+            .abort_multipart_upload(&upload.key, &upload.upload_id)
+            .await?;
+    }
+    let deleted = storage.delete_prefix(&prefix).await?;
+    tracing::info!("Deleted {} objects of video {}", deleted, video_id);
+    Ok(deleted)
+}
+
+pub struct DeleteAssetsRunner {
+    state: Arc<RunnerState>,
+}
+
+impl DeleteAssetsRunner {
+    pub fn new(state: Arc<RunnerState>) -> Self {
+        Self { state }
+    }
+}
+
+impl Runner for DeleteAssetsRunner {
+    type Job = DeleteAssetsPayload;
+
+    /// Deletes the assets of a video from storage, then the video itself
+    async fn process_job(&self, _context: &JobContext, payload: Self::Job) -> Result<()> {
+        tracing::debug!(
+            "Processing job with runner DeleteAssetsRunner for video ID {video_id}",
+            video_id = payload.video_id,
+        );
+
+        delete_video_assets(&*self.state.storage, &payload.video_id)
+            .await
+            .map_err(|e| anyhow!("Could not delete assets of {}: {}", payload.video_id, e))?;
+        Video::delete(
+            &self.state.db,
+            payload.user_id,
+            vec![payload.video_id.clone()],
+        )
+        .await?;
+        tracing::info!("Finished deleting video {}", payload.video_id);
+        Ok(())
+    }
+}
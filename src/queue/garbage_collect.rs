@@ -0,0 +1,260 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{Job, JobContext, Queue, RetryPolicy, Runner, RunnerState};
+use crate::{db::Video, error::QueueError, prelude::get_storage_dir};
+
+pub const DEFAULT_GC_INTERVAL: Duration = Duration::from_secs(60 * 60);
+pub const DEFAULT_GC_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// When garbage is collected and what counts as garbage
+pub struct GcConfig {
+    /// How often the collection runs
+    pub interval: Duration,
+    /// How long uploads can sit unfinished, and prefixes without a video untouched, before they're garbage
+    pub max_age: Duration,
+    /// Only report what would be cleaned up, which is the default until an operator has checked a report
+    pub dry_run: bool,
+}
+
+impl GcConfig {
+    pub fn new() -> Self {
+        GcConfig {
+            interval: Self::get_duration("GC_INTERVAL_SECS", DEFAULT_GC_INTERVAL),
+            max_age: Self::get_duration("GC_MAX_AGE_SECS", DEFAULT_GC_MAX_AGE),
+            dry_run: Self::get_dry_run(),
+        }
+    }
+    /// Gets a duration in seconds from environment variables
+    fn get_duration(name: &str, default: Duration) -> Duration {
+        match std::env::var(name) {
+            Ok(seconds) => Duration::from_secs(
+                seconds
+                    .parse()
+                    .unwrap_or_else(|_| panic!("{} must be a number of seconds", name)),
+            ),
+            Err(_) => default,
+        }
+    }
+    /// Gets whether collections only report from environment variables, true unless GC_DRY_RUN is false
+    fn get_dry_run() -> bool {
+        match std::env::var("GC_DRY_RUN") {
+            Ok(dry_run) => dry_run.parse().expect("GC_DRY_RUN must be true or false"),
+            Err(_) => true,
+        }
+    }
+    /// Schedules the next periodic collection, which only happens once however many runners ask
+    pub async fn schedule_next(&self, queue: &Queue) -> Result<(), QueueError> {
+        let interval = self.interval.as_secs().max(1) as i64;
+        // Every runner picks the same time, so the payloads match and the job is only scheduled once
+        let scheduled_for = (Utc::now().timestamp() / interval + 1) * interval;
+        let run_at = DateTime::from_timestamp(scheduled_for, 0).unwrap_or_else(Utc::now);
+        let payload = GarbageCollectPayload {
+            scheduled_for,
+            dry_run: self.dry_run,
+        };
+        queue.enqueue_at(&payload, run_at).await?;
+        Ok(())
+    }
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GarbageCollectPayload {
+    /// Unix time the collection was scheduled for, which keeps every run its own job
+    pub scheduled_for: i64,
+    /// Only report what would be cleaned up
+    pub dry_run: bool,
+}
+
+impl Job for GarbageCollectPayload {
+    const NAME: &'static str = "garbage_collect";
+    // The next scheduled run picks up whatever a failed one missed
+    const RETRY_POLICY: RetryPolicy =
+        RetryPolicy::new(2, Duration::from_secs(5 * 60), Duration::from_secs(5 * 60));
+}
+
+/// A video ID under the storage root without a video, along with what's stored for it
+#[derive(Debug, Serialize)]
+pub struct OrphanedPrefix {
+    pub prefix: String,
+    pub objects: usize,
+    /// Size of every object under the prefix in bytes
+    pub bytes: u64,
+}
+
+/// Everything a collection cleaned up, or would have in a dry run
+#[derive(Debug, Default, Serialize)]
+pub struct GarbageReport {
+    pub dry_run: bool,
+    /// Keys of the multipart uploads that were abandoned
+    pub aborted_uploads: Vec<String>,
+    /// Videos whose upload was never finished, marked as failed
+    pub stale_videos: Vec<String>,
+    pub orphaned_prefixes: Vec<OrphanedPrefix>,
+    /// Cleanup that failed, the next collection tries again
+    pub errors: Vec<String>,
+}
+
+/// What's stored under the prefix of a single video
+#[derive(Default)]
+struct PrefixUsage {
+    objects: usize,
+    bytes: u64,
+    last_modified: Option<DateTime<Utc>>,
+}
+
+pub struct GarbageCollectRunner {
+    state: Arc<RunnerState>,
+    max_age: Duration,
+}
+
+impl GarbageCollectRunner {
+    pub fn new(state: Arc<RunnerState>, config: &GcConfig) -> Self {
+        Self {
+            state,
+            max_age: config.max_age,
+        }
+    }
+    /// Cleans up abandoned uploads, the videos they were for and prefixes left without a video
+    async fn collect(&self, dry_run: bool) -> Result<GarbageReport> {
+        let storage = &*self.state.storage;
+        let cutoff = Utc::now() - chrono::Duration::from_std(self.max_age)?;
+        let root = format!("{}/", get_storage_dir());
+        let video_id_of = |key: &str| -> Option<String> {
+            let (video_id, _) = key.strip_prefix(&root)?.split_once('/')?;
+            Some(video_id.to_string())
+        };
+        let mut report = GarbageReport {
+            dry_run,
+            ..Default::default()
+        };
+
+        // Abort uploads the browser never finished, and keep the prefixes of the rest
+        let mut uploading = HashSet::new();
+        for upload in storage.list_multipart_uploads(&root).await? {
+            // Backends that don't know when an upload started can't tell it's stale
+            if upload
+                .initiated
+                .map_or(true, |initiated| initiated > cutoff)
+            {
+                uploading.extend(video_id_of(&upload.key));
+                continue;
+            }
+            if !dry_run {
+                if let Err(e) = storage
+                    .abort_multipart_upload(&upload.key, &upload.upload_id)
+                    .await
+                {
+                    report
+                        .errors
+                        .push(format!("Could not abort upload of {}: {}", upload.key, e));
+                    continue;
+                }
+            }
+            report.aborted_uploads.push(upload.key);
+        }
+
+        // Videos stuck waiting on an upload that's gone won't ever be processed
+        for video in Video::stale_pending(&self.state.db, cutoff).await? {
+            if uploading.contains(&video.id) {
+                continue;
+            }
+            if !dry_run {
+                Video::set_failed(
+                    &self.state.db,
+                    &video.id,
+                    "The upload was never completed",
+                    "Upload was never completed",
+                )
+                .await?;
+            }
+            report.stale_videos.push(video.id);
+        }
+
+        // Group everything in storage by the video it was stored for
+        let mut prefixes: BTreeMap<String, PrefixUsage> = BTreeMap::new();
+        for object in storage.list(&root).await? {
+            let Some(video_id) = video_id_of(&object.key) else {
+                continue;
+            };
+            let usage = prefixes.entry(video_id).or_default();
+            usage.objects += 1;
+            usage.bytes += object.size;
+            usage.last_modified = usage.last_modified.max(object.last_modified);
+        }
+        let video_ids: Vec<String> = prefixes.keys().cloned().collect();
+        let existing: HashSet<String> = Video::existing_ids(&self.state.db, &video_ids)
+            .await?
+            .into_iter()
+            .collect();
+
+        for (video_id, usage) in prefixes {
+            // Uploads are started before their video is created, so leave anything recent alone
+            let recent = usage
+                .last_modified
+                .map_or(true, |last_modified| last_modified > cutoff);
+            if existing.contains(&video_id) || uploading.contains(&video_id) || recent {
+                continue;
+            }
+            let prefix = format!("{}{}/", root, video_id);
+            if !dry_run {
+                if let Err(e) = storage.delete_prefix(&prefix).await {
+                    report
+                        .errors
+                        .push(format!("Could not delete {}: {}", prefix, e));
+                    continue;
+                }
+            }
+            report.orphaned_prefixes.push(OrphanedPrefix {
+                prefix,
+                objects: usage.objects,
+                bytes: usage.bytes,
+            });
+        }
+
+        Ok(report)
+    }
+}
+
+impl Runner for GarbageCollectRunner {
+    type Job = GarbageCollectPayload;
+
+    /// Collects the garbage left behind by abandoned uploads and deleted videos
+    async fn process_job(&self, context: &JobContext, payload: Self::Job) -> Result<()> {
+        tracing::debug!(
+            "Processing job with runner GarbageCollectRunner, dry run {}",
+            payload.dry_run
+        );
+
+        let report = self.collect(payload.dry_run).await?;
+        let verb = match report.dry_run {
+            true => "Would clean up",
+            false => "Cleaned up",
+        };
+        tracing::info!(
+            "{} {} uploads, {} stale videos and {} orphaned prefixes",
+            verb,
+            report.aborted_uploads.len(),
+            report.stale_videos.len(),
+            report.orphaned_prefixes.len()
+        );
+        for error in &report.errors {
+            tracing::warn!("Garbage collection error: {}", error);
+        }
+        context.set_output(&report)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{Job, JobContext, RetryPolicy, Runner, RunnerState};
+use crate::{
+    db::{NewVideoImages, VideoImages, VideoMetadata},
+    error::JobError,
+    prelude::get_storage_dir,
+    storage::{sync_directory, SyncOptions, SyncReport},
+    vod::{images::ImageGenerator, stream::get_ffmpeg_location, DownloadSettings, Vod},
+};
+
+#[derive(Serialize, Deserialize)]
+pub struct GenerateImagesPayload {
+    pub video_id: String,
+}
+
+impl Job for GenerateImagesPayload {
+    const NAME: &'static str = "generate_images";
+    const RETRY_POLICY: RetryPolicy =
+        RetryPolicy::new(3, Duration::from_secs(60), Duration::from_secs(15 * 60));
+
+    fn video_id(&self) -> Option<&str> {
+        Some(&self.video_id)
+    }
+}
+
+pub struct GenerateImagesRunner {
+    state: Arc<RunnerState>,
+}
+
+impl GenerateImagesRunner {
+    pub fn new(state: Arc<RunnerState>) -> Self {
+        Self { state }
+    }
+    /// Downloads the raw video, grabs its stills and uploads them beside the stream files
+    async fn generate(&self, context: &JobContext, video_id: &str) -> Result<VideoImages> {
+        let working_dir = PathBuf::from(get_storage_dir())
+            .join(video_id)
+            .join("images");
+        tokio::fs::create_dir_all(&working_dir).await?;
+        let vod = Vod::by_id(&self.state.db, video_id.to_string(), working_dir.clone())
+            .await
+            .map_err(JobError::fatal_if_not_found)?;
+
+        let download_settings = DownloadSettings {
+            storage: &*self.state.storage,
+        };
+        let raw_video_path = vod
+            .get_raw_video(working_dir.clone(), Some(download_settings))
+            .await?
+            .ok_or_else(|| {
+                JobError::fatal(anyhow!("Raw video for {} could not be found", video_id))
+            })?;
+
+        // The probe step stored the metadata, only probe again if it's missing
+        let info = match VideoMetadata::by_video_id(&self.state.db, video_id).await? {
+            Some(metadata) => metadata.info,
+            None => vod.converter.probe_media(&raw_video_path).await?,
+        };
+
+        let images_dir = working_dir.join("thumbnails");
+        let generator = ImageGenerator::new(get_ffmpeg_location())?;
+        let images = generator
+            .generate(&raw_video_path, &images_dir, &info, &context.cancellation())
+            .await?;
+
+        let remote_prefix = format!("{}/thumbnails", vod.get_remote_storage_prefix());
+        sync_directory(
+            &*self.state.storage,
+            &images_dir,
+            &remote_prefix,
+            &SyncOptions::default(),
+        )
+        .await
+        .and_then(SyncReport::into_result)
+        .map_err(|e| anyhow!("Could not sync images to storage: {}", e))?;
+
+        if let Err(e) = tokio::fs::remove_dir_all(&working_dir).await {
+            tracing::warn!("Could not clean up {:?}: {}", working_dir, e);
+        }
+
+        let remote_key = |name: &str| format!("{}/{}", remote_prefix, name);
+        let candidates: Vec<String> = images
+            .candidates
+            .iter()
+            .map(|name| remote_key(name))
+            .collect();
+        let new_images = NewVideoImages {
+            poster_path: &remote_key(&images.poster),
+            thumbnail_candidates: &candidates,
+            storyboard_path: &remote_key(&images.storyboard),
+        };
+        Ok(VideoImages::upsert(&self.state.db, video_id, &new_images).await?)
+    }
+}
+
+impl Runner for GenerateImagesRunner {
+    type Job = GenerateImagesPayload;
+
+    /// Generates the poster, thumbnails and storyboard for a processed video
+    /// The stream is already published by now, so failing here leaves the video playable
+    async fn process_job(&self, context: &JobContext, payload: Self::Job) -> Result<()> {
+        tracing::debug!(
+            "Processing job with runner GenerateImagesRunner for video ID {video_id}",
+            video_id = payload.video_id,
+        );
+
+        match self.generate(context, &payload.video_id).await {
+            Ok(images) => {
+                tracing::info!(
+                    "Generated images for video {} with thumbnail {}",
+                    payload.video_id,
+                    images.thumbnail_path
+                );
+                Ok(())
+            }
+            Err(_) if context.is_cancelled() => Err(JobError::Cancelled.into()),
+            Err(err) => {
+                tracing::error!(
+                    "Failed to generate images for video {}: {}",
+                    payload.video_id,
+                    err
+                );
+                Err(err)
+            }
+        }
+    }
+}
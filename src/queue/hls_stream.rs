@@ -1,27 +1,167 @@
+use std::{sync::Arc, time::Duration};
+
 use anyhow::Result;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use super::Runner;
+use super::{
+    generate_images::GenerateImagesPayload,
+    probe_media::{ProbeMediaPayload, PROBE_STEP},
+    publish_stream::PublishStreamPayload,
+    transcode_audio::{TranscodeAudioPayload, AUDIO_STEP},
+    transcode_ladder::{TranscodeLadderPayload, LADDER_STEP},
+    transcode_rendition::TranscodeRenditionPayload,
+    Job, JobContext, RetryPolicy, Runner, RunnerState, Workflow,
+};
+use crate::{
+    db::{ProcessingStatus, TranscodeProfile, Video},
+    error::{JobError, QueueError},
+    vod::ladder::{EncodingLadder, EncodingMode},
+};
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 pub struct VideoToStreamPayload {
     pub video_id: String,
 }
 
-pub struct HlsStreamRunner;
+impl Job for VideoToStreamPayload {
+    const NAME: &'static str = "video_to_stream";
+    // Starting the workflow is cheap, the conversion retries happen in its steps
+    const RETRY_POLICY: RetryPolicy =
+        RetryPolicy::new(3, Duration::from_secs(60), Duration::from_secs(15 * 60));
+
+    fn video_id(&self) -> Option<&str> {
+        Some(&self.video_id)
+    }
+}
+
+pub struct HlsStreamRunner {
+    state: Arc<RunnerState>,
+}
+
+impl HlsStreamRunner {
+    pub fn new(state: Arc<RunnerState>) -> Self {
+        Self { state }
+    }
+    /// Finds the ladder the video is converted with, falling back to the built in one
+    /// when the video has no profile and no profile is the default
+    async fn ladder(&self, video_id: &str) -> Result<EncodingLadder> {
+        match TranscodeProfile::for_video(&self.state.db, video_id).await? {
+            Some(profile) => {
+                tracing::debug!(
+                    "Converting video {} with profile {}",
+                    video_id,
+                    profile.name
+                );
+                Ok(profile.ladder())
+            }
+            None => Ok(EncodingLadder::default()),
+        }
+    }
+    /// Builds the workflow that converts the video into every quality and its audio side by side,
+    /// then publishes the stream once all of them are done and grabs its stills
+    /// Steps can run on different runners, so the audio, every rendition and the images each
+    /// download the whole raw video, SinglePass downloads it once for all of the qualities
+    fn workflow(video_id: &str, ladder: EncodingLadder) -> Result<Workflow, QueueError> {
+        // Every rendition waits on the probe, which decides whether the source is big enough for it
+        let probe = ProbeMediaPayload {
+            video_id: video_id.to_string(),
+        };
+        let mut workflow = Workflow::new(VideoToStreamPayload::NAME)
+            .for_video(video_id)
+            .step(PROBE_STEP, &probe, &[])?;
+        let audio = TranscodeAudioPayload {
+            video_id: video_id.to_string(),
+            settings: ladder.settings.clone(),
+        };
+        workflow = workflow.step(AUDIO_STEP, &audio, &[PROBE_STEP])?;
+        let mut renditions = vec![AUDIO_STEP.to_string()];
+        match ladder.settings.mode {
+            EncodingMode::PerRendition => {
+                for quality in ladder.qualities {
+                    let step = format!("transcode_{}", quality.name);
+                    let payload = TranscodeRenditionPayload {
+                        video_id: video_id.to_string(),
+                        quality,
+                        settings: ladder.settings.clone(),
+                    };
+                    workflow = workflow.step(step.as_str(), &payload, &[PROBE_STEP])?;
+                    renditions.push(step);
+                }
+            }
+            // A single step decodes the source once for every quality
+            EncodingMode::SinglePass => {
+                let payload = TranscodeLadderPayload {
+                    video_id: video_id.to_string(),
+                    qualities: ladder.qualities,
+                    settings: ladder.settings.clone(),
+                };
+                workflow = workflow.step(LADDER_STEP, &payload, &[PROBE_STEP])?;
+                renditions.push(LADDER_STEP.to_string());
+            }
+        }
+
+        let renditions: Vec<&str> = renditions.iter().map(String::as_str).collect();
+        let payload = PublishStreamPayload {
+            video_id: video_id.to_string(),
+            manifest: ladder.settings.manifest,
+        };
+        let workflow = workflow.step("publish_stream", &payload, &renditions)?;
+
+        // Images come last so a video is playable as soon as possible
+        let images = GenerateImagesPayload {
+            video_id: video_id.to_string(),
+        };
+        workflow.step(GenerateImagesPayload::NAME, &images, &["publish_stream"])
+    }
+}
 
 impl Runner for HlsStreamRunner {
-    type Payload = VideoToStreamPayload;
+    type Job = VideoToStreamPayload;
 
-    /// Converts a raw video file to an HLS stream
-    async fn process_job(&self, payload: Self::Payload) -> Result<()> {
+    /// Starts the workflow that converts a raw video file to an HLS stream
+    async fn process_job(&self, context: &JobContext, payload: Self::Job) -> Result<()> {
         tracing::debug!(
             "Processing job with runner HlsStreamRunner for video ID {video_id}",
             video_id = payload.video_id,
         );
+        let video_id = payload.video_id;
+
+        Video::update_status(
+            &self.state.db,
+            video_id.clone(),
+            ProcessingStatus::Processing,
+        )
+        .await?;
 
-        // TODO: Implement video to HLS stream conversion
-        tracing::warn!("HLS stream conversion not implemented");
-        Ok(())
+        // The workflow's steps take it from here, the video stays processing until they finish
+        let ladder = self.ladder(&video_id).await?;
+        let workflow = Self::workflow(&video_id, ladder)?.started_by(context.id);
+        match self.state.job_queue.start_workflow(workflow).await {
+            Ok(workflow_id) => {
+                tracing::info!(
+                    "Started workflow {} to process video {}",
+                    workflow_id,
+                    video_id
+                );
+                Ok(())
+            }
+            Err(err) => {
+                tracing::error!("Failed to process video {}: {}", video_id, err);
+                // A retry picks up the same workflow, the video only fails once none are left
+                let err = anyhow::Error::from(err);
+                let retrying = JobError::is_retryable(&err)
+                    && Self::Job::RETRY_POLICY.should_retry(context.attempt);
+                if !retrying {
+                    Video::set_failed(
+                        &self.state.db,
+                        &video_id,
+                        "The video could not be processed",
+                        &err.to_string(),
+                    )
+                    .await?;
+                }
+                Err(err)
+            }
+        }
     }
 }
@@ -0,0 +1,23 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::RetryPolicy;
+use crate::event::{JOB_PREFIX, MESSAGE_PREFIX};
+
+/// A typed job that can be published to the queue
+pub trait Job: Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// The name of the job, used as the last token of its subject
+    const NAME: &'static str;
+    /// The version of the payload, bumped when older runners can't handle new payloads
+    const VERSION: u32 = 1;
+    /// How failed jobs of this type are retried
+    const RETRY_POLICY: RetryPolicy = RetryPolicy::DEFAULT;
+
+    /// The subject jobs of this type are published on
+    fn subject() -> String {
+        format!("{}.{}.{}", MESSAGE_PREFIX, JOB_PREFIX, Self::NAME)
+    }
+    /// The video the job is for, used to link the job record to the video
+    fn video_id(&self) -> Option<&str> {
+        None
+    }
+}
@@ -1,56 +1,49 @@
+pub mod archive_raw;
+pub mod backend;
+pub mod client;
+pub mod config;
+pub mod context;
+pub mod dead_letter;
+pub mod delete_assets;
+pub mod garbage_collect;
+pub mod generate_images;
 pub mod hls_stream;
-pub mod queue;
+pub mod job;
+pub mod probe_media;
+pub mod publish_stream;
+pub mod registry;
+pub mod retry;
+pub mod runner_state;
+pub mod transcode_audio;
+pub mod transcode_ladder;
+pub mod transcode_rendition;
+pub mod workflow;
 
-use anyhow::Result;
-use async_nats::Message;
-use hls_stream::HlsStreamRunner;
-pub use queue::Queue;
-use serde::de::DeserializeOwned;
-
-/// Creates the appropriate runner based on the subject, then runs it
-pub async fn process_message(message: &Message) -> Result<()> {
-    let subject = message.subject.as_str();
-    let runner = RunnerType::from_subject(subject)?;
-    runner.run(message).await
-}
+use std::future::Future;
 
-pub(crate) trait Runner: Send + Sync + 'static {
-    type Payload: DeserializeOwned;
+use anyhow::Result;
+pub use backend::{
+    Ack, Delivery, JobConsumer, JobQueue, NatsQueue, PostgresQueue, QueueBackend, ACK_WAIT,
+    MAX_DELIVER,
+};
+pub use client::Queue;
+pub use config::RunnerConfig;
+pub use context::JobContext;
+pub use dead_letter::DeadLetterQueue;
+pub use job::Job;
+pub use registry::RunnerRegistry;
+pub use retry::RetryPolicy;
+pub use runner_state::RunnerState;
+pub use workflow::{StepOutcome, Workflow};
 
-    /// Parses a payload from NATS into the expected format
-    async fn parse_payload(&self, message: &Message) -> Result<Self::Payload> {
-        let payload = message.payload.clone();
-        let payload = serde_json::from_slice::<Self::Payload>(&payload)?;
+/// Processes jobs of a single type, registered with a `RunnerRegistry`
+pub trait Runner: Send + Sync + 'static {
+    type Job: Job;
 
-        Ok(payload)
-    }
-    /// Parses the payload and runs the job
-    async fn run(&self, message: &Message) -> Result<()> {
-        let payload = self.parse_payload(message).await?;
-        self.process_job(payload).await
-    }
     /// Processes the job
-    async fn process_job(&self, payload: Self::Payload) -> Result<()>;
-}
-
-/// Represents the different types of runners that can be used in the application
-pub enum RunnerType {
-    TransformVideo(HlsStreamRunner),
-}
-
-impl RunnerType {
-    /// Creates a new runner from a subject
-    pub fn from_subject(subject: &str) -> Result<Self> {
-        tracing::debug!("Creating runner for subject: {}", subject);
-        match subject {
-            "farmhand.jobs.video_to_stream" => Ok(RunnerType::TransformVideo(HlsStreamRunner)),
-            _ => Err(anyhow::anyhow!("{} has no runner associated", subject)),
-        }
-    }
-    /// Method to run the appropriate runner
-    pub async fn run(&self, message: &Message) -> Result<()> {
-        match self {
-            RunnerType::TransformVideo(runner) => runner.run(message).await,
-        }
-    }
+    fn process_job(
+        &self,
+        context: &JobContext,
+        job: Self::Job,
+    ) -> impl Future<Output = Result<()>> + Send;
 }
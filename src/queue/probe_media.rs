@@ -0,0 +1,139 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{Job, JobContext, RetryPolicy, Runner, RunnerState};
+use crate::{
+    db::VideoMetadata,
+    error::{JobError, StorageError, VideoFormatError},
+    prelude::get_storage_dir,
+    vod::{probe::MediaInfo, stream::HLSConverter, Vod},
+};
+
+/// Name of the step probing the video in the workflows that convert it
+pub const PROBE_STEP: &str = "probe_media";
+
+/// How long ffprobe can read the raw video through its signed URL
+const PROBE_URL_EXPIRY: Duration = Duration::from_secs(15 * 60);
+
+#[derive(Serialize, Deserialize)]
+pub struct ProbeMediaPayload {
+    pub video_id: String,
+}
+
+impl Job for ProbeMediaPayload {
+    const NAME: &'static str = "probe_media";
+    const RETRY_POLICY: RetryPolicy =
+        RetryPolicy::new(3, Duration::from_secs(30), Duration::from_secs(5 * 60));
+
+    fn video_id(&self) -> Option<&str> {
+        Some(&self.video_id)
+    }
+}
+
+/// Gets what the probe step found in the video, missing for jobs that don't depend on it
+pub async fn probed_media(context: &JobContext) -> Result<Option<MediaInfo>> {
+    Ok(context
+        .dependency_outputs()
+        .await?
+        .remove(PROBE_STEP)
+        .flatten()
+        .and_then(|output| serde_json::from_value(output).ok()))
+}
+
+/// Gets what's known about the downloaded video, only probing it when the probe step didn't
+pub async fn media_info(
+    probed: Option<MediaInfo>,
+    converter: &HLSConverter,
+    raw_video_path: &Path,
+) -> Result<MediaInfo> {
+    match probed {
+        Some(info) => Ok(info),
+        // A file we can't convert won't get any better on another attempt
+        None => converter.probe_media(raw_video_path).await.map_err(|e| {
+            match e.is::<VideoFormatError>() {
+                true => JobError::fatal(e).into(),
+                false => e,
+            }
+        }),
+    }
+}
+
+pub struct ProbeMediaRunner {
+    state: Arc<RunnerState>,
+}
+
+impl ProbeMediaRunner {
+    pub fn new(state: Arc<RunnerState>) -> Self {
+        Self { state }
+    }
+    /// Reads the metadata of the raw video with ffprobe, without downloading it
+    /// ffprobe goes through a signed URL and only fetches the parts of the file it needs,
+    /// objects on the local filesystem are read where they are
+    async fn probe(&self, video_id: &str) -> Result<MediaInfo> {
+        let working_dir = PathBuf::from(get_storage_dir())
+            .join(video_id)
+            .join("probe");
+        tokio::fs::create_dir_all(&working_dir).await?;
+        let vod = Vod::by_id(&self.state.db, video_id.to_string(), working_dir.clone())
+            .await
+            .map_err(JobError::fatal_if_not_found)?;
+
+        let storage = &self.state.storage;
+        let key = &vod.video.raw_video_path;
+        let object = storage.head(key).await.map_err(|e| match e {
+            StorageError::NotFound(_) => {
+                JobError::fatal(anyhow!("Raw video for {} could not be found", video_id)).into()
+            }
+            e => anyhow::Error::from(e),
+        })?;
+        let probed = match storage.as_local() {
+            Some(local) => vod.converter.probe_media(&local.object_path(key)?).await,
+            None => {
+                let url = storage.presign_get(key, PROBE_URL_EXPIRY).await?;
+                vod.converter.probe_url(&url, object.size).await
+            }
+        };
+
+        // A file we can't convert won't get any better on another attempt
+        let probed = probed.map_err(|e| match e.is::<VideoFormatError>() {
+            true => JobError::fatal(e).into(),
+            false => e,
+        });
+
+        if let Err(e) = tokio::fs::remove_dir_all(&working_dir).await {
+            tracing::warn!("Could not clean up {:?}: {}", working_dir, e);
+        }
+        probed
+    }
+}
+
+impl Runner for ProbeMediaRunner {
+    type Job = ProbeMediaPayload;
+
+    /// Stores what ffprobe found in a raw video, handing it to the steps that convert it
+    async fn process_job(&self, context: &JobContext, payload: Self::Job) -> Result<()> {
+        tracing::debug!(
+            "Processing job with runner ProbeMediaRunner for video ID {video_id}",
+            video_id = payload.video_id,
+        );
+
+        match self.probe(&payload.video_id).await {
+            Ok(info) => {
+                VideoMetadata::upsert(&self.state.db, &payload.video_id, &info).await?;
+                context.set_output(&info)?;
+                Ok(())
+            }
+            Err(_) if context.is_cancelled() => Err(JobError::Cancelled.into()),
+            Err(err) => {
+                tracing::error!("Failed to probe video {}: {}", payload.video_id, err);
+                Err(err)
+            }
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    archive_raw::{ArchiveRawPayload, ARCHIVE_DELAY},
+    transcode_audio::AUDIO_STEP,
+    transcode_ladder::LADDER_STEP,
+    Job, JobContext, Runner, RunnerState,
+};
+use crate::{
+    db::Video,
+    error::JobError,
+    storage::ObjectHeaders,
+    vod::{
+        ladder::ManifestType,
+        manifest::{
+            dash_manifest, master_playlist, AudioRendition, VideoRendition, DASH_MANIFEST,
+            MASTER_PLAYLIST,
+        },
+        Vod,
+    },
+};
+
+#[derive(Serialize, Deserialize)]
+pub struct PublishStreamPayload {
+    pub video_id: String,
+    /// Which manifests point at the renditions
+    #[serde(default)]
+    pub manifest: ManifestType,
+}
+
+/// Where the manifests of a published stream were uploaded to
+struct PublishedManifests {
+    master_playlist: String,
+    dash_manifest: Option<String>,
+}
+
+impl Job for PublishStreamPayload {
+    const NAME: &'static str = "publish_stream";
+
+    fn video_id(&self) -> Option<&str> {
+        Some(&self.video_id)
+    }
+}
+
+pub struct PublishStreamRunner {
+    state: Arc<RunnerState>,
+}
+
+impl PublishStreamRunner {
+    pub fn new(state: Arc<RunnerState>) -> Self {
+        Self { state }
+    }
+    /// Queues up archiving the raw video now that it has been processed
+    async fn queue_archive(&self, video_id: String) {
+        let payload = ArchiveRawPayload {
+            video_id: video_id.clone(),
+        };
+        match self
+            .state
+            .job_queue
+            .enqueue_after(&payload, ARCHIVE_DELAY)
+            .await
+        {
+            Ok(_) => tracing::info!("Scheduled archive job for video {}", video_id),
+            Err(e) => tracing::error!("Could not schedule archive job for {}: {}", video_id, e),
+        }
+    }
+    /// Uploads a manifest beside the renditions, returning its remote key
+    async fn upload(&self, vod: &Vod, name: &str, body: String) -> Result<String> {
+        let key = format!("{}/{}", vod.get_remote_storage_prefix(), name);
+        self.state
+            .storage
+            .put(&key, body.into(), ObjectHeaders::for_key(&key))
+            .await
+            .map_err(|e| anyhow!("Could not upload manifest {}: {}", key, e))?;
+        Ok(key)
+    }
+    /// Writes the manifests for every rendition the workflow produced
+    async fn publish(
+        &self,
+        context: &JobContext,
+        payload: &PublishStreamPayload,
+    ) -> Result<PublishedManifests> {
+        let video_id = payload.video_id.as_str();
+        // Renditions the source was too small for and silent videos don't produce anything
+        let mut videos = Vec::new();
+        let mut audio = None;
+        for (step, output) in context.dependency_outputs().await? {
+            let Some(output) = output else {
+                continue;
+            };
+            if step == AUDIO_STEP {
+                audio = serde_json::from_value::<Option<AudioRendition>>(output)?;
+            } else if step == LADDER_STEP {
+                videos.extend(serde_json::from_value::<Vec<VideoRendition>>(output)?);
+            } else if let Some(video) = serde_json::from_value::<Option<VideoRendition>>(output)? {
+                videos.push(video);
+            }
+        }
+        if videos.is_empty() {
+            return Err(JobError::fatal(anyhow!(
+                "No quality levels could be produced for video {}",
+                video_id
+            ))
+            .into());
+        }
+        // Players list the highest quality first
+        videos.sort_by(|a, b| b.quality.height.cmp(&a.quality.height));
+
+        let vod = Vod::by_id(&self.state.db, video_id.to_string(), std::env::temp_dir())
+            .await
+            .map_err(JobError::fatal_if_not_found)?;
+        // The DASH manifest goes up first, so the video never points at a missing one
+        let dash_manifest = match payload.manifest {
+            ManifestType::Cmaf => Some(
+                self.upload(&vod, DASH_MANIFEST, dash_manifest(&videos, audio.as_ref()))
+                    .await?,
+            ),
+            ManifestType::Hls => None,
+        };
+        let master_playlist = self
+            .upload(
+                &vod,
+                MASTER_PLAYLIST,
+                master_playlist(&videos, audio.as_ref(), payload.manifest),
+            )
+            .await?;
+
+        Ok(PublishedManifests {
+            master_playlist,
+            dash_manifest,
+        })
+    }
+}
+
+impl Runner for PublishStreamRunner {
+    type Job = PublishStreamPayload;
+
+    /// Publishes the HLS stream once every rendition has been converted
+    async fn process_job(&self, context: &JobContext, payload: Self::Job) -> Result<()> {
+        tracing::debug!(
+            "Processing job with runner PublishStreamRunner for video ID {video_id}",
+            video_id = payload.video_id,
+        );
+        let result = self.publish(context, &payload).await;
+        let video_id = payload.video_id;
+
+        match result {
+            Ok(manifests) => {
+                Video::set_processed(
+                    &self.state.db,
+                    &video_id,
+                    &manifests.master_playlist,
+                    manifests.dash_manifest.as_deref(),
+                )
+                .await?;
+                tracing::info!("Successfully processed video {}", video_id);
+                self.queue_archive(video_id).await;
+                Ok(())
+            }
+            Err(err) => {
+                tracing::error!("Failed to publish video {}: {}", video_id, err);
+                Err(err)
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+
+use super::{Delivery, Job, JobContext, Queue, RetryPolicy, Runner};
+use crate::error::JobError;
+
+/// A runner with its job type erased so different runners can be stored together
+#[async_trait]
+trait RegisteredRunner: Send + Sync {
+    /// Parses the payload and runs the job
+    async fn run(&self, delivery: &Delivery, context: &JobContext) -> Result<()>;
+    /// Gets how failed jobs are retried
+    fn retry_policy(&self) -> RetryPolicy;
+}
+
+#[async_trait]
+impl<R: Runner> RegisteredRunner for R {
+    async fn run(&self, delivery: &Delivery, context: &JobContext) -> Result<()> {
+        // Jobs published without a version, like dead letter replays, skip the check
+        if let Some(version) = delivery
+            .version
+            .filter(|version| *version > R::Job::VERSION)
+        {
+            return Err(JobError::fatal(anyhow!(
+                "{} job version {} is newer than the runner supports ({})",
+                R::Job::NAME,
+                version,
+                R::Job::VERSION
+            ))
+            .into());
+        }
+
+        // A payload that doesn't parse now never will
+        let job = serde_json::from_slice::<R::Job>(&delivery.payload).map_err(JobError::fatal)?;
+        self.process_job(context, job).await
+    }
+    fn retry_policy(&self) -> RetryPolicy {
+        R::Job::RETRY_POLICY
+    }
+}
+
+/// Maps job subjects to the runners that process them
+#[derive(Default)]
+pub struct RunnerRegistry {
+    runners: HashMap<String, Box<dyn RegisteredRunner>>,
+}
+
+impl RunnerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Registers a runner for its job's subject
+    pub fn register<R: Runner>(mut self, runner: R) -> Self {
+        let subject = R::Job::subject();
+        tracing::debug!("Registering runner for subject: {}", subject);
+        if self
+            .runners
+            .insert(subject.clone(), Box::new(runner))
+            .is_some()
+        {
+            tracing::warn!("Replaced existing runner for subject: {}", subject);
+        }
+        self
+    }
+    /// Gets every subject that has a runner registered
+    pub fn subjects(&self) -> Vec<String> {
+        self.runners.keys().cloned().collect()
+    }
+    /// Finds the runner for the job's subject, then runs it
+    pub async fn process(&self, delivery: &Delivery, context: &JobContext) -> Result<()> {
+        let subject = delivery.subject.as_str();
+        let runner = self
+            .runners
+            .get(subject)
+            .ok_or_else(|| JobError::fatal(anyhow!("{} has no runner associated", subject)))?;
+        runner.run(delivery, context).await
+    }
+    /// Gets how failed jobs on the subject are retried
+    pub fn retry_policy(&self, subject: &str) -> RetryPolicy {
+        self.runners
+            .get(subject)
+            .map(|runner| runner.retry_policy())
+            .unwrap_or_default()
+    }
+    /// Makes sure every subject with jobs waiting in the queue has a runner registered
+    pub async fn verify(&self, queue: &Queue) -> Result<()> {
+        let missing: Vec<String> = queue
+            .pending_subjects()
+            .await?
+            .into_iter()
+            .filter(|subject| !self.runners.contains_key(subject))
+            .collect();
+
+        if !missing.is_empty() {
+            return Err(anyhow!(
+                "No runner registered for subjects: {}",
+                missing.join(", ")
+            ));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use super::MAX_DELIVER;
+
+/// How many times a job is attempted and how long to wait between attempts
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// The most times the job is attempted, capped at `MAX_DELIVER`
+    pub max_attempts: i64,
+    /// How long to wait before the first retry, doubled for every retry after it
+    pub initial_delay: Duration,
+    /// The longest to wait between two attempts
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// The policy used by jobs that don't set their own
+    pub const DEFAULT: Self = Self::new(3, Duration::from_secs(30), Duration::from_secs(30 * 60));
+
+    pub const fn new(max_attempts: i64, initial_delay: Duration, max_delay: Duration) -> Self {
+        Self {
+            max_attempts,
+            initial_delay,
+            max_delay,
+        }
+    }
+    /// Gets the most times a job is attempted, never more than the consumer delivers it
+    pub fn max_attempts(&self) -> i64 {
+        self.max_attempts.clamp(1, MAX_DELIVER)
+    }
+    /// Checks whether a job that failed on the given attempt gets another one
+    pub fn should_retry(&self, attempt: i64) -> bool {
+        attempt < self.max_attempts()
+    }
+    /// Gets how long to wait before retrying a job that failed on the given attempt
+    pub fn delay(&self, attempt: i64) -> Duration {
+        let exponent = attempt.saturating_sub(1).clamp(0, 31) as u32;
+        self.initial_delay
+            .saturating_mul(2u32.saturating_pow(exponent))
+            .min(self.max_delay)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
@@ -0,0 +1,46 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_nats::Client;
+
+use super::{DeadLetterQueue, Queue};
+use crate::{
+    db::{connect_to_database, DBPool},
+    storage::{self, Storage},
+};
+
+/// Shared state available to the job runners
+pub struct RunnerState {
+    pub db: DBPool,
+    pub job_queue: Queue,
+    /// Only available when NATS is, failed jobs are still kept as failed in the database
+    pub dead_letters: Option<DeadLetterQueue>,
+    /// Where raw uploads and processed videos live
+    pub storage: Arc<dyn Storage>,
+}
+
+impl RunnerState {
+    pub async fn new(nats_client: Option<Client>) -> Result<Self> {
+        // Initialize a connection to the database
+        let db = connect_to_database().await?;
+
+        // Connect to the job queue so runners can publish follow-up jobs
+        let job_queue = Queue::connect(nats_client.clone(), db.clone()).await?;
+
+        // Connect to the dead letter queue for jobs that exhaust their attempts
+        let dead_letters = match nats_client {
+            Some(nats_client) => Some(DeadLetterQueue::connect(nats_client).await?),
+            None => None,
+        };
+
+        // Connect to the storage that raw uploads and processed videos live in
+        let storage = storage::connect().await?;
+
+        Ok(Self {
+            db,
+            job_queue,
+            dead_letters,
+            storage,
+        })
+    }
+}
@@ -0,0 +1,165 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    probe_media::{media_info, probed_media},
+    transcode_rendition::TRANSCODE_TIMEOUT,
+    Job, JobContext, RetryPolicy, Runner, RunnerState,
+};
+use crate::{
+    error::{JobError, VideoFormatError},
+    prelude::get_storage_dir,
+    storage::{sync_directory, SyncOptions, SyncReport},
+    vod::{
+        ladder::{EncodingSettings, AUDIO_RENDITION},
+        manifest::AudioRendition,
+        DownloadSettings, Vod,
+    },
+};
+
+/// Name of the workflow step that converts the audio, no video quality can be named audio
+pub const AUDIO_STEP: &str = "transcode_audio";
+
+#[derive(Serialize, Deserialize)]
+pub struct TranscodeAudioPayload {
+    pub video_id: String,
+    /// How the audio is packaged, matching the video renditions
+    pub settings: EncodingSettings,
+}
+
+impl Job for TranscodeAudioPayload {
+    const NAME: &'static str = "transcode_audio";
+    const RETRY_POLICY: RetryPolicy =
+        RetryPolicy::new(3, Duration::from_secs(60), Duration::from_secs(15 * 60));
+
+    fn video_id(&self) -> Option<&str> {
+        Some(&self.video_id)
+    }
+}
+
+pub struct TranscodeAudioRunner {
+    state: Arc<RunnerState>,
+}
+
+impl TranscodeAudioRunner {
+    pub fn new(state: Arc<RunnerState>) -> Self {
+        Self { state }
+    }
+    /// Downloads the raw video, converts its audio and uploads the stream files
+    /// Returns None when the video has no audio
+    async fn transcode(
+        &self,
+        context: &JobContext,
+        payload: &TranscodeAudioPayload,
+    ) -> Result<Option<AudioRendition>> {
+        let video_id = payload.video_id.as_str();
+        // Skip the download when the probe step already found there's nothing to convert
+        let probed = probed_media(context).await?;
+        if probed
+            .as_ref()
+            .is_some_and(|info| info.audio_codec.is_none())
+        {
+            tracing::info!("Video {} has no audio to convert", video_id);
+            return Ok(None);
+        }
+
+        // Runs beside the video renditions, so it gets its own directory
+        let working_dir = PathBuf::from(get_storage_dir())
+            .join(video_id)
+            .join("renditions")
+            .join(AUDIO_RENDITION);
+        tokio::fs::create_dir_all(&working_dir).await?;
+        let vod = Vod::by_id(&self.state.db, video_id.to_string(), working_dir.clone())
+            .await
+            .map_err(JobError::fatal_if_not_found)?;
+
+        let download_settings = DownloadSettings {
+            storage: &*self.state.storage,
+        };
+        let raw_video_path = vod
+            .get_raw_video(working_dir.clone(), Some(download_settings))
+            .await?
+            .ok_or_else(|| {
+                JobError::fatal(anyhow!("Raw video for {} could not be found", video_id))
+            })?;
+
+        let converter = vod
+            .converter
+            .clone()
+            .with_timeout(TRANSCODE_TIMEOUT)
+            .with_settings(payload.settings.clone());
+        let info = media_info(probed, &converter, &raw_video_path).await?;
+        let progress = context.progress_reporter();
+        let rendition = converter
+            .convert_audio(
+                &raw_video_path,
+                &info,
+                |percent| {
+                    let _ = progress.send(percent);
+                },
+                &context.cancellation(),
+            )
+            .await
+            .map_err(
+                |e| match (context.is_cancelled(), e.is::<VideoFormatError>()) {
+                    (true, _) => JobError::Cancelled.into(),
+                    // A file we can't convert won't get any better on another attempt
+                    (false, true) => JobError::fatal(e).into(),
+                    (false, false) => e,
+                },
+            )?;
+
+        if rendition.is_some() {
+            let remote_prefix = format!("{}/{}", vod.get_remote_storage_prefix(), AUDIO_RENDITION);
+            sync_directory(
+                &*self.state.storage,
+                working_dir.join(AUDIO_RENDITION),
+                &remote_prefix,
+                &SyncOptions::default(),
+            )
+            .await
+            .and_then(SyncReport::into_result)
+            .map_err(|e| anyhow!("Could not sync audio stream files to storage: {}", e))?;
+        }
+
+        // Everything we need is in storage now, so clean up the working directory
+        if let Err(e) = tokio::fs::remove_dir_all(&working_dir).await {
+            tracing::warn!("Could not clean up {:?}: {}", working_dir, e);
+        }
+
+        Ok(rendition)
+    }
+}
+
+impl Runner for TranscodeAudioRunner {
+    type Job = TranscodeAudioPayload;
+
+    /// Converts the audio of a raw video file to an HLS stream every quality shares
+    async fn process_job(&self, context: &JobContext, payload: Self::Job) -> Result<()> {
+        tracing::debug!(
+            "Processing job with runner TranscodeAudioRunner for video ID {video_id}",
+            video_id = payload.video_id,
+        );
+
+        match self.transcode(context, &payload).await {
+            Ok(rendition) => {
+                context.set_output(&rendition)?;
+                Ok(())
+            }
+            Err(_) if context.is_cancelled() => {
+                tracing::info!("Cancelled transcoding audio of video {}", payload.video_id);
+                Err(JobError::Cancelled.into())
+            }
+            Err(err) => {
+                tracing::error!(
+                    "Failed to transcode audio of video {}: {}",
+                    payload.video_id,
+                    err
+                );
+                Err(err)
+            }
+        }
+    }
+}
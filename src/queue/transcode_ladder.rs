@@ -0,0 +1,157 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    probe_media::{media_info, probed_media},
+    transcode_rendition::TRANSCODE_TIMEOUT,
+    Job, JobContext, RetryPolicy, Runner, RunnerState,
+};
+use crate::{
+    error::{JobError, VideoFormatError},
+    prelude::get_storage_dir,
+    storage::{sync_directory, SyncOptions, SyncReport},
+    vod::{
+        ladder::EncodingSettings, manifest::VideoRendition, stream::Quality, DownloadSettings, Vod,
+    },
+};
+
+/// Name of the workflow step that converts every quality in a single pass
+pub const LADDER_STEP: &str = "transcode_ladder";
+
+#[derive(Serialize, Deserialize)]
+pub struct TranscodeLadderPayload {
+    pub video_id: String,
+    pub qualities: Vec<Quality>,
+    pub settings: EncodingSettings,
+}
+
+impl Job for TranscodeLadderPayload {
+    const NAME: &'static str = "transcode_ladder";
+    // Conversions are expensive, so don't retry them too often
+    const RETRY_POLICY: RetryPolicy =
+        RetryPolicy::new(3, Duration::from_secs(60), Duration::from_secs(15 * 60));
+
+    fn video_id(&self) -> Option<&str> {
+        Some(&self.video_id)
+    }
+}
+
+pub struct TranscodeLadderRunner {
+    state: Arc<RunnerState>,
+}
+
+impl TranscodeLadderRunner {
+    pub fn new(state: Arc<RunnerState>) -> Self {
+        Self { state }
+    }
+    /// Downloads the raw video, converts it to every quality at once and uploads the stream files
+    /// Returns the renditions, leaving out the qualities the source is too small for
+    async fn transcode(
+        &self,
+        context: &JobContext,
+        payload: &TranscodeLadderPayload,
+    ) -> Result<Vec<VideoRendition>> {
+        let video_id = payload.video_id.as_str();
+        let working_dir = PathBuf::from(get_storage_dir())
+            .join(video_id)
+            .join("renditions")
+            .join("ladder");
+        tokio::fs::create_dir_all(&working_dir).await?;
+        let vod = Vod::by_id(&self.state.db, video_id.to_string(), working_dir.clone())
+            .await
+            .map_err(JobError::fatal_if_not_found)?;
+
+        // Download the raw video if it isn't available locally
+        let download_settings = DownloadSettings {
+            storage: &*self.state.storage,
+        };
+        let raw_video_path = vod
+            .get_raw_video(working_dir.clone(), Some(download_settings))
+            .await?
+            .ok_or_else(|| {
+                JobError::fatal(anyhow!("Raw video for {} could not be found", video_id))
+            })?;
+
+        // One ffmpeg does the work of every quality, so it gets their time between them
+        let timeout = TRANSCODE_TIMEOUT * payload.qualities.len().max(1) as u32;
+        let converter = vod
+            .converter
+            .clone()
+            .with_timeout(timeout)
+            .with_settings(payload.settings.clone());
+        // The probe step already read the source, only probe it here without it
+        let probed = probed_media(context).await?;
+        let info = media_info(probed, &converter, &raw_video_path).await?;
+        let progress = context.progress_reporter();
+        let renditions = converter
+            .convert_to_hls(
+                &raw_video_path,
+                &info,
+                payload.qualities.clone(),
+                |percent| {
+                    let _ = progress.send(percent);
+                },
+                &context.cancellation(),
+            )
+            .await
+            .map_err(
+                |e| match (context.is_cancelled(), e.is::<VideoFormatError>()) {
+                    (true, _) => JobError::Cancelled.into(),
+                    // A file we can't convert won't get any better on another attempt
+                    (false, true) => JobError::fatal(e).into(),
+                    (false, false) => e,
+                },
+            )?;
+
+        // The master playlist is written once the audio is done too
+        for rendition in &renditions {
+            let name = &rendition.quality.name;
+            let remote_prefix = format!("{}/{}", vod.get_remote_storage_prefix(), name);
+            sync_directory(
+                &*self.state.storage,
+                working_dir.join(name),
+                &remote_prefix,
+                &SyncOptions::default(),
+            )
+            .await
+            .and_then(SyncReport::into_result)
+            .map_err(|e| anyhow!("Could not sync {} stream files to storage: {}", name, e))?;
+        }
+
+        // Everything we need is in storage now, so clean up the working directory
+        if let Err(e) = tokio::fs::remove_dir_all(&working_dir).await {
+            tracing::warn!("Could not clean up {:?}: {}", working_dir, e);
+        }
+
+        Ok(renditions)
+    }
+}
+
+impl Runner for TranscodeLadderRunner {
+    type Job = TranscodeLadderPayload;
+
+    /// Converts a raw video file to the HLS stream of every quality with a single ffmpeg
+    async fn process_job(&self, context: &JobContext, payload: Self::Job) -> Result<()> {
+        tracing::debug!(
+            "Processing job with runner TranscodeLadderRunner for video ID {video_id}",
+            video_id = payload.video_id,
+        );
+
+        match self.transcode(context, &payload).await {
+            Ok(renditions) => {
+                context.set_output(&renditions)?;
+                Ok(())
+            }
+            Err(_) if context.is_cancelled() => {
+                tracing::info!("Cancelled transcoding video {}", payload.video_id);
+                Err(JobError::Cancelled.into())
+            }
+            Err(err) => {
+                tracing::error!("Failed to transcode video {}: {}", payload.video_id, err);
+                Err(err)
+            }
+        }
+    }
+}
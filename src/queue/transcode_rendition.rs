@@ -0,0 +1,180 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    probe_media::{media_info, probed_media},
+    Job, JobContext, RetryPolicy, Runner, RunnerState,
+};
+use crate::{
+    error::JobError,
+    prelude::get_storage_dir,
+    storage::{sync_directory, SyncOptions, SyncReport},
+    vod::{
+        ladder::EncodingSettings, manifest::VideoRendition, stream::Quality, DownloadSettings, Vod,
+    },
+};
+
+/// How long converting a single quality can take before ffmpeg is assumed to be stuck
+pub const TRANSCODE_TIMEOUT: Duration = Duration::from_secs(6 * 60 * 60);
+
+#[derive(Serialize, Deserialize)]
+pub struct TranscodeRenditionPayload {
+    pub video_id: String,
+    pub quality: Quality,
+    /// How the quality is encoded, jobs queued before profiles existed use the defaults
+    #[serde(default)]
+    pub settings: EncodingSettings,
+}
+
+impl Job for TranscodeRenditionPayload {
+    const NAME: &'static str = "transcode_rendition";
+    // Conversions are expensive, so don't retry them too often
+    const RETRY_POLICY: RetryPolicy =
+        RetryPolicy::new(3, Duration::from_secs(60), Duration::from_secs(15 * 60));
+
+    fn video_id(&self) -> Option<&str> {
+        Some(&self.video_id)
+    }
+}
+
+pub struct TranscodeRenditionRunner {
+    state: Arc<RunnerState>,
+}
+
+impl TranscodeRenditionRunner {
+    pub fn new(state: Arc<RunnerState>) -> Self {
+        Self { state }
+    }
+    /// Downloads the raw video, converts it to a single quality and uploads the stream files
+    /// Returns the converted rendition, or None when the source is too small to produce it
+    async fn transcode(
+        &self,
+        context: &JobContext,
+        payload: &TranscodeRenditionPayload,
+    ) -> Result<Option<VideoRendition>> {
+        let video_id = payload.video_id.as_str();
+        let quality = payload.quality.clone();
+        // Renditions of the same video can run side by side, so each gets its own directory
+        let working_dir = PathBuf::from(get_storage_dir())
+            .join(video_id)
+            .join("renditions")
+            .join(&quality.name);
+        tokio::fs::create_dir_all(&working_dir).await?;
+        let vod = Vod::by_id(&self.state.db, video_id.to_string(), working_dir.clone())
+            .await
+            .map_err(JobError::fatal_if_not_found)?;
+
+        // Download the raw video if it isn't available locally
+        let download_settings = DownloadSettings {
+            storage: &*self.state.storage,
+        };
+        let raw_video_path = vod
+            .get_raw_video(working_dir.clone(), Some(download_settings))
+            .await?
+            .ok_or_else(|| {
+                JobError::fatal(anyhow!("Raw video for {} could not be found", video_id))
+            })?;
+
+        let converter = vod
+            .converter
+            .clone()
+            .with_timeout(TRANSCODE_TIMEOUT)
+            .with_settings(payload.settings.clone());
+        // The probe step already read the source, only probe it here without it
+        let probed = probed_media(context).await?;
+        let info = media_info(probed, &converter, &raw_video_path).await?;
+        let (width, height) = (info.width as u32, info.height as u32);
+        let mut rendition = None;
+        if quality.width <= width && quality.height <= height {
+            let progress = context.progress_reporter();
+            let renditions = converter
+                .convert_to_hls(
+                    &raw_video_path,
+                    &info,
+                    vec![quality.clone()],
+                    |percent| {
+                        let _ = progress.send(percent);
+                    },
+                    &context.cancellation(),
+                )
+                .await
+                .map_err(|e| match context.is_cancelled() {
+                    true => JobError::Cancelled.into(),
+                    false => e,
+                })?;
+            rendition = renditions.into_iter().next();
+        }
+
+        if rendition.is_some() {
+            // Only upload this quality, the master playlist is written once every rendition is done
+            let remote_prefix = format!("{}/{}", vod.get_remote_storage_prefix(), quality.name);
+            sync_directory(
+                &*self.state.storage,
+                working_dir.join(&quality.name),
+                &remote_prefix,
+                &SyncOptions::default(),
+            )
+            .await
+            .and_then(SyncReport::into_result)
+            .map_err(|e| {
+                anyhow!(
+                    "Could not sync {} stream files to storage: {}",
+                    quality.name,
+                    e
+                )
+            })?;
+        } else {
+            tracing::warn!(
+                "Skipping quality {} as it exceeds the resolution of video {}",
+                quality.name,
+                video_id
+            );
+        }
+
+        // Everything we need is in storage now, so clean up the working directory
+        if let Err(e) = tokio::fs::remove_dir_all(&working_dir).await {
+            tracing::warn!("Could not clean up {:?}: {}", working_dir, e);
+        }
+
+        Ok(rendition)
+    }
+}
+
+impl Runner for TranscodeRenditionRunner {
+    type Job = TranscodeRenditionPayload;
+
+    /// Converts a raw video file to the HLS stream of a single quality
+    async fn process_job(&self, context: &JobContext, payload: Self::Job) -> Result<()> {
+        tracing::debug!(
+            "Processing job with runner TranscodeRenditionRunner for video ID {video_id} at {quality}",
+            video_id = payload.video_id,
+            quality = payload.quality.name,
+        );
+
+        match self.transcode(context, &payload).await {
+            Ok(rendition) => {
+                context.set_output(&rendition)?;
+                Ok(())
+            }
+            Err(_) if context.is_cancelled() => {
+                tracing::info!(
+                    "Cancelled transcoding video {} to {}",
+                    payload.video_id,
+                    payload.quality.name
+                );
+                Err(JobError::Cancelled.into())
+            }
+            Err(err) => {
+                tracing::error!(
+                    "Failed to transcode video {} to {}: {}",
+                    payload.video_id,
+                    payload.quality.name,
+                    err
+                );
+                Err(err)
+            }
+        }
+    }
+}
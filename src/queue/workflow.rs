@@ -0,0 +1,315 @@
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::{Job, Queue};
+use crate::{
+    db::{
+        workflows::NewWorkflowStep, JobRecord, NewJob, ProcessingStatus, Video, WorkflowRecord,
+        WorkflowStatus, WorkflowStepRecord, WorkflowStepStatus,
+    },
+    error::QueueError,
+};
+
+/// A set of typed jobs that run in order of their dependencies
+/// Steps without dependencies start right away, steps that share a dependency fan out from it,
+/// and a step that depends on several others waits for all of them to complete
+pub struct Workflow {
+    name: String,
+    video_id: Option<String>,
+    job_id: Option<Uuid>,
+    steps: Vec<WorkflowStep>,
+}
+
+struct WorkflowStep {
+    name: String,
+    subject: String,
+    payload: Value,
+    version: u32,
+    depends_on: Vec<String>,
+}
+
+/// How the job running a workflow step finished
+pub enum StepOutcome {
+    /// The step completed, with whatever it produced for the steps after it
+    Completed(Option<Value>),
+    /// The step failed with no more attempts coming, which fails the workflow
+    Failed(String),
+    /// The step was cancelled, which cancels the workflow
+    Cancelled,
+}
+
+impl Workflow {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            video_id: None,
+            job_id: None,
+            steps: Vec::new(),
+        }
+    }
+    /// Ties the workflow and the jobs it runs to a video
+    pub fn for_video(mut self, video_id: impl Into<String>) -> Self {
+        self.video_id = Some(video_id.into());
+        self
+    }
+    /// Records the job starting the workflow, so starting it again from a retry of that job
+    /// picks up the existing workflow instead of creating another one
+    pub fn started_by(mut self, job_id: Option<Uuid>) -> Self {
+        self.job_id = job_id;
+        self
+    }
+    /// Adds a step that runs the job once every step in depends_on has completed
+    /// Steps can only depend on steps added before them, which keeps workflows free of cycles
+    pub fn step<J: Job>(
+        mut self,
+        name: impl Into<String>,
+        job: &J,
+        depends_on: &[&str],
+    ) -> Result<Self, QueueError> {
+        let name = name.into();
+        if self.steps.iter().any(|step| step.name == name) {
+            return Err(QueueError::InvalidWorkflow(format!(
+                "{} has more than one step named {}",
+                self.name, name
+            )));
+        }
+        if let Some(missing) = depends_on
+            .iter()
+            .find(|dependency| !self.steps.iter().any(|step| step.name == **dependency))
+        {
+            return Err(QueueError::InvalidWorkflow(format!(
+                "Step {} depends on {}, which isn't an earlier step of {}",
+                name, missing, self.name
+            )));
+        }
+
+        let payload =
+            serde_json::to_value(job).map_err(|e| QueueError::InvalidPayload(e.to_string()))?;
+        self.steps.push(WorkflowStep {
+            name,
+            subject: J::subject(),
+            payload,
+            version: J::VERSION,
+            depends_on: depends_on.iter().map(|name| name.to_string()).collect(),
+        });
+        Ok(self)
+    }
+}
+
+impl Queue {
+    /// Saves the workflow and publishes the steps that don't depend on anything
+    /// Returns the ID of the workflow
+    pub async fn start_workflow(&self, workflow: Workflow) -> Result<Uuid, QueueError> {
+        if workflow.steps.is_empty() {
+            return Err(QueueError::InvalidWorkflow(format!(
+                "{} has no steps",
+                workflow.name
+            )));
+        }
+        if let Some(job_id) = workflow.job_id {
+            if let Some(existing) = WorkflowRecord::by_job_id(&self.db, job_id).await? {
+                tracing::info!(
+                    "Job {} already started workflow {} ({})",
+                    job_id,
+                    existing.name,
+                    existing.id
+                );
+                if existing.status == WorkflowStatus::Running {
+                    self.advance_workflow(existing.id).await?;
+                }
+                return Ok(existing.id);
+            }
+        }
+
+        let id = Uuid::new_v4();
+        let mut tx = self.db.begin().await?;
+        WorkflowRecord::create(
+            &mut tx,
+            id,
+            &workflow.name,
+            workflow.video_id.as_deref(),
+            workflow.job_id,
+        )
+        .await?;
+        for step in &workflow.steps {
+            let new_step = NewWorkflowStep {
+                id: Uuid::new_v4(),
+                workflow_id: id,
+                name: &step.name,
+                subject: &step.subject,
+                payload: &step.payload,
+                version: Some(step.version as i32),
+                depends_on: &step.depends_on,
+            };
+            WorkflowStepRecord::create(&mut tx, &new_step).await?;
+        }
+        tx.commit().await?;
+        tracing::info!(
+            "Started workflow {} ({}) with {} steps",
+            workflow.name,
+            id,
+            workflow.steps.len()
+        );
+
+        self.advance_workflow(id).await?;
+        Ok(id)
+    }
+    /// Records how the job running a workflow step finished, then moves the workflow along
+    /// Jobs that aren't part of a workflow are ignored
+    pub async fn finish_step(&self, job_id: Uuid, outcome: StepOutcome) -> Result<(), QueueError> {
+        let Some(step) = WorkflowStepRecord::by_job_id(&self.db, job_id).await? else {
+            return Ok(());
+        };
+
+        match outcome {
+            StepOutcome::Completed(output) => {
+                WorkflowStepRecord::mark_completed(&self.db, step.id, output.as_ref()).await?;
+                tracing::debug!("Workflow {} step {} completed", step.workflow_id, step.name);
+                // Nothing retries the completed step, so a workflow that can't move on fails
+                if let Err(e) = self.advance_workflow(step.workflow_id).await {
+                    let error = format!("Could not continue after step {}: {}", step.name, e);
+                    self.end_workflow(step.workflow_id, WorkflowStatus::Failed, Some(&error))
+                        .await?;
+                    return Err(e);
+                }
+                Ok(())
+            }
+            StepOutcome::Failed(error) => {
+                let error = format!("Step {} failed: {}", step.name, error);
+                self.stop_workflow(&step, WorkflowStatus::Failed, Some(&error))
+                    .await
+            }
+            StepOutcome::Cancelled => {
+                self.stop_workflow(&step, WorkflowStatus::Cancelled, None)
+                    .await
+            }
+        }
+    }
+    /// Publishes every step that's ready to run, completing the workflow once nothing is left
+    /// Steps that couldn't be published go back to pending for the next call to pick up
+    async fn advance_workflow(&self, workflow_id: Uuid) -> Result<(), QueueError> {
+        let ready = WorkflowStepRecord::claim_ready(&self.db, workflow_id).await?;
+        if ready.is_empty() {
+            if WorkflowStepRecord::incomplete_count(&self.db, workflow_id).await? == 0 {
+                WorkflowRecord::finish(&self.db, workflow_id, WorkflowStatus::Completed, None)
+                    .await?;
+                tracing::info!("Workflow {} completed", workflow_id);
+            }
+            return Ok(());
+        }
+
+        let workflow = WorkflowRecord::by_id(&self.db, workflow_id).await?;
+        for (index, step) in ready.iter().enumerate() {
+            if let Err(e) = self.publish_step(step, workflow.video_id.as_deref()).await {
+                tracing::error!(
+                    "Workflow {}: could not publish step {}: {}",
+                    workflow_id,
+                    step.name,
+                    e
+                );
+                let unpublished: Vec<Uuid> = ready[index..].iter().map(|step| step.id).collect();
+                WorkflowStepRecord::release(&self.db, &unpublished).await?;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+    /// Publishes the job for a claimed step, returning the ID of the job
+    async fn publish_step(
+        &self,
+        step: &WorkflowStepRecord,
+        video_id: Option<&str>,
+    ) -> Result<Uuid, QueueError> {
+        let id = Uuid::new_v4();
+        let new_job = NewJob {
+            id,
+            subject: &step.subject,
+            payload: &step.payload,
+            video_id,
+            version: step.version,
+            dedup_key: None,
+        };
+        JobRecord::create(&self.db, &new_job).await?;
+        WorkflowStepRecord::set_job(&self.db, step.id, id).await?;
+
+        // A step that couldn't be published gets a new job next time, so the job ID is the message ID
+        let version = step.version.map(|version| version as u32);
+        let message_id = id.to_string();
+        if let Err(e) = self
+            .send(
+                id,
+                &step.subject,
+                &step.payload.to_string(),
+                version,
+                Some(&message_id),
+            )
+            .await
+        {
+            JobRecord::mark_failed(&self.db, id, &e.to_string()).await?;
+            return Err(e);
+        }
+        tracing::debug!("Published workflow {} step {}", step.workflow_id, step.name);
+
+        Ok(id)
+    }
+    /// Ends a workflow early because of one of its steps, cancelling everything else in it
+    async fn stop_workflow(
+        &self,
+        step: &WorkflowStepRecord,
+        status: WorkflowStatus,
+        error: Option<&str>,
+    ) -> Result<(), QueueError> {
+        let step_status = match status {
+            WorkflowStatus::Failed => WorkflowStepStatus::Failed,
+            _ => WorkflowStepStatus::Cancelled,
+        };
+        WorkflowStepRecord::mark_unsuccessful(&self.db, step.id, step_status, error).await?;
+        self.end_workflow(step.workflow_id, status.clone(), error)
+            .await?;
+        tracing::warn!(
+            "Workflow {} stopped as {:?} by step {}",
+            step.workflow_id,
+            status,
+            step.name
+        );
+        Ok(())
+    }
+    /// Cancels everything left in a workflow and finishes it with the given status
+    async fn end_workflow(
+        &self,
+        workflow_id: Uuid,
+        status: WorkflowStatus,
+        error: Option<&str>,
+    ) -> Result<(), QueueError> {
+        // Stop sibling steps that are still running, there's no use finishing them
+        for job_id in WorkflowStepRecord::cancel_unfinished(&self.db, workflow_id).await? {
+            JobRecord::cancel(&self.db, job_id).await?;
+        }
+        WorkflowRecord::finish(&self.db, workflow_id, status.clone(), error).await?;
+        // Steps only fail once they're out of attempts, so this is where the video fails too
+        if let WorkflowStatus::Failed = status {
+            let workflow = WorkflowRecord::by_id(&self.db, workflow_id).await?;
+            if let Some(video_id) = workflow.video_id {
+                // Steps after publishing, like images, leave an already playable video alone
+                // Their failure stays on the step and the workflow
+                let video = Video::by_id(&self.db, &video_id).await?;
+                if let ProcessingStatus::Completed = video.processing_status {
+                    tracing::warn!(
+                        "Video {} stays published after workflow {} failed",
+                        video_id,
+                        workflow_id
+                    );
+                } else {
+                    Video::set_failed(
+                        &self.db,
+                        &video_id,
+                        "The video could not be processed",
+                        error.unwrap_or_default(),
+                    )
+                    .await?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
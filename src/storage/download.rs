@@ -0,0 +1,206 @@
+use std::{
+    collections::HashSet,
+    io::SeekFrom,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use futures::StreamExt;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+use super::{file_checksum, ObjectInfo, Storage};
+use crate::error::StorageError;
+
+/// Size of each ranged request a download is split into
+pub const DEFAULT_CHUNK_SIZE: u64 = 64 * 1024 * 1024;
+
+/// How many chunks are downloaded at once
+pub const DEFAULT_DOWNLOAD_CONCURRENCY: usize = 4;
+
+/// How an object gets downloaded to a file
+#[derive(Debug, Clone)]
+pub struct DownloadOptions {
+    pub chunk_size: u64,
+    /// Most chunks downloaded at once
+    pub concurrency: usize,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            concurrency: DEFAULT_DOWNLOAD_CONCURRENCY,
+        }
+    }
+}
+
+/// Adds a suffix to the file name of the path
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Where a download is written until all of it has been checked
+pub fn partial_path(target_path: &Path) -> PathBuf {
+    with_suffix(target_path, ".part")
+}
+
+/// Where the chunks of a partial download that are already on disk are recorded
+fn progress_path(target_path: &Path) -> PathBuf {
+    with_suffix(target_path, ".part.progress")
+}
+
+/// Identifies the object a partial download is of, so a changed object starts over
+fn progress_header(info: &ObjectInfo) -> String {
+    format!("{} {}", info.size, info.checksum.as_deref().unwrap_or("-"))
+}
+
+/// Reads which chunks a previous attempt finished, nothing when it was of another object
+async fn read_progress(path: &Path, header: &str) -> HashSet<u64> {
+    let Ok(progress) = tokio::fs::read_to_string(path).await else {
+        return HashSet::new();
+    };
+    let mut lines = progress.lines();
+    if lines.next() != Some(header) {
+        return HashSet::new();
+    }
+    // A line cut off by a crash won't parse, and that chunk just gets downloaded again
+    lines.filter_map(|line| line.parse().ok()).collect()
+}
+
+/// Downloads a range of the object into the same range of the file
+async fn download_chunk(
+    storage: &dyn Storage,
+    key: &str,
+    path: &Path,
+    range: Range<u64>,
+) -> Result<(), StorageError> {
+    let mut file = tokio::fs::OpenOptions::new().write(true).open(path).await?;
+    file.seek(SeekFrom::Start(range.start)).await?;
+
+    let expected = range.end - range.start;
+    let mut written = 0;
+    let mut body = storage.get_range(key, range.clone()).await?;
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk?;
+        written += chunk.len() as u64;
+        if written > expected {
+            return Err(StorageError::Request(format!(
+                "Got more than the {} bytes asked for from {}",
+                expected, key
+            )));
+        }
+        file.write_all(&chunk).await?;
+    }
+    if written != expected {
+        return Err(StorageError::Request(format!(
+            "Download of bytes {}-{} of {} ended early",
+            range.start, range.end, key
+        )));
+    }
+    // Only count the chunk as done once it's really on disk
+    file.sync_data().await?;
+    Ok(())
+}
+
+/// Downloads an object to the target path in parallel ranged requests, without holding it in memory
+/// Picks up where an interrupted download of the same object left off, and only moves the file
+/// to the target path once its size, and checksum when the object has one, are confirmed
+pub async fn download_file(
+    storage: &dyn Storage,
+    key: &str,
+    target_path: &Path,
+    options: &DownloadOptions,
+) -> Result<(), StorageError> {
+    let info = storage.head(key).await?;
+    if let Some(parent) = target_path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let partial_path = partial_path(target_path);
+    let progress_path = progress_path(target_path);
+
+    let header = progress_header(&info);
+    let mut finished = read_progress(&progress_path, &header).await;
+    let resumable = !finished.is_empty()
+        && tokio::fs::metadata(&partial_path)
+            .await
+            .is_ok_and(|metadata| metadata.len() == info.size);
+    if resumable {
+        tracing::info!(
+            "Resuming download of {} with {} chunks already on disk",
+            key,
+            finished.len()
+        );
+    } else {
+        finished.clear();
+        let file = tokio::fs::File::create(&partial_path).await?;
+        file.set_len(info.size).await?;
+        tokio::fs::write(&progress_path, format!("{}\n", header)).await?;
+    }
+
+    let chunk_size = options.chunk_size.max(1);
+    let chunks: Vec<Range<u64>> = (0..info.size.div_ceil(chunk_size))
+        .filter(|index| !finished.contains(index))
+        .map(|index| index * chunk_size..((index + 1) * chunk_size).min(info.size))
+        .collect();
+    tracing::debug!(
+        "Downloading {} bytes of {} in {} chunks",
+        info.size,
+        key,
+        chunks.len()
+    );
+
+    let mut progress = tokio::fs::OpenOptions::new()
+        .append(true)
+        .open(&progress_path)
+        .await?;
+    let partial_path_ref = &partial_path;
+    let mut downloads = futures::stream::iter(chunks)
+        .map(|range| async move {
+            let index = range.start / chunk_size;
+            (
+                index,
+                download_chunk(storage, key, partial_path_ref, range).await,
+            )
+        })
+        .buffer_unordered(options.concurrency.max(1));
+    while let Some((index, result)) = downloads.next().await {
+        // Finished chunks stay recorded, so the next attempt only downloads what's missing
+        result?;
+        progress
+            .write_all(format!("{}\n", index).as_bytes())
+            .await?;
+    }
+    drop(downloads);
+    progress.sync_data().await?;
+
+    let size = tokio::fs::metadata(&partial_path).await?.len();
+    if size != info.size {
+        return Err(StorageError::Request(format!(
+            "Downloaded {} bytes of {}, expected {}",
+            size, key, info.size
+        )));
+    }
+    if let Some(expected) = &info.checksum {
+        let checksum_path = partial_path.clone();
+        let checksum = tokio::task::spawn_blocking(move || file_checksum(&checksum_path))
+            .await
+            .map_err(std::io::Error::other)??;
+        if &checksum != expected {
+            // Something on disk is wrong, and there's no telling which chunk it's in
+            let _ = tokio::fs::remove_file(&partial_path).await;
+            let _ = tokio::fs::remove_file(&progress_path).await;
+            return Err(StorageError::Request(format!(
+                "Checksum of {} is {}, expected {}",
+                key, checksum, expected
+            )));
+        }
+    }
+
+    tokio::fs::rename(&partial_path, target_path).await?;
+    if let Err(e) = tokio::fs::remove_file(&progress_path).await {
+        tracing::warn!("Could not clean up {:?}: {}", progress_path, e);
+    }
+    Ok(())
+}
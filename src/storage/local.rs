@@ -0,0 +1,502 @@
+use std::{
+    io::SeekFrom,
+    ops::Range,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt, TryStreamExt};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use super::{
+    file_checksum, CompletedPart, MultipartUpload, ObjectHeaders, ObjectInfo, ObjectStream, Storage,
+};
+use crate::error::StorageError;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Directory under the root that the parts of unfinished multipart uploads are kept in
+const MULTIPART_DIR: &str = ".multipart";
+
+/// Directory under the root that objects are written to before they're moved into place
+const TEMP_DIR: &str = ".tmp";
+
+/// Most parts a multipart upload can have, the same as S3
+const MAX_PARTS: i32 = 10_000;
+
+/// Stores objects as files under a directory, keyed by their path relative to it
+/// Presigned URLs point back at the API, which checks their signature before serving them,
+/// so the API and the job runners have to share the directory
+pub struct LocalStorage {
+    root: PathBuf,
+    /// Where the API serves the storage routes, e.g. http://localhost:3000/storage
+    public_url: String,
+    signing_key: Vec<u8>,
+}
+
+impl LocalStorage {
+    pub fn new(
+        root: impl Into<PathBuf>,
+        public_url: impl Into<String>,
+        signing_key: impl Into<Vec<u8>>,
+    ) -> Result<Self, StorageError> {
+        let root = root.into();
+        std::fs::create_dir_all(&root)?;
+        let signing_key = signing_key.into();
+        if signing_key.is_empty() {
+            return Err(StorageError::InvalidConfig(
+                "A signing key is required for local storage".to_string(),
+            ));
+        }
+        Ok(Self {
+            root,
+            public_url: public_url.into().trim_end_matches('/').to_string(),
+            signing_key,
+        })
+    }
+    /// Stores objects under LOCAL_STORAGE_DIR, signing URLs for STORAGE_PUBLIC_URL with
+    /// STORAGE_SIGNING_KEY, or JWT_SECRET when there isn't a key of its own
+    pub fn from_env() -> Result<Self, StorageError> {
+        let root = std::env::var("LOCAL_STORAGE_DIR").unwrap_or_else(|_| "objects".to_string());
+        let public_url = std::env::var("STORAGE_PUBLIC_URL").unwrap_or_else(|_| {
+            let port = std::env::var("PORT").unwrap_or_else(|_| "3000".to_string());
+            format!("http://localhost:{}/storage", port)
+        });
+        let signing_key = std::env::var("STORAGE_SIGNING_KEY")
+            .or_else(|_| std::env::var("JWT_SECRET"))
+            .map_err(|_| {
+                StorageError::InvalidConfig(
+                    "STORAGE_SIGNING_KEY or JWT_SECRET required for local storage".to_string(),
+                )
+            })?;
+        Self::new(root, public_url, signing_key)
+    }
+    /// Gets the path of the file an object is kept in
+    /// Keys can't reach outside of the root or into the directories kept for uploads
+    fn path(&self, key: &str) -> Result<PathBuf, StorageError> {
+        let valid = !key.is_empty()
+            && !key.contains('\\')
+            && key
+                .split('/')
+                .all(|part| !part.is_empty() && !part.starts_with('.'));
+        if !valid {
+            return Err(StorageError::InvalidKey(key.to_string()));
+        }
+        Ok(self.root.join(key))
+    }
+    /// Gets the directory the parts of a multipart upload are kept in
+    fn upload_dir(&self, upload_id: &str) -> Result<PathBuf, StorageError> {
+        let upload_id = Uuid::parse_str(upload_id)
+            .map_err(|_| StorageError::NotFound(format!("Upload {}", upload_id)))?;
+        Ok(self
+            .root
+            .join(MULTIPART_DIR)
+            .join(upload_id.hyphenated().to_string()))
+    }
+    /// A path to write to before moving the file into place, so readers never see half of it
+    async fn temp_path(&self) -> Result<PathBuf, StorageError> {
+        let temp_dir = self.root.join(TEMP_DIR);
+        tokio::fs::create_dir_all(&temp_dir).await?;
+        Ok(temp_dir.join(Uuid::new_v4().to_string()))
+    }
+    /// Opens the file of an object for reading
+    async fn open(&self, key: &str) -> Result<tokio::fs::File, StorageError> {
+        match tokio::fs::File::open(self.path(key)?).await {
+            Ok(file) => Ok(file),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(StorageError::NotFound(key.to_string()))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+    /// Moves a finished file into place as the object
+    async fn commit(&self, temp_path: &Path, key: &str) -> Result<(), StorageError> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::rename(temp_path, &path).await?;
+        Ok(())
+    }
+    fn sign(&self, message: &str) -> String {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.signing_key).expect("HMAC can take keys of any size");
+        mac.update(message.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+    /// Signs the message until the expiry, returning the query string for the URL
+    fn signed_query(&self, message: &str, expires_in: Duration) -> String {
+        let expires = Utc::now().timestamp() + expires_in.as_secs() as i64;
+        let signature = self.sign(&format!("{}\n{}", message, expires));
+        format!("expires={}&signature={}", expires, signature)
+    }
+    fn verify(&self, message: &str, expires: i64, signature: &str) -> Result<(), StorageError> {
+        if expires < Utc::now().timestamp() {
+            return Err(StorageError::InvalidSignature(
+                "URL has expired".to_string(),
+            ));
+        }
+        let signature = hex::decode(signature)
+            .map_err(|_| StorageError::InvalidSignature("Malformed signature".to_string()))?;
+        let mut mac =
+            HmacSha256::new_from_slice(&self.signing_key).expect("HMAC can take keys of any size");
+        mac.update(format!("{}\n{}", message, expires).as_bytes());
+        mac.verify_slice(&signature)
+            .map_err(|_| StorageError::InvalidSignature("Signature does not match".to_string()))
+    }
+    /// Gets where an object is kept on disk, for tools that can read it in place
+    pub fn object_path(&self, key: &str) -> Result<PathBuf, StorageError> {
+        self.path(key)
+    }
+    /// Checks a URL from `presign_get` before the object is served
+    pub fn verify_get(&self, key: &str, expires: i64, signature: &str) -> Result<(), StorageError> {
+        self.verify(&format!("GET\n{}", key), expires, signature)
+    }
+    /// Checks a URL from `presign_upload_part` before the part is written
+    pub fn verify_upload_part(
+        &self,
+        upload_id: &str,
+        part_number: i32,
+        expires: i64,
+        signature: &str,
+    ) -> Result<(), StorageError> {
+        self.verify(
+            &format!("PUT\n{}\n{}", upload_id, part_number),
+            expires,
+            signature,
+        )
+    }
+    /// Writes a part of a multipart upload as it's received, returning its ETag
+    pub async fn write_part<S>(
+        &self,
+        upload_id: &str,
+        part_number: i32,
+        mut body: S,
+    ) -> Result<String, StorageError>
+    where
+        S: Stream<Item = Result<Bytes, std::io::Error>> + Send + Unpin,
+    {
+        let upload_dir = self.upload_dir(upload_id)?;
+        if !tokio::fs::try_exists(&upload_dir).await? {
+            return Err(StorageError::NotFound(format!("Upload {}", upload_id)));
+        }
+        if !(1..=MAX_PARTS).contains(&part_number) {
+            return Err(StorageError::InvalidKey(format!(
+                "Part number {} is outside of 1 to {}",
+                part_number, MAX_PARTS
+            )));
+        }
+
+        let temp_path = self.temp_path().await?;
+        let mut file = tokio::fs::File::create(&temp_path).await?;
+        let mut hasher = Sha256::new();
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk?;
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+
+        let etag = hex::encode(hasher.finalize());
+        let part_path = upload_dir.join(format!("part_{:05}", part_number));
+        tokio::fs::rename(&temp_path, &part_path).await?;
+        tokio::fs::write(part_path.with_extension("etag"), &etag).await?;
+        Ok(etag)
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(
+        &self,
+        key: &str,
+        body: Bytes,
+        _headers: ObjectHeaders<'_>,
+    ) -> Result<(), StorageError> {
+        self.path(key)?;
+        let temp_path = self.temp_path().await?;
+        tokio::fs::write(&temp_path, body).await?;
+        self.commit(&temp_path, key).await
+    }
+
+    async fn put_file(
+        &self,
+        key: &str,
+        path: &Path,
+        _headers: ObjectHeaders<'_>,
+    ) -> Result<(), StorageError> {
+        self.path(key)?;
+        let temp_path = self.temp_path().await?;
+        tokio::fs::copy(path, &temp_path).await?;
+        self.commit(&temp_path, key).await
+    }
+
+    async fn get(&self, key: &str) -> Result<ObjectStream, StorageError> {
+        let file = self.open(key).await?;
+        Ok(Box::pin(
+            ReaderStream::new(file).map_err(StorageError::from),
+        ))
+    }
+
+    async fn get_range(&self, key: &str, range: Range<u64>) -> Result<ObjectStream, StorageError> {
+        let mut file = self.open(key).await?;
+        file.seek(SeekFrom::Start(range.start)).await?;
+        let file = file.take(range.end.saturating_sub(range.start));
+        Ok(Box::pin(
+            ReaderStream::new(file).map_err(StorageError::from),
+        ))
+    }
+
+    async fn head(&self, key: &str) -> Result<ObjectInfo, StorageError> {
+        let path = self.path(key)?;
+        let metadata = match tokio::fs::metadata(&path).await {
+            Ok(metadata) if metadata.is_file() => metadata,
+            Ok(_) => return Err(StorageError::NotFound(key.to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(StorageError::NotFound(key.to_string()))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let checksum = tokio::task::spawn_blocking(move || file_checksum(&path))
+            .await
+            .map_err(std::io::Error::other)??;
+        Ok(ObjectInfo {
+            key: key.to_string(),
+            size: metadata.len(),
+            last_modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+            checksum: Some(checksum),
+        })
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, StorageError> {
+        Ok(tokio::fs::try_exists(self.path(key)?).await?)
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>, StorageError> {
+        // Only walk the deepest directory the prefix is sure to be in
+        let start = match prefix.rfind('/') {
+            Some(index) => self.root.join(&prefix[..index]),
+            None => self.root.clone(),
+        };
+        let root = self.root.clone();
+        let prefix = prefix.to_string();
+        tokio::task::spawn_blocking(move || {
+            let mut objects = Vec::new();
+            if !start.is_dir() {
+                return Ok(objects);
+            }
+            let entries = walkdir::WalkDir::new(&start)
+                .into_iter()
+                // Skip the directories kept for uploads
+                .filter_entry(|entry| !entry.file_name().to_string_lossy().starts_with('.'));
+            for entry in entries {
+                let entry = entry.map_err(|e| StorageError::Io(e.into()))?;
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+                let Ok(relative_path) = entry.path().strip_prefix(&root) else {
+                    continue;
+                };
+                let key = relative_path
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                if !key.starts_with(&prefix) {
+                    continue;
+                }
+                let metadata = entry.metadata().map_err(|e| StorageError::Io(e.into()))?;
+                objects.push(ObjectInfo {
+                    key,
+                    size: metadata.len(),
+                    last_modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+                    // Hashing every file would read all of them, `head` hashes a single object
+                    checksum: None,
+                });
+            }
+            Ok(objects)
+        })
+        .await
+        .map_err(|e| StorageError::Io(std::io::Error::other(e)))?
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        let path = self.path(key)?;
+        match tokio::fs::remove_file(&path).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+        // Clean up the directories the object leaves empty, stopping at the first one that isn't
+        let mut dir = path.parent();
+        while let Some(parent) = dir {
+            if parent == self.root || tokio::fs::remove_dir(parent).await.is_err() {
+                break;
+            }
+            dir = parent.parent();
+        }
+        Ok(())
+    }
+
+    async fn delete_prefix(&self, prefix: &str) -> Result<usize, StorageError> {
+        let objects = self.list(prefix).await?;
+        for object in &objects {
+            self.delete(&object.key).await?;
+        }
+        Ok(objects.len())
+    }
+
+    async fn presign_get(&self, key: &str, expires_in: Duration) -> Result<String, StorageError> {
+        self.path(key)?;
+        let encoded_key = key
+            .split('/')
+            .map(|part| urlencoding::encode(part).into_owned())
+            .collect::<Vec<_>>()
+            .join("/");
+        Ok(format!(
+            "{}/objects/{}?{}",
+            self.public_url,
+            encoded_key,
+            self.signed_query(&format!("GET\n{}", key), expires_in)
+        ))
+    }
+
+    async fn create_multipart_upload(
+        &self,
+        key: &str,
+        _content_type: &str,
+    ) -> Result<String, StorageError> {
+        self.path(key)?;
+        let upload_id = Uuid::new_v4().to_string();
+        let upload_dir = self.upload_dir(&upload_id)?;
+        tokio::fs::create_dir_all(&upload_dir).await?;
+        // Remember the key, so the upload can't be completed as some other object
+        tokio::fs::write(upload_dir.join("key"), key).await?;
+        Ok(upload_id)
+    }
+
+    async fn presign_upload_part(
+        &self,
+        _key: &str,
+        upload_id: &str,
+        part_number: i32,
+        expires_in: Duration,
+    ) -> Result<String, StorageError> {
+        self.upload_dir(upload_id)?;
+        Ok(format!(
+            "{}/parts/{}/{}?{}",
+            self.public_url,
+            upload_id,
+            part_number,
+            self.signed_query(&format!("PUT\n{}\n{}", upload_id, part_number), expires_in)
+        ))
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: &[CompletedPart],
+    ) -> Result<(), StorageError> {
+        let upload_dir = self.upload_dir(upload_id)?;
+        let upload_key = match tokio::fs::read_to_string(upload_dir.join("key")).await {
+            Ok(upload_key) => upload_key,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(StorageError::NotFound(format!("Upload {}", upload_id)))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if upload_key != key {
+            return Err(StorageError::InvalidKey(format!(
+                "Upload {} is for {}, not {}",
+                upload_id, upload_key, key
+            )));
+        }
+
+        let mut parts = parts.to_vec();
+        parts.sort_by_key(|part| part.part_number);
+        let temp_path = self.temp_path().await?;
+        let mut file = tokio::fs::File::create(&temp_path).await?;
+        for part in &parts {
+            let part_path = upload_dir.join(format!("part_{:05}", part.part_number));
+            // Clients send the ETag back the way the header had it, in quotes
+            let etag = tokio::fs::read_to_string(part_path.with_extension("etag"))
+                .await
+                .map_err(|_| StorageError::NotFound(format!("Part {}", part.part_number)))?;
+            if etag != part.etag.trim_matches('"') {
+                return Err(StorageError::InvalidKey(format!(
+                    "ETag of part {} does not match",
+                    part.part_number
+                )));
+            }
+            let mut part_file = tokio::fs::File::open(&part_path).await?;
+            tokio::io::copy(&mut part_file, &mut file).await?;
+        }
+        file.flush().await?;
+
+        self.commit(&temp_path, key).await?;
+        tokio::fs::remove_dir_all(&upload_dir).await?;
+        Ok(())
+    }
+
+    async fn list_multipart_uploads(
+        &self,
+        prefix: &str,
+    ) -> Result<Vec<MultipartUpload>, StorageError> {
+        let mut uploads = Vec::new();
+        let mut entries = match tokio::fs::read_dir(self.root.join(MULTIPART_DIR)).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(uploads),
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let key_path = entry.path().join("key");
+            // The key is written right after the directory, so it can be missing for a moment
+            let Ok(key) = tokio::fs::read_to_string(&key_path).await else {
+                continue;
+            };
+            if !key.starts_with(prefix) {
+                continue;
+            }
+            let initiated = tokio::fs::metadata(&key_path)
+                .await
+                .ok()
+                .and_then(|metadata| metadata.modified().ok())
+                .map(DateTime::<Utc>::from);
+            uploads.push(MultipartUpload {
+                key,
+                upload_id: entry.file_name().to_string_lossy().to_string(),
+                initiated,
+            });
+        }
+        Ok(uploads)
+    }
+
+    async fn abort_multipart_upload(
+        &self,
+        _key: &str,
+        upload_id: &str,
+    ) -> Result<(), StorageError> {
+        let upload_dir = match self.upload_dir(upload_id) {
+            Ok(upload_dir) => upload_dir,
+            // Not an ID we would have handed out, so there's nothing to abort
+            Err(_) => return Ok(()),
+        };
+        match tokio::fs::remove_dir_all(&upload_dir).await {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn as_local(&self) -> Option<&LocalStorage> {
+        Some(self)
+    }
+}
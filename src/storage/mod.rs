@@ -1 +1,230 @@
+pub mod download;
+pub mod local;
 pub mod s3;
+pub mod sync;
+
+use std::{io::Read, ops::Range, path::Path, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use md5::{Digest, Md5};
+
+pub use self::download::{download_file, DownloadOptions};
+pub use self::local::LocalStorage;
+pub use self::s3::S3Storage;
+pub use self::sync::{sync_directory, SyncFailure, SyncOptions, SyncReport};
+use crate::error::StorageError;
+
+/// The contents of an object, read a chunk at a time
+pub type ObjectStream = BoxStream<'static, Result<Bytes, StorageError>>;
+
+/// Which service objects are stored in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StorageBackend {
+    /// Cloudflare R2, for the account in R2_ACCOUNT_ID
+    R2,
+    /// Any S3 compatible service, like MinIO, at S3_ENDPOINT
+    S3,
+    /// A directory on this machine, for local development and self-hosting
+    Local,
+}
+
+impl StorageBackend {
+    /// Reads the backend from STORAGE_BACKEND, defaulting to R2
+    pub fn from_env() -> Result<Self, StorageError> {
+        match std::env::var("STORAGE_BACKEND") {
+            Err(_) => Ok(Self::R2),
+            Ok(backend) => match backend.trim().to_lowercase().as_str() {
+                "" | "r2" => Ok(Self::R2),
+                "s3" | "minio" => Ok(Self::S3),
+                "local" | "filesystem" => Ok(Self::Local),
+                other => Err(StorageError::InvalidConfig(format!(
+                    "Unknown STORAGE_BACKEND {}, expected r2, s3 or local",
+                    other
+                ))),
+            },
+        }
+    }
+}
+
+/// An object found when listing a prefix
+#[derive(Debug, Clone)]
+pub struct ObjectInfo {
+    pub key: String,
+    /// Size of the object in bytes
+    pub size: u64,
+    pub last_modified: Option<DateTime<Utc>>,
+    /// MD5 of the contents in hex, when the backend knows it
+    /// Objects uploaded in parts don't have one, their ETag isn't a hash of the contents
+    /// The filesystem backend only hashes objects for `head`, listings leave it out
+    pub checksum: Option<String>,
+}
+
+/// A multipart upload that was started but hasn't been completed or aborted
+#[derive(Debug, Clone)]
+pub struct MultipartUpload {
+    pub key: String,
+    pub upload_id: String,
+    pub initiated: Option<DateTime<Utc>>,
+}
+
+/// Headers an object is served with
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ObjectHeaders<'a> {
+    pub content_type: Option<&'a str>,
+    pub cache_control: Option<&'a str>,
+}
+
+impl<'a> ObjectHeaders<'a> {
+    pub fn content_type(content_type: &'a str) -> Self {
+        Self {
+            content_type: Some(content_type),
+            cache_control: None,
+        }
+    }
+}
+
+/// Playlists can be rewritten when a video is reprocessed, so players check back often
+const PLAYLIST_CACHE_CONTROL: &str = "public, max-age=60";
+/// Segments and archives never change once they've been written
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+/// Thumbnails can be replaced, but only under a new name
+const IMAGE_CACHE_CONTROL: &str = "public, max-age=86400";
+
+impl ObjectHeaders<'static> {
+    /// Picks the headers for an object by the extension of its key
+    pub fn for_key(key: &str) -> Self {
+        let extension = key
+            .rsplit_once('.')
+            .map(|(_, extension)| extension.to_lowercase())
+            .unwrap_or_default();
+        let (content_type, cache_control) = match extension.as_str() {
+            "m3u8" => ("application/vnd.apple.mpegurl", PLAYLIST_CACHE_CONTROL),
+            "mpd" => ("application/dash+xml", PLAYLIST_CACHE_CONTROL),
+            "ts" => ("video/mp2t", IMMUTABLE_CACHE_CONTROL),
+            "m4s" => ("video/iso.segment", IMMUTABLE_CACHE_CONTROL),
+            "mp4" => ("video/mp4", IMMUTABLE_CACHE_CONTROL),
+            "aac" => ("audio/aac", IMMUTABLE_CACHE_CONTROL),
+            "vtt" => ("text/vtt", PLAYLIST_CACHE_CONTROL),
+            "jpg" | "jpeg" => ("image/jpeg", IMAGE_CACHE_CONTROL),
+            "png" => ("image/png", IMAGE_CACHE_CONTROL),
+            "webp" => ("image/webp", IMAGE_CACHE_CONTROL),
+            "mkv" => ("video/x-matroska", IMMUTABLE_CACHE_CONTROL),
+            _ => return Self::default(),
+        };
+        Self {
+            content_type: Some(content_type),
+            cache_control: Some(cache_control),
+        }
+    }
+}
+
+/// A part of a multipart upload the client has finished sending
+#[derive(Debug, Clone)]
+pub struct CompletedPart {
+    pub part_number: i32,
+    pub etag: String,
+}
+
+/// Stores the raw uploads and everything processed from them
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Writes an object from memory, replacing any object with the key
+    async fn put(
+        &self,
+        key: &str,
+        body: Bytes,
+        headers: ObjectHeaders<'_>,
+    ) -> Result<(), StorageError>;
+    /// Writes an object from a local file without reading all of it into memory
+    async fn put_file(
+        &self,
+        key: &str,
+        path: &Path,
+        headers: ObjectHeaders<'_>,
+    ) -> Result<(), StorageError>;
+    /// Reads an object as a stream of chunks
+    async fn get(&self, key: &str) -> Result<ObjectStream, StorageError>;
+    /// Reads the bytes of an object in the range, which has to be within the object
+    async fn get_range(&self, key: &str, range: Range<u64>) -> Result<ObjectStream, StorageError>;
+    /// Gets the size and checksum of an object without reading it
+    async fn head(&self, key: &str) -> Result<ObjectInfo, StorageError>;
+    /// Checks whether there's an object with the key
+    async fn exists(&self, key: &str) -> Result<bool, StorageError>;
+    /// Lists every object with a key starting with the prefix
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>, StorageError>;
+    /// Deletes an object, which is fine when it's already gone
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+    /// Deletes every object with a key starting with the prefix, returning how many there were
+    /// End directory-like prefixes with a slash, or video1 would take video10 with it
+    async fn delete_prefix(&self, prefix: &str) -> Result<usize, StorageError>;
+    /// Builds a URL anyone can download the object from until it expires
+    async fn presign_get(&self, key: &str, expires_in: Duration) -> Result<String, StorageError>;
+    /// Starts an upload the client sends in parts, returning its ID
+    async fn create_multipart_upload(
+        &self,
+        key: &str,
+        content_type: &str,
+    ) -> Result<String, StorageError>;
+    /// Builds a URL the client can upload a single part to until it expires
+    async fn presign_upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        expires_in: Duration,
+    ) -> Result<String, StorageError>;
+    /// Joins the uploaded parts into the object, in order of their part numbers
+    async fn complete_multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: &[CompletedPart],
+    ) -> Result<(), StorageError>;
+    /// Lists the multipart uploads still open for keys starting with the prefix
+    async fn list_multipart_uploads(
+        &self,
+        prefix: &str,
+    ) -> Result<Vec<MultipartUpload>, StorageError>;
+    /// Throws away the parts of a multipart upload, which is fine when it's already gone
+    async fn abort_multipart_upload(&self, key: &str, upload_id: &str) -> Result<(), StorageError>;
+    /// The filesystem backend, whose presigned URLs are served by the API
+    fn as_local(&self) -> Option<&LocalStorage> {
+        None
+    }
+}
+
+/// Connects to the storage backend set by STORAGE_BACKEND, holding objects in UPLOAD_BUCKET
+/// The filesystem backend doesn't need a bucket, it keeps objects under LOCAL_STORAGE_DIR
+pub async fn connect() -> Result<Arc<dyn Storage>, StorageError> {
+    let backend = StorageBackend::from_env()?;
+    let bucket = || {
+        std::env::var("UPLOAD_BUCKET")
+            .map_err(|_| StorageError::InvalidConfig("UPLOAD_BUCKET required".to_string()))
+    };
+    let storage: Arc<dyn Storage> = match backend {
+        StorageBackend::R2 => Arc::new(S3Storage::r2(bucket()?).await?),
+        StorageBackend::S3 => Arc::new(S3Storage::s3_compatible(bucket()?).await?),
+        StorageBackend::Local => Arc::new(LocalStorage::from_env()?),
+    };
+    tracing::info!("Storing objects with the {:?} backend", backend);
+    Ok(storage)
+}
+
+/// Hashes a file the same way S3 sets the ETag of objects uploaded in a single request
+/// Reads the whole file, so call it off of the async runtime
+pub fn file_checksum(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Md5::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
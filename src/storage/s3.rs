@@ -1,65 +1,448 @@
+use std::{ops::Range, path::Path, time::Duration};
+
+use async_trait::async_trait;
 use aws_config::Region;
-use aws_sdk_s3::Client;
+use aws_sdk_s3::{
+    presigning::PresigningConfig,
+    primitives::ByteStream,
+    types::{CompletedMultipartUpload, Delete, ObjectIdentifier},
+    Client,
+};
+use bytes::Bytes;
+use chrono::DateTime;
+
+use super::{CompletedPart, MultipartUpload, ObjectHeaders, ObjectInfo, ObjectStream, Storage};
+use crate::error::StorageError;
+
+/// Most keys a single DeleteObjects request can take
+const DELETE_BATCH_SIZE: usize = 1000;
+
+/// Stores objects in a bucket on R2 or any other S3 compatible service
+pub struct S3Storage {
+    client: Client,
+    bucket: String,
+}
+
+impl S3Storage {
+    pub fn new(client: Client, bucket: impl Into<String>) -> Self {
+        Self {
+            client,
+            bucket: bucket.into(),
+        }
+    }
+    /// Connects to Cloudflare R2 for the account in R2_ACCOUNT_ID
+    pub async fn r2(bucket: impl Into<String>) -> Result<Self, StorageError> {
+        let r2_account_id = std::env::var("R2_ACCOUNT_ID").map_err(|_| {
+            StorageError::InvalidConfig("R2_ACCOUNT_ID required for R2 storage".to_string())
+        })?;
+        let endpoint_url = format!("https://{}.r2.cloudflarestorage.com", r2_account_id);
+
+        let config = aws_config::from_env()
+            .region(Region::new("auto"))
+            .endpoint_url(endpoint_url)
+            .load()
+            .await;
+
+        Ok(Self::new(Client::new(&config), bucket))
+    }
+    /// Connects to an S3 compatible service, AWS itself when S3_ENDPOINT isn't set
+    /// S3_REGION defaults to us-east-1, and custom endpoints use path-style addressing
+    /// unless S3_FORCE_PATH_STYLE is false, which is what MinIO expects out of the box
+    pub async fn s3_compatible(bucket: impl Into<String>) -> Result<Self, StorageError> {
+        let endpoint_url = std::env::var("S3_ENDPOINT").ok();
+        let region = std::env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+        let force_path_style = match std::env::var("S3_FORCE_PATH_STYLE") {
+            Ok(value) => value.parse::<bool>().map_err(|_| {
+                StorageError::InvalidConfig(format!(
+                    "S3_FORCE_PATH_STYLE must be true or false, got {}",
+                    value
+                ))
+            })?,
+            Err(_) => endpoint_url.is_some(),
+        };
+
+        let mut loader = aws_config::from_env().region(Region::new(region));
+        if let Some(endpoint_url) = endpoint_url {
+            loader = loader.endpoint_url(endpoint_url);
+        }
+        let shared_config = loader.load().await;
+        let config = aws_sdk_s3::config::Builder::from(&shared_config)
+            .force_path_style(force_path_style)
+            .build();
+
+        Ok(Self::new(Client::from_conf(config), bucket))
+    }
+    /// Streams an object, or only the bytes in the HTTP range when there is one
+    async fn get_object(
+        &self,
+        key: &str,
+        range: Option<String>,
+    ) -> Result<ObjectStream, StorageError> {
+        let output = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .set_range(range)
+            .send()
+            .await
+            .map_err(|e| match e.as_service_error() {
+                Some(service_error) if service_error.is_no_such_key() => {
+                    StorageError::NotFound(key.to_string())
+                }
+                _ => request_error(&format!("Could not download {}", key), e),
+            })?;
 
-/// Create an S3 Client configured against Cloudflare R2
-pub async fn create_s3_client() -> Client {
-    let region = Region::new("auto");
-    let r2_account_id = std::env::var("R2_ACCOUNT_ID").expect("R2_ACCOUNT_ID required");
-    let endpoint_url = format!("https://{}.r2.cloudflarestorage.com", r2_account_id);
+        let key = key.to_string();
+        let stream = futures::stream::try_unfold(output.body, move |mut body| {
+            let key = key.clone();
+            async move {
+                match body.try_next().await {
+                    Ok(Some(chunk)) => Ok(Some((chunk, body))),
+                    Ok(None) => Ok(None),
+                    Err(e) => Err(request_error(&format!("Could not download {}", key), e)),
+                }
+            }
+        });
+        Ok(Box::pin(stream))
+    }
+}
 
-    let config = aws_config::from_env()
-        .region(region)
-        .endpoint_url(endpoint_url)
-        .load()
-        .await;
+/// Flattens an SDK error down to its message along with what caused it
+fn request_error(context: &str, err: impl std::error::Error) -> StorageError {
+    let mut message = format!("{}: {}", context, err);
+    let mut source = err.source();
+    while let Some(cause) = source {
+        message.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+    StorageError::Request(message)
+}
+
+/// Gets the MD5 out of an ETag, which objects uploaded in parts don't have
+/// Their ETags end with the number of parts instead
+fn etag_checksum(etag: Option<&str>) -> Option<String> {
+    etag.map(|etag| etag.trim_matches('"').to_string())
+        .filter(|etag| !etag.contains('-'))
+}
 
-    Client::new(&config)
+fn presigning_config(expires_in: Duration) -> Result<PresigningConfig, StorageError> {
+    PresigningConfig::expires_in(expires_in)
+        .map_err(|e| StorageError::InvalidConfig(format!("Invalid presign expiry: {}", e)))
 }
 
-pub async fn sync_directory_to_bucket<P: AsRef<std::path::Path>>(
-    client: &Client,
-    local_dir: P,
-    bucket: &str,
-    target_prefix: &str,
-    ignore_patterns: &[&str],
-) -> Result<(), Box<dyn std::error::Error>> {
-    let local_dir = local_dir.as_ref();
-    if !local_dir.is_dir() {
-        return Err("Source path is not a directory".into());
-    }
-
-    let walk_dir = walkdir::WalkDir::new(local_dir);
-
-    for entry in walk_dir.into_iter().filter_map(|e| e.ok()) {
-        let path = entry.path();
-        if ignore_patterns
-            .iter()
-            .any(|pattern| path.to_string_lossy().contains(pattern))
+#[async_trait]
+impl Storage for S3Storage {
+    async fn put(
+        &self,
+        key: &str,
+        body: Bytes,
+        headers: ObjectHeaders<'_>,
+    ) -> Result<(), StorageError> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .set_content_type(headers.content_type.map(str::to_string))
+            .set_cache_control(headers.cache_control.map(str::to_string))
+            .body(ByteStream::from(body))
+            .send()
+            .await
+            .map_err(|e| request_error(&format!("Could not upload {}", key), e))?;
+        Ok(())
+    }
+
+    async fn put_file(
+        &self,
+        key: &str,
+        path: &Path,
+        headers: ObjectHeaders<'_>,
+    ) -> Result<(), StorageError> {
+        let body = ByteStream::from_path(path)
+            .await
+            .map_err(|e| request_error(&format!("Could not read {:?}", path), e))?;
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .set_content_type(headers.content_type.map(str::to_string))
+            .set_cache_control(headers.cache_control.map(str::to_string))
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| request_error(&format!("Could not upload {}", key), e))?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<ObjectStream, StorageError> {
+        self.get_object(key, None).await
+    }
+
+    async fn get_range(&self, key: &str, range: Range<u64>) -> Result<ObjectStream, StorageError> {
+        if range.is_empty() {
+            return Ok(Box::pin(futures::stream::empty()));
+        }
+        // HTTP ranges include the last byte
+        let range = format!("bytes={}-{}", range.start, range.end - 1);
+        self.get_object(key, Some(range)).await
+    }
+
+    async fn head(&self, key: &str) -> Result<ObjectInfo, StorageError> {
+        let output = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| match e.as_service_error() {
+                Some(service_error) if service_error.is_not_found() => {
+                    StorageError::NotFound(key.to_string())
+                }
+                _ => request_error(&format!("Could not check for {}", key), e),
+            })?;
+        Ok(ObjectInfo {
+            key: key.to_string(),
+            size: output.content_length().unwrap_or_default().max(0) as u64,
+            last_modified: output
+                .last_modified()
+                .and_then(|time| DateTime::from_timestamp(time.secs(), time.subsec_nanos())),
+            checksum: etag_checksum(output.e_tag()),
+        })
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, StorageError> {
+        match self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
         {
-            tracing::debug!("Skipping {:?}", path);
-            continue;
+            Ok(_) => Ok(true),
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_not_found()) => Ok(false),
+            Err(e) => Err(request_error(&format!("Could not check for {}", key), e)),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>, StorageError> {
+        let mut objects = Vec::new();
+        let mut pages = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(prefix)
+            .into_paginator()
+            .send();
+        while let Some(page) = pages.next().await {
+            let page = page.map_err(|e| request_error(&format!("Could not list {}", prefix), e))?;
+            for object in page.contents() {
+                let Some(key) = object.key() else {
+                    continue;
+                };
+                objects.push(ObjectInfo {
+                    key: key.to_string(),
+                    size: object.size().unwrap_or_default().max(0) as u64,
+                    last_modified: object.last_modified().and_then(|time| {
+                        DateTime::from_timestamp(time.secs(), time.subsec_nanos())
+                    }),
+                    checksum: etag_checksum(object.e_tag()),
+                });
+            }
         }
-        if path.is_file() {
-            let relative_path = path
-                .strip_prefix(local_dir)
-                .map_err(|e| format!("Failed to strip prefix: {}", e))?;
-            let key = if target_prefix.is_empty() {
-                relative_path.to_string_lossy().to_string()
-            } else {
-                format!("{}/{}", target_prefix, relative_path.to_string_lossy())
-            };
-
-            let body = aws_sdk_s3::primitives::ByteStream::from_path(path).await?;
-            tracing::debug!("full key from walkdir {key}");
-            client
-                .put_object()
-                .bucket(bucket)
-                .key(&key)
-                .body(body)
+        Ok(objects)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| request_error(&format!("Could not delete {}", key), e))?;
+        Ok(())
+    }
+
+    async fn delete_prefix(&self, prefix: &str) -> Result<usize, StorageError> {
+        let objects = self.list(prefix).await?;
+        for batch in objects.chunks(DELETE_BATCH_SIZE) {
+            let identifiers = batch
+                .iter()
+                .map(|object| ObjectIdentifier::builder().key(&object.key).build())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| request_error("Could not build delete request", e))?;
+            let delete = Delete::builder()
+                .set_objects(Some(identifiers))
+                .quiet(true)
+                .build()
+                .map_err(|e| request_error("Could not build delete request", e))?;
+            let output = self
+                .client
+                .delete_objects()
+                .bucket(&self.bucket)
+                .delete(delete)
                 .send()
-                .await?;
+                .await
+                .map_err(|e| request_error(&format!("Could not delete {}", prefix), e))?;
+            // Quiet deletes only report the keys that couldn't be deleted
+            if let Some(error) = output.errors().first() {
+                return Err(StorageError::Request(format!(
+                    "Could not delete {}: {}",
+                    error.key().unwrap_or_default(),
+                    error.message().unwrap_or_default()
+                )));
+            }
         }
+        Ok(objects.len())
     }
 
-    Ok(())
+    async fn presign_get(&self, key: &str, expires_in: Duration) -> Result<String, StorageError> {
+        let request = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .presigned(presigning_config(expires_in)?)
+            .await
+            .map_err(|e| request_error(&format!("Could not presign {}", key), e))?;
+        Ok(request.uri().to_string())
+    }
+
+    async fn create_multipart_upload(
+        &self,
+        key: &str,
+        content_type: &str,
+    ) -> Result<String, StorageError> {
+        let output = self
+            .client
+            .create_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type(content_type)
+            .send()
+            .await
+            .map_err(|e| request_error(&format!("Could not start upload of {}", key), e))?;
+        output
+            .upload_id()
+            .map(str::to_string)
+            .ok_or_else(|| StorageError::Request(format!("No upload ID was returned for {}", key)))
+    }
+
+    async fn presign_upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        expires_in: Duration,
+    ) -> Result<String, StorageError> {
+        let request = self
+            .client
+            .upload_part()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .presigned(presigning_config(expires_in)?)
+            .await
+            .map_err(|e| {
+                request_error(
+                    &format!("Could not presign part {} of {}", part_number, key),
+                    e,
+                )
+            })?;
+        Ok(request.uri().to_string())
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: &[CompletedPart],
+    ) -> Result<(), StorageError> {
+        let mut parts = parts.to_vec();
+        parts.sort_by_key(|part| part.part_number);
+        let completed_parts = parts
+            .into_iter()
+            .map(|part| {
+                aws_sdk_s3::types::CompletedPart::builder()
+                    .e_tag(part.etag)
+                    .part_number(part.part_number)
+                    .build()
+            })
+            .collect();
+        let completed_upload = CompletedMultipartUpload::builder()
+            .set_parts(Some(completed_parts))
+            .build();
+
+        self.client
+            .complete_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .multipart_upload(completed_upload)
+            .send()
+            .await
+            .map_err(|e| request_error(&format!("Could not complete upload of {}", key), e))?;
+        Ok(())
+    }
+
+    async fn list_multipart_uploads(
+        &self,
+        prefix: &str,
+    ) -> Result<Vec<MultipartUpload>, StorageError> {
+        let mut uploads = Vec::new();
+        let mut key_marker = None;
+        let mut upload_id_marker = None;
+        loop {
+            let output = self
+                .client
+                .list_multipart_uploads()
+                .bucket(&self.bucket)
+                .prefix(prefix)
+                .set_key_marker(key_marker)
+                .set_upload_id_marker(upload_id_marker)
+                .send()
+                .await
+                .map_err(|e| request_error(&format!("Could not list uploads of {}", prefix), e))?;
+            for upload in output.uploads() {
+                let (Some(key), Some(upload_id)) = (upload.key(), upload.upload_id()) else {
+                    continue;
+                };
+                uploads.push(MultipartUpload {
+                    key: key.to_string(),
+                    upload_id: upload_id.to_string(),
+                    initiated: upload.initiated().and_then(|time| {
+                        DateTime::from_timestamp(time.secs(), time.subsec_nanos())
+                    }),
+                });
+            }
+            if !output.is_truncated().unwrap_or_default() {
+                break;
+            }
+            key_marker = output.next_key_marker().map(str::to_string);
+            upload_id_marker = output.next_upload_id_marker().map(str::to_string);
+        }
+        Ok(uploads)
+    }
+
+    async fn abort_multipart_upload(&self, key: &str, upload_id: &str) -> Result<(), StorageError> {
+        match self
+            .client
+            .abort_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .send()
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_no_such_upload()) => Ok(()),
+            Err(e) => Err(request_error(
+                &format!("Could not abort upload of {}", key),
+                e,
+            )),
+        }
+    }
 }
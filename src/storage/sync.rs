@@ -0,0 +1,289 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use futures::StreamExt;
+use serde::Serialize;
+
+use super::{file_checksum, ObjectHeaders, Storage};
+use crate::error::StorageError;
+
+/// How many files are uploaded at once when syncing a directory
+pub const DEFAULT_SYNC_CONCURRENCY: usize = 8;
+
+/// How a directory gets synced to storage
+#[derive(Debug, Clone)]
+pub struct SyncOptions {
+    /// Globs of paths relative to the directory to leave out, like `*.tmp` or `logs/**`
+    /// `*` and `?` stay within a path segment and `**` crosses them
+    /// Globs without a slash are matched against every segment of the path
+    pub ignore: Vec<String>,
+    /// Most files uploaded at once
+    pub concurrency: usize,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            ignore: Vec::new(),
+            concurrency: DEFAULT_SYNC_CONCURRENCY,
+        }
+    }
+}
+
+impl SyncOptions {
+    pub fn with_ignore(mut self, patterns: &[&str]) -> Self {
+        self.ignore = patterns.iter().map(|pattern| pattern.to_string()).collect();
+        self
+    }
+}
+
+/// A file that couldn't be uploaded
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncFailure {
+    pub key: String,
+    pub error: String,
+}
+
+/// What happened to every file in a synced directory
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncReport {
+    pub uploaded: Vec<String>,
+    /// Files that already matched the object in storage
+    pub skipped: Vec<String>,
+    pub failed: Vec<SyncFailure>,
+}
+
+impl SyncReport {
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+    /// Turns a sync with any failed files into an error naming them
+    pub fn into_result(self) -> Result<Self, StorageError> {
+        if self.is_complete() {
+            return Ok(self);
+        }
+        let failures = self
+            .failed
+            .iter()
+            .map(|failure| format!("{} ({})", failure.key, failure.error))
+            .collect::<Vec<_>>()
+            .join(", ");
+        Err(StorageError::Request(format!(
+            "Could not upload {} of {} files: {}",
+            self.failed.len(),
+            self.uploaded.len() + self.skipped.len() + self.failed.len(),
+            failures
+        )))
+    }
+}
+
+impl fmt::Display for SyncReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} uploaded, {} skipped, {} failed",
+            self.uploaded.len(),
+            self.skipped.len(),
+            self.failed.len()
+        )
+    }
+}
+
+/// Globs for paths to leave out of a sync
+struct IgnoreRules {
+    patterns: Vec<Vec<char>>,
+}
+
+impl IgnoreRules {
+    fn new(patterns: &[String]) -> Result<Self, StorageError> {
+        let mut rules = Vec::new();
+        for pattern in patterns {
+            let pattern = pattern.trim().trim_start_matches("./");
+            if pattern.is_empty() {
+                return Err(StorageError::InvalidConfig(
+                    "Ignore patterns can't be empty".to_string(),
+                ));
+            }
+            rules.push(pattern.chars().collect());
+        }
+        Ok(Self { patterns: rules })
+    }
+    /// Checks a path relative to the synced directory, with slashes between its segments
+    fn is_ignored(&self, relative_path: &str) -> bool {
+        let path: Vec<char> = relative_path.chars().collect();
+        self.patterns.iter().any(|pattern| {
+            if pattern.contains(&'/') {
+                glob_match(pattern, &path)
+            } else {
+                relative_path.split('/').any(|segment| {
+                    let segment: Vec<char> = segment.chars().collect();
+                    glob_match(pattern, &segment)
+                })
+            }
+        })
+    }
+}
+
+/// Matches a path against a glob, where `*` and `?` stay within a segment and `**` crosses them
+fn glob_match(pattern: &[char], path: &[char]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some(('*', rest)) if rest.first() == Some(&'*') => {
+            let rest = &rest[1..];
+            // `**/` also matches no directories at all
+            let without_slash = rest.strip_prefix(&['/']).unwrap_or(rest);
+            glob_match(without_slash, path)
+                || (0..=path.len()).any(|start| glob_match(rest, &path[start..]))
+        }
+        Some(('*', rest)) => (0..=path.len())
+            .take_while(|&start| start == 0 || path[start - 1] != '/')
+            .any(|start| glob_match(rest, &path[start..])),
+        Some(('?', rest)) => {
+            path.first().is_some_and(|c| *c != '/') && glob_match(rest, &path[1..])
+        }
+        Some((c, rest)) => path.first() == Some(c) && glob_match(rest, &path[1..]),
+    }
+}
+
+/// Joins the segments of a relative path with slashes, whatever the platform uses
+fn relative_key(relative_path: &Path) -> String {
+    relative_path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Finds every file in the directory that isn't ignored, along with the key it's synced to
+fn collect_files(
+    local_dir: &Path,
+    target_prefix: &str,
+    ignore: &IgnoreRules,
+) -> Vec<(PathBuf, String)> {
+    let entries = walkdir::WalkDir::new(local_dir)
+        .into_iter()
+        .filter_entry(|entry| match entry.path().strip_prefix(local_dir) {
+            // Ignoring a directory skips everything in it
+            Ok(relative_path) if entry.depth() > 0 => {
+                let ignored = ignore.is_ignored(&relative_key(relative_path));
+                if ignored {
+                    tracing::debug!("Skipping {:?}", entry.path());
+                }
+                !ignored
+            }
+            _ => true,
+        });
+
+    let mut files = Vec::new();
+    for entry in entries.filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let Ok(relative_path) = entry.path().strip_prefix(local_dir) else {
+            continue;
+        };
+        let relative_key = relative_key(relative_path);
+        let key = if target_prefix.is_empty() {
+            relative_key
+        } else {
+            format!("{}/{}", target_prefix, relative_key)
+        };
+        files.push((entry.into_path(), key));
+    }
+    files
+}
+
+enum SyncOutcome {
+    Uploaded,
+    Skipped,
+}
+
+/// Uploads a file unless its checksum matches the object already in storage
+async fn sync_file(
+    storage: &dyn Storage,
+    path: PathBuf,
+    key: &str,
+    remote_checksum: Option<&str>,
+) -> Result<SyncOutcome, StorageError> {
+    if let Some(remote_checksum) = remote_checksum {
+        let checksum_path = path.clone();
+        let checksum = tokio::task::spawn_blocking(move || file_checksum(&checksum_path))
+            .await
+            .map_err(std::io::Error::other)??;
+        if checksum == remote_checksum {
+            return Ok(SyncOutcome::Skipped);
+        }
+    }
+    storage
+        .put_file(key, &path, ObjectHeaders::for_key(key))
+        .await?;
+    Ok(SyncOutcome::Uploaded)
+}
+
+/// Uploads every file in the directory, keyed by its path relative to the directory
+/// Files that already match their object are skipped, and failed files don't stop the rest
+pub async fn sync_directory<P: AsRef<Path>>(
+    storage: &dyn Storage,
+    local_dir: P,
+    target_prefix: &str,
+    options: &SyncOptions,
+) -> Result<SyncReport, StorageError> {
+    let local_dir = local_dir.as_ref();
+    if !local_dir.is_dir() {
+        return Err(StorageError::NotFound(format!(
+            "{:?} is not a directory",
+            local_dir
+        )));
+    }
+    let ignore = IgnoreRules::new(&options.ignore)?;
+
+    let owned_dir = local_dir.to_path_buf();
+    let owned_prefix = target_prefix.to_string();
+    let files =
+        tokio::task::spawn_blocking(move || collect_files(&owned_dir, &owned_prefix, &ignore))
+            .await
+            .map_err(std::io::Error::other)?;
+
+    // One listing gets the checksums of everything that's already been uploaded
+    let list_prefix = match target_prefix.is_empty() {
+        true => String::new(),
+        false => format!("{}/", target_prefix),
+    };
+    let remote_checksums: HashMap<String, String> = storage
+        .list(&list_prefix)
+        .await?
+        .into_iter()
+        .filter_map(|object| Some((object.key, object.checksum?)))
+        .collect();
+
+    let remote_checksums = &remote_checksums;
+    let outcomes: Vec<_> = futures::stream::iter(files)
+        .map(|(path, key)| async move {
+            let remote_checksum = remote_checksums.get(&key).map(String::as_str);
+            let outcome = sync_file(storage, path, &key, remote_checksum).await;
+            (key, outcome)
+        })
+        .buffer_unordered(options.concurrency.max(1))
+        .collect()
+        .await;
+
+    let mut report = SyncReport::default();
+    for (key, outcome) in outcomes {
+        match outcome {
+            Ok(SyncOutcome::Uploaded) => report.uploaded.push(key),
+            Ok(SyncOutcome::Skipped) => report.skipped.push(key),
+            Err(e) => {
+                tracing::warn!("Could not upload {}: {}", key, e);
+                report.failed.push(SyncFailure {
+                    key,
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+    tracing::info!("Synced {:?} to {}: {}", local_dir, target_prefix, report);
+    Ok(report)
+}
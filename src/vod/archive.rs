@@ -0,0 +1,75 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use tokio_util::sync::CancellationToken;
+
+use super::ffmpeg::FfmpegCommand;
+
+/// Re-encodes raw videos into a smaller file suitable for long term storage
+#[derive(Clone)]
+pub struct ArchiveConverter {
+    pub ffmpeg_path: PathBuf,
+}
+
+impl ArchiveConverter {
+    pub fn new<P: AsRef<Path>>(ffmpeg_path: P) -> Result<Self> {
+        let ffmpeg = ffmpeg_path.as_ref().to_path_buf();
+
+        if !ffmpeg.exists() {
+            anyhow::bail!("FFmpeg not found at {:?}", ffmpeg);
+        }
+
+        Ok(Self {
+            ffmpeg_path: ffmpeg,
+        })
+    }
+
+    /// Transcodes the input into an HEVC Matroska file, keeping the original audio and any subtitles
+    /// ffmpeg is killed and an error returned once `cancellation` is cancelled
+    pub async fn archive(
+        &self,
+        input_path: &Path,
+        output_path: &Path,
+        cancellation: &CancellationToken,
+    ) -> Result<()> {
+        if !input_path.exists() {
+            anyhow::bail!("Input file not found: {:?}", input_path);
+        }
+
+        let mut command = FfmpegCommand::new(&self.ffmpeg_path);
+        command
+            .cancellation(cancellation.clone())
+            .arg("-y")
+            .arg("-i")
+            .arg(input_path)
+            // Keep the video, audio and subtitles, data tracks like tmcd don't fit in Matroska
+            .arg("-map")
+            .arg("0:v")
+            .arg("-map")
+            .arg("0:a?")
+            .arg("-map")
+            .arg("0:s?")
+            .arg("-dn")
+            // Video encoding settings, favoring size over encoding speed
+            .arg("-c:v")
+            .arg("libx265")
+            .arg("-crf")
+            .arg("26")
+            .arg("-preset")
+            .arg("slow")
+            .arg("-pix_fmt")
+            .arg("yuv420p")
+            // Audio is small, so copy it as-is
+            .arg("-c:a")
+            .arg("copy")
+            // Matroska can't hold mov_text subtitles from MP4 sources, so convert them to SRT
+            .arg("-c:s")
+            .arg("srt")
+            .arg("-f")
+            .arg("matroska")
+            .arg(output_path);
+
+        command.run(|_| {}).await.context("FFmpeg archive failed")?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,264 @@
+use std::{
+    collections::VecDeque,
+    ffi::{OsStr, OsString},
+    future::Future,
+    path::{Path, PathBuf},
+    process::{ExitStatus, Output, Stdio},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader},
+    process::{Child, Command},
+};
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+use crate::error::FfmpegError;
+
+/// How many lines of stderr are kept from a conversion for its errors
+const STDERR_TAIL_LINES: usize = 20;
+
+/// A progress update parsed from ffmpeg's `-progress` output
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FfmpegProgress {
+    /// How much of the input has been processed
+    pub processed: Duration,
+    /// Frames encoded per second, missing until ffmpeg has encoded some video
+    pub fps: Option<f32>,
+    /// How many times faster than realtime ffmpeg is running
+    pub speed: Option<f32>,
+    /// Whether ffmpeg is done, it sends one last update when it finishes
+    pub finished: bool,
+}
+
+impl FfmpegProgress {
+    /// Applies a key=value line of `-progress` output
+    /// Returns true once the line ends an update, ffmpeg ends every update with a progress key
+    fn update(&mut self, line: &str) -> bool {
+        let Some((key, value)) = line.split_once('=') else {
+            return false;
+        };
+        let value = value.trim();
+        match key.trim() {
+            // out_time_ms is in microseconds as well, it's only kept around by ffmpeg for compatibility
+            "out_time_us" | "out_time_ms" => {
+                // The time is N/A or negative until the first frame is out
+                if let Ok(micros) = value.parse::<u64>() {
+                    self.processed = Duration::from_micros(micros);
+                }
+            }
+            "fps" => self.fps = value.parse().ok(),
+            "speed" => self.speed = value.trim_end_matches('x').parse().ok(),
+            "progress" => {
+                self.finished = value == "end";
+                return true;
+            }
+            _ => {}
+        }
+        false
+    }
+}
+
+/// How a supervised process stopped
+enum Exit<T> {
+    Finished(ExitStatus, T),
+    Cancelled,
+    TimedOut(Duration),
+}
+
+/// Runs ffmpeg or ffprobe without blocking the async workers
+/// The process is killed once the timeout passes, the cancellation token is cancelled or the
+/// command is dropped
+pub struct FfmpegCommand {
+    program: PathBuf,
+    args: Vec<OsString>,
+    timeout: Option<Duration>,
+    cancellation: CancellationToken,
+}
+
+impl FfmpegCommand {
+    pub fn new(program: impl AsRef<Path>) -> Self {
+        Self {
+            program: program.as_ref().to_path_buf(),
+            args: Vec::new(),
+            timeout: None,
+            cancellation: CancellationToken::new(),
+        }
+    }
+    pub fn arg(&mut self, arg: impl AsRef<OsStr>) -> &mut Self {
+        self.args.push(arg.as_ref().to_os_string());
+        self
+    }
+    pub fn args<I, S>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        for arg in args {
+            self.arg(arg);
+        }
+        self
+    }
+    /// Kills the process if it runs for longer than the timeout
+    pub fn timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.timeout = timeout;
+        self
+    }
+    /// Kills the process once the token is cancelled
+    pub fn cancellation(&mut self, cancellation: CancellationToken) -> &mut Self {
+        self.cancellation = cancellation;
+        self
+    }
+    /// Runs the command to completion, returning everything it wrote whether it succeeded or not
+    /// Meant for probes and other short commands, long running ones should use `run`
+    pub async fn output(&self) -> Result<Output, FfmpegError> {
+        let mut child = self.spawn(&[])?;
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = tokio::spawn(read_lines(
+            child.stderr.take().expect("stderr is piped"),
+            None,
+        ));
+
+        let read_stdout = async {
+            let mut output = Vec::new();
+            stdout.read_to_end(&mut output).await?;
+            Ok(output)
+        };
+        let exit = self.supervise(&mut child, read_stdout).await;
+        let stderr = stderr.await.unwrap_or_default();
+        let (status, stdout) = self.finish(exit, &stderr)?;
+
+        Ok(Output {
+            status,
+            stdout,
+            stderr: stderr.into_bytes(),
+        })
+    }
+    /// Runs ffmpeg, calling `on_progress` with each update it reports on `-progress pipe:1`
+    /// Fails when ffmpeg exits unsuccessfully, keeping the last lines of stderr in the error
+    pub async fn run<F: FnMut(&FfmpegProgress)>(
+        &self,
+        mut on_progress: F,
+    ) -> Result<(), FfmpegError> {
+        let mut child = self.spawn(&["-progress", "pipe:1", "-nostats"])?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = tokio::spawn(read_lines(
+            child.stderr.take().expect("stderr is piped"),
+            Some(STDERR_TAIL_LINES),
+        ));
+
+        let read_progress = async {
+            let mut lines = BufReader::new(stdout).lines();
+            let mut progress = FfmpegProgress::default();
+            while let Some(line) = lines.next_line().await? {
+                if progress.update(&line) {
+                    on_progress(&progress);
+                }
+            }
+            Ok(())
+        };
+        let exit = self.supervise(&mut child, read_progress).await;
+        let stderr = stderr.await.unwrap_or_default();
+        let (status, ()) = self.finish(exit, &stderr)?;
+
+        if !status.success() {
+            return Err(FfmpegError::Failed {
+                program: self.program_name(),
+                status,
+                stderr,
+            });
+        }
+        Ok(())
+    }
+    /// Starts the process with its output piped back, the given args go before the command's own
+    fn spawn(&self, leading_args: &[&str]) -> Result<Child, FfmpegError> {
+        let mut command = Command::new(&self.program);
+        command
+            .args(leading_args)
+            .args(&self.args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        debug!("FFmpeg command: {:?}", command);
+
+        command.spawn().map_err(|source| FfmpegError::Spawn {
+            program: self.program_name(),
+            source,
+        })
+    }
+    /// Waits for the work on the process's output and for the process to exit
+    /// Kills the process when it's cancelled or times out first
+    async fn supervise<T>(
+        &self,
+        child: &mut Child,
+        work: impl Future<Output = std::io::Result<T>>,
+    ) -> std::io::Result<Exit<T>> {
+        let deadline = async {
+            match self.timeout {
+                Some(timeout) => tokio::time::sleep(timeout).await,
+                None => std::future::pending().await,
+            }
+        };
+        let exit = tokio::select! {
+            finished = async {
+                let value = work.await?;
+                let status = child.wait().await?;
+                Ok::<_, std::io::Error>(Exit::Finished(status, value))
+            } => return finished,
+            _ = self.cancellation.cancelled() => Exit::Cancelled,
+            _ = deadline => Exit::TimedOut(self.timeout.unwrap_or_default()),
+        };
+
+        // Killing also waits for the process, so nothing is left behind
+        if let Err(e) = child.kill().await {
+            tracing::warn!("Could not kill {}: {}", self.program_name(), e);
+        }
+        Ok(exit)
+    }
+    /// Turns how the process stopped into its exit status and output
+    fn finish<T>(
+        &self,
+        exit: std::io::Result<Exit<T>>,
+        stderr: &str,
+    ) -> Result<(ExitStatus, T), FfmpegError> {
+        let program = self.program_name();
+        match exit {
+            Ok(Exit::Finished(status, value)) => Ok((status, value)),
+            Ok(Exit::Cancelled) => Err(FfmpegError::Cancelled { program }),
+            Ok(Exit::TimedOut(timeout)) => Err(FfmpegError::TimedOut {
+                program,
+                timeout,
+                stderr: stderr.to_string(),
+            }),
+            Err(source) => Err(FfmpegError::Io { program, source }),
+        }
+    }
+    fn program_name(&self) -> String {
+        self.program
+            .file_name()
+            .unwrap_or(self.program.as_os_str())
+            .to_string_lossy()
+            .to_string()
+    }
+}
+
+/// Reads every line from the reader, keeping only the last `limit` lines when there's a limit
+/// Lines that aren't valid UTF-8 are kept lossily, so the pipe is always drained
+async fn read_lines(reader: impl AsyncRead + Unpin, limit: Option<usize>) -> String {
+    let mut reader = BufReader::new(reader);
+    let mut lines = VecDeque::new();
+    let mut line = Vec::new();
+    while let Ok(read) = reader.read_until(b'\n', &mut line).await {
+        if read == 0 {
+            break;
+        }
+        if limit.is_some_and(|limit| lines.len() >= limit) {
+            lines.pop_front();
+        }
+        lines.push_back(String::from_utf8_lossy(&line).trim_end().to_string());
+        line.clear();
+    }
+    Vec::from(lines).join("\n")
+}
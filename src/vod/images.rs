@@ -0,0 +1,233 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use super::{ffmpeg::FfmpegCommand, probe::MediaInfo};
+
+/// How long grabbing a single frame can take
+const FRAME_TIMEOUT: Duration = Duration::from_secs(2 * 60);
+
+/// How long building the storyboard can take, it decodes the whole video
+const STORYBOARD_TIMEOUT: Duration = Duration::from_secs(2 * 60 * 60);
+
+/// Where in the video candidate thumbnails are taken from, as a share of the duration
+const CANDIDATE_POSITIONS: &[f64] = &[0.25, 0.5, 0.75];
+
+/// Widest the poster gets, smaller videos keep their own width
+const POSTER_MAX_WIDTH: u32 = 1280;
+
+/// Widest a candidate thumbnail gets
+const CANDIDATE_MAX_WIDTH: u32 = 640;
+
+/// Width of each frame in the storyboard
+const STORYBOARD_FRAME_WIDTH: u32 = 160;
+
+/// Frames per row and column of each storyboard sprite sheet
+const STORYBOARD_COLUMNS: u32 = 10;
+
+/// Seconds between storyboard frames, long videos space them out further
+const STORYBOARD_INTERVAL: f64 = 10.0;
+
+/// Most frames a storyboard gets, which keeps the sprite sheets for long videos in check
+const STORYBOARD_MAX_FRAMES: f64 = 1000.0;
+
+/// Name of the WebVTT thumbnails track pointing into the storyboard sprite sheets
+pub const STORYBOARD_TRACK: &str = "storyboard.vtt";
+
+/// Names of the images generated for a video, relative to the directory they were written to
+#[derive(Debug, Clone)]
+pub struct GeneratedImages {
+    pub poster: String,
+    pub candidates: Vec<String>,
+    pub storyboard: String,
+}
+
+/// Grabs stills from videos for posters, thumbnails and scrub bar previews
+#[derive(Clone)]
+pub struct ImageGenerator {
+    pub ffmpeg_path: PathBuf,
+}
+
+impl ImageGenerator {
+    pub fn new<P: AsRef<Path>>(ffmpeg_path: P) -> Result<Self> {
+        let ffmpeg = ffmpeg_path.as_ref().to_path_buf();
+
+        if !ffmpeg.exists() {
+            anyhow::bail!("FFmpeg not found at {:?}", ffmpeg);
+        }
+
+        Ok(Self {
+            ffmpeg_path: ffmpeg,
+        })
+    }
+
+    /// Writes a poster, the candidate thumbnails and the storyboard for the input to output_dir
+    /// ffmpeg is killed and an error returned once `cancellation` is cancelled
+    pub async fn generate(
+        &self,
+        input_path: &Path,
+        output_dir: &Path,
+        info: &MediaInfo,
+        cancellation: &CancellationToken,
+    ) -> Result<GeneratedImages> {
+        if !input_path.exists() {
+            anyhow::bail!("Input file not found: {:?}", input_path);
+        }
+        tokio::fs::create_dir_all(output_dir)
+            .await
+            .context("Failed to create image directory")?;
+        let duration = info.duration.unwrap_or_default();
+
+        // Skip past any fade in for the poster
+        let poster = "poster.jpg".to_string();
+        self.frame(
+            input_path,
+            duration * 0.1,
+            POSTER_MAX_WIDTH,
+            &output_dir.join(&poster),
+            cancellation,
+        )
+        .await
+        .context("Failed to generate poster")?;
+
+        let mut candidates = Vec::new();
+        for (index, position) in CANDIDATE_POSITIONS.iter().enumerate() {
+            let candidate = format!("thumbnail_{}.jpg", index);
+            self.frame(
+                input_path,
+                duration * position,
+                CANDIDATE_MAX_WIDTH,
+                &output_dir.join(&candidate),
+                cancellation,
+            )
+            .await
+            .with_context(|| format!("Failed to generate thumbnail {}", index))?;
+            candidates.push(candidate);
+        }
+
+        self.storyboard(input_path, output_dir, info, cancellation)
+            .await
+            .context("Failed to generate storyboard")?;
+
+        Ok(GeneratedImages {
+            poster,
+            candidates,
+            storyboard: STORYBOARD_TRACK.to_string(),
+        })
+    }
+
+    /// Writes the frame at the given second as a JPEG, scaled down to max_width
+    async fn frame(
+        &self,
+        input_path: &Path,
+        at: f64,
+        max_width: u32,
+        output_path: &Path,
+        cancellation: &CancellationToken,
+    ) -> Result<()> {
+        let mut command = FfmpegCommand::new(&self.ffmpeg_path);
+        command
+            .timeout(Some(FRAME_TIMEOUT))
+            .cancellation(cancellation.clone())
+            .arg("-y")
+            // Seeking before the input jumps straight to the nearest keyframe
+            .arg("-ss")
+            .arg(format!("{:.3}", at))
+            .arg("-i")
+            .arg(input_path)
+            .arg("-frames:v")
+            .arg("1")
+            .arg("-vf")
+            .arg(format!("scale='min({},iw)':-2", max_width))
+            .arg("-q:v")
+            .arg("2")
+            .arg(output_path);
+        command.run(|_| {}).await?;
+        Ok(())
+    }
+
+    /// Tiles evenly spaced frames into sprite sheets, then writes the WebVTT track for them
+    async fn storyboard(
+        &self,
+        input_path: &Path,
+        output_dir: &Path,
+        info: &MediaInfo,
+        cancellation: &CancellationToken,
+    ) -> Result<()> {
+        let duration = info.duration.unwrap_or_default();
+        if duration <= 0.0 || info.width <= 0 || info.height <= 0 {
+            anyhow::bail!("The video needs a duration and dimensions for a storyboard");
+        }
+        let interval = STORYBOARD_INTERVAL.max(duration / STORYBOARD_MAX_FRAMES);
+        let frame_width = STORYBOARD_FRAME_WIDTH;
+        // Keep the aspect ratio, rounded to an even height for the encoder
+        let frame_height = ((frame_width as f64 * info.height as f64 / info.width as f64 / 2.0)
+            .round() as u32)
+            .max(1)
+            * 2;
+
+        let mut command = FfmpegCommand::new(&self.ffmpeg_path);
+        command
+            .timeout(Some(STORYBOARD_TIMEOUT))
+            .cancellation(cancellation.clone())
+            .arg("-y")
+            .arg("-i")
+            .arg(input_path)
+            .arg("-an")
+            .arg("-vf")
+            .arg(format!(
+                "fps=1/{},scale={}:{},tile={}x{}",
+                interval, frame_width, frame_height, STORYBOARD_COLUMNS, STORYBOARD_COLUMNS
+            ))
+            .arg("-q:v")
+            .arg("4")
+            .arg(output_dir.join("storyboard_%03d.jpg"));
+        command.run(|_| {}).await?;
+
+        let track = storyboard_track(duration, interval, frame_width, frame_height);
+        tokio::fs::write(output_dir.join(STORYBOARD_TRACK), track)
+            .await
+            .context("Failed to write storyboard track")?;
+        Ok(())
+    }
+}
+
+/// Builds the WebVTT thumbnails track, pointing each span of the video at its spot in a sprite sheet
+fn storyboard_track(duration: f64, interval: f64, frame_width: u32, frame_height: u32) -> String {
+    let frames_per_sheet = STORYBOARD_COLUMNS * STORYBOARD_COLUMNS;
+    let frame_count = (duration / interval).ceil() as u32;
+    let mut track = String::from("WEBVTT\n");
+    for frame in 0..frame_count {
+        let start = frame as f64 * interval;
+        let end = (start + interval).min(duration);
+        // ffmpeg numbers the sheets from 1
+        let sheet = frame / frames_per_sheet + 1;
+        let position = frame % frames_per_sheet;
+        let x = (position % STORYBOARD_COLUMNS) * frame_width;
+        let y = (position / STORYBOARD_COLUMNS) * frame_height;
+        track.push_str(&format!(
+            "\n{} --> {}\nstoryboard_{:03}.jpg#xywh={},{},{},{}\n",
+            vtt_timestamp(start),
+            vtt_timestamp(end),
+            sheet,
+            x,
+            y,
+            frame_width,
+            frame_height
+        ));
+    }
+    track
+}
+
+/// Formats seconds as a WebVTT timestamp, e.g. 01:02:03.456
+fn vtt_timestamp(seconds: f64) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+
+use super::stream::Quality;
+
+/// Presets both x264 and x265 understand, from fastest to smallest output
+pub const PRESETS: &[&str] = &[
+    "ultrafast",
+    "superfast",
+    "veryfast",
+    "faster",
+    "fast",
+    "medium",
+    "slow",
+    "slower",
+    "veryslow",
+];
+
+/// The video codec renditions are encoded with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "video_codec", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum VideoCodec {
+    #[default]
+    H264,
+    H265,
+}
+
+impl VideoCodec {
+    /// The ffmpeg encoder for the codec
+    pub fn encoder(&self) -> &'static str {
+        match self {
+            VideoCodec::H264 => "libx264",
+            VideoCodec::H265 => "libx265",
+        }
+    }
+}
+
+/// How the renditions are packaged and which manifests point at them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "manifest_type", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ManifestType {
+    /// MPEG-TS segments behind an HLS playlist
+    #[default]
+    Hls,
+    /// Fragmented MP4 segments shared by an HLS playlist and a DASH manifest
+    Cmaf,
+}
+
+impl ManifestType {
+    /// The value ffmpeg's hls muxer takes for -hls_segment_type
+    pub fn segment_type(&self) -> &'static str {
+        match self {
+            ManifestType::Hls => "mpegts",
+            ManifestType::Cmaf => "fmp4",
+        }
+    }
+    /// The file extension of each segment
+    pub fn segment_extension(&self) -> &'static str {
+        match self {
+            ManifestType::Hls => "ts",
+            ManifestType::Cmaf => "m4s",
+        }
+    }
+    /// The HLS version the playlists need, fMP4 segments came with version 7
+    pub fn hls_version(&self) -> u32 {
+        match self {
+            ManifestType::Hls => 3,
+            ManifestType::Cmaf => 7,
+        }
+    }
+}
+
+/// Whether the renditions are encoded by one ffmpeg each or all together
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "encoding_mode", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum EncodingMode {
+    /// Every rendition decodes the source on its own, so they can run on different workers
+    #[default]
+    PerRendition,
+    /// The source is decoded once and split into every rendition by a single ffmpeg
+    SinglePass,
+}
+
+/// How every rendition of a ladder is encoded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EncodingSettings {
+    pub codec: VideoCodec,
+    pub preset: String,
+    /// Length of each HLS segment in seconds
+    pub segment_duration: u32,
+    /// Settings queued before manifest types existed are plain HLS
+    #[serde(default)]
+    pub manifest: ManifestType,
+    /// Settings queued before encoding modes existed encode every rendition on its own
+    #[serde(default)]
+    pub mode: EncodingMode,
+}
+
+impl Default for EncodingSettings {
+    fn default() -> Self {
+        Self {
+            codec: VideoCodec::H264,
+            preset: "faster".to_string(),
+            segment_duration: 6,
+            manifest: ManifestType::Hls,
+            mode: EncodingMode::PerRendition,
+        }
+    }
+}
+
+/// Directory and step name of the audio rendition, so no video quality can take it
+pub const AUDIO_RENDITION: &str = "audio";
+
+/// The renditions a video is converted into, along with how they're encoded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncodingLadder {
+    pub qualities: Vec<Quality>,
+    pub settings: EncodingSettings,
+}
+
+impl Default for EncodingLadder {
+    /// The ladder used when no profile has been picked and no profile is marked as the default
+    fn default() -> Self {
+        Self {
+            qualities: vec![
+                Quality::new(1920, 1080, "5000k", "1080p"),
+                Quality::new(1280, 720, "2800k", "720p"),
+                Quality::new(854, 480, "1400k", "480p"),
+            ],
+            settings: EncodingSettings::default(),
+        }
+    }
+}
+
+impl EncodingLadder {
+    /// Checks the ladder can be handed to ffmpeg, returning what's wrong with it if not
+    /// Quality names end up in file paths, so they're limited to letters, digits, - and _
+    pub fn validate(&self) -> Result<(), String> {
+        if self.qualities.is_empty() {
+            return Err("At least one rendition is required".to_string());
+        }
+        for (index, quality) in self.qualities.iter().enumerate() {
+            if quality.name.is_empty()
+                || !quality
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(format!(
+                    "Rendition name {:?} can only use letters, digits, - and _",
+                    quality.name
+                ));
+            }
+            if quality.name == AUDIO_RENDITION {
+                return Err(format!(
+                    "Rendition name {} is kept for the audio rendition",
+                    AUDIO_RENDITION
+                ));
+            }
+            if self.qualities[..index]
+                .iter()
+                .any(|other| other.name == quality.name)
+            {
+                return Err(format!("Rendition {} is listed twice", quality.name));
+            }
+            if quality.width == 0
+                || quality.height == 0
+                || quality.width % 2 != 0
+                || quality.height % 2 != 0
+                || quality.width > 7680
+                || quality.height > 4320
+            {
+                return Err(format!(
+                    "Rendition {} needs an even resolution up to 7680x4320, got {}x{}",
+                    quality.name, quality.width, quality.height
+                ));
+            }
+            if quality.bitrate_kbps().is_none() {
+                return Err(format!(
+                    "Rendition {} needs a bitrate in kbps like 2800k, got {}",
+                    quality.name, quality.bitrate
+                ));
+            }
+            if quality.crf.is_some_and(|crf| crf > 51) {
+                return Err(format!("Rendition {} needs a CRF up to 51", quality.name));
+            }
+            if quality.max_fps.is_some_and(|fps| fps == 0 || fps > 240) {
+                return Err(format!(
+                    "Rendition {} needs an fps cap between 1 and 240",
+                    quality.name
+                ));
+            }
+        }
+        if !PRESETS.contains(&self.settings.preset.as_str()) {
+            return Err(format!(
+                "Unknown preset {}, expected one of {}",
+                self.settings.preset,
+                PRESETS.join(", ")
+            ));
+        }
+        if !(1..=30).contains(&self.settings.segment_duration) {
+            return Err("Segment duration must be between 1 and 30 seconds".to_string());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,348 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    ladder::{ManifestType, AUDIO_RENDITION},
+    probe::ProbeStream,
+    stream::Quality,
+};
+
+/// Name of the HLS playlist that lists every rendition
+pub const MASTER_PLAYLIST: &str = "master.m3u8";
+
+/// Name of the DASH manifest written beside the master playlist for CMAF streams
+pub const DASH_MANIFEST: &str = "manifest.mpd";
+
+/// Name of the init segment ffmpeg writes beside each CMAF rendition's playlist
+pub const INIT_SEGMENT: &str = "init.mp4";
+
+/// The rendition group every video rendition plays its audio from
+const AUDIO_GROUP: &str = "audio";
+
+/// The name of a rendition's playlist, which lives in a directory named after the rendition
+pub fn playlist_name(rendition: &str) -> String {
+    format!("stream_{}.m3u8", rendition)
+}
+
+/// The file names ffmpeg numbers a rendition's segments with
+pub fn segment_pattern(rendition: &str, manifest: ManifestType) -> String {
+    format!(
+        "stream_{}_segment_%03d.{}",
+        rendition,
+        manifest.segment_extension()
+    )
+}
+
+/// What was measured from a converted rendition's playlist and segments
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenditionStats {
+    /// The RFC 6381 codec, e.g. avc1.4d401f, None when the encoder's output wasn't recognised
+    pub codecs: Option<String>,
+    /// Bitrate of the busiest segment in bits per second
+    pub peak_bandwidth: u64,
+    /// Bitrate over the whole rendition in bits per second
+    pub average_bandwidth: u64,
+    /// Length of every segment in seconds, in playlist order
+    pub segments: Vec<f64>,
+}
+
+impl RenditionStats {
+    /// Reads the segments from a rendition's playlist and sizes each of them up from its file
+    pub async fn measure(playlist_path: &Path, codecs: Option<String>) -> Result<Self> {
+        let playlist = tokio::fs::read_to_string(playlist_path)
+            .await
+            .with_context(|| format!("Failed to read playlist {:?}", playlist_path))?;
+        let dir = playlist_path.parent().unwrap_or(Path::new("."));
+
+        let mut segments = Vec::new();
+        let mut peak_bandwidth = 0;
+        let mut total_bits = 0;
+        let mut duration = None;
+        for line in playlist.lines().map(str::trim) {
+            if let Some(info) = line.strip_prefix("#EXTINF:") {
+                duration = info.split(',').next().and_then(|d| d.parse::<f64>().ok());
+            } else if !line.is_empty() && !line.starts_with('#') {
+                let duration = duration
+                    .take()
+                    .with_context(|| format!("Segment {} has no duration", line))?;
+                let bits = tokio::fs::metadata(dir.join(line))
+                    .await
+                    .with_context(|| format!("Failed to read segment {}", line))?
+                    .len()
+                    * 8;
+                if duration > 0.0 {
+                    peak_bandwidth = peak_bandwidth.max((bits as f64 / duration).ceil() as u64);
+                }
+                total_bits += bits;
+                segments.push(duration);
+            }
+        }
+
+        let total_duration: f64 = segments.iter().sum();
+        if total_duration <= 0.0 {
+            anyhow::bail!("Playlist {:?} has no segments", playlist_path);
+        }
+        Ok(Self {
+            codecs,
+            peak_bandwidth,
+            average_bandwidth: (total_bits as f64 / total_duration).ceil() as u64,
+            segments,
+        })
+    }
+    /// Length of the rendition in seconds
+    pub fn duration(&self) -> f64 {
+        self.segments.iter().sum()
+    }
+}
+
+/// A video quality once it's been converted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VideoRendition {
+    pub quality: Quality,
+    /// The frame rate after any cap, None when the source didn't say
+    pub frame_rate: Option<f64>,
+    pub stats: RenditionStats,
+}
+
+/// The audio every video rendition shares once it's been converted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioRendition {
+    pub channels: u32,
+    pub sample_rate: u32,
+    pub stats: RenditionStats,
+}
+
+/// Builds the RFC 6381 codec players pick renditions by, from a probe of the converted stream
+pub fn codec_string(stream: &ProbeStream) -> Option<String> {
+    let profile = stream.profile.as_deref()?;
+    match stream.codec_name.as_deref()? {
+        "h264" => {
+            // profile_idc, then the constraint flags x264 sets for the profile
+            let (profile_idc, constraints) = match profile {
+                "Constrained Baseline" => (0x42, 0xc0),
+                "Baseline" => (0x42, 0x00),
+                "Main" => (0x4d, 0x40),
+                "High" => (0x64, 0x00),
+                _ => return None,
+            };
+            Some(format!(
+                "avc1.{:02x}{:02x}{:02x}",
+                profile_idc, constraints, stream.level?
+            ))
+        }
+        "hevc" => {
+            // profile_idc, then the profile compatibility flags written back to front
+            let (profile_idc, compatibility) = match profile {
+                "Main" => (1, 6),
+                "Main 10" => (2, 4),
+                _ => return None,
+            };
+            Some(format!(
+                "hvc1.{}.{}.L{}.B0",
+                profile_idc, compatibility, stream.level?
+            ))
+        }
+        "aac" => match profile {
+            "LC" => Some("mp4a.40.2".to_string()),
+            "HE-AAC" => Some("mp4a.40.5".to_string()),
+            "HE-AACv2" => Some("mp4a.40.29".to_string()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Builds the master playlist that points at the playlist of every rendition
+/// Video renditions are listed in the order given, all of them sharing the audio rendition
+pub fn master_playlist(
+    videos: &[VideoRendition],
+    audio: Option<&AudioRendition>,
+    manifest: ManifestType,
+) -> String {
+    let mut playlist = format!(
+        "#EXTM3U\n#EXT-X-VERSION:{}\n#EXT-X-INDEPENDENT-SEGMENTS\n",
+        manifest.hls_version()
+    );
+    if let Some(audio) = audio {
+        playlist.push_str(&format!(
+            "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"{}\",NAME=\"Audio\",DEFAULT=YES,AUTOSELECT=YES,CHANNELS=\"{}\",URI=\"{}/{}\"\n",
+            AUDIO_GROUP,
+            audio.channels,
+            AUDIO_RENDITION,
+            playlist_name(AUDIO_RENDITION)
+        ));
+    }
+
+    for video in videos {
+        let quality = &video.quality;
+        // The bandwidth covers everything a player downloads for the variant, audio included
+        let audio_stats = audio.map(|audio| &audio.stats);
+        let mut attributes = vec![
+            format!(
+                "BANDWIDTH={}",
+                video.stats.peak_bandwidth + audio_stats.map_or(0, |a| a.peak_bandwidth)
+            ),
+            format!(
+                "AVERAGE-BANDWIDTH={}",
+                video.stats.average_bandwidth + audio_stats.map_or(0, |a| a.average_bandwidth)
+            ),
+        ];
+        // Listing only some of the codecs would tell players the others aren't there
+        let codecs: Option<Vec<&str>> = std::iter::once(&video.stats)
+            .chain(audio_stats)
+            .map(|stats| stats.codecs.as_deref())
+            .collect();
+        if let Some(codecs) = codecs {
+            attributes.push(format!("CODECS=\"{}\"", codecs.join(",")));
+        }
+        attributes.push(format!("RESOLUTION={}x{}", quality.width, quality.height));
+        if let Some(frame_rate) = video.frame_rate {
+            attributes.push(format!("FRAME-RATE={:.3}", frame_rate));
+        }
+        if audio.is_some() {
+            attributes.push(format!("AUDIO=\"{}\"", AUDIO_GROUP));
+        }
+        attributes.push(format!("NAME=\"{}\"", quality.name));
+
+        // Path includes the rendition directory
+        playlist.push_str(&format!(
+            "#EXT-X-STREAM-INF:{}\n{}/{}\n",
+            attributes.join(","),
+            quality.name,
+            quality.playlist_name()
+        ));
+    }
+    playlist
+}
+
+/// Builds the DASH manifest for a CMAF stream, which points at the same segments as the playlists
+pub fn dash_manifest(videos: &[VideoRendition], audio: Option<&AudioRendition>) -> String {
+    let all_stats = videos
+        .iter()
+        .map(|video| &video.stats)
+        .chain(audio.map(|audio| &audio.stats));
+    let (duration, longest_segment) = all_stats.fold((0.0f64, 0.0f64), |(duration, longest), s| {
+        let segment = s.segments.iter().copied().fold(0.0, f64::max);
+        (duration.max(s.duration()), longest.max(segment))
+    });
+
+    let mut mpd = format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<MPD xmlns=\"urn:mpeg:dash:schema:mpd:2011\" ",
+            "profiles=\"urn:mpeg:dash:profile:isoff-live:2011\" type=\"static\" ",
+            "mediaPresentationDuration=\"PT{:.3}S\" minBufferTime=\"PT{}S\">\n",
+            "  <Period id=\"0\" start=\"PT0S\">\n",
+        ),
+        duration,
+        longest_segment.ceil() as u64
+    );
+
+    mpd.push_str(concat!(
+        "    <AdaptationSet id=\"0\" contentType=\"video\" mimeType=\"video/mp4\" ",
+        "segmentAlignment=\"true\" startWithSAP=\"1\">\n"
+    ));
+    for video in videos {
+        let quality = &video.quality;
+        let mut attributes = format!(
+            "id=\"{}\" bandwidth=\"{}\" width=\"{}\" height=\"{}\"",
+            quality.name, video.stats.average_bandwidth, quality.width, quality.height
+        );
+        if let Some(frame_rate) = video.frame_rate {
+            attributes.push_str(&format!(" frameRate=\"{}\"", dash_frame_rate(frame_rate)));
+        }
+        if let Some(codecs) = &video.stats.codecs {
+            attributes.push_str(&format!(" codecs=\"{}\"", codecs));
+        }
+        mpd.push_str(&format!("      <Representation {}>\n", attributes));
+        mpd.push_str(&segment_template(&quality.name, &video.stats));
+        mpd.push_str("      </Representation>\n");
+    }
+    mpd.push_str("    </AdaptationSet>\n");
+
+    if let Some(audio) = audio {
+        mpd.push_str(concat!(
+            "    <AdaptationSet id=\"1\" contentType=\"audio\" mimeType=\"audio/mp4\" ",
+            "segmentAlignment=\"true\" startWithSAP=\"1\">\n"
+        ));
+        let mut attributes = format!(
+            "id=\"{}\" bandwidth=\"{}\" audioSamplingRate=\"{}\"",
+            AUDIO_RENDITION, audio.stats.average_bandwidth, audio.sample_rate
+        );
+        if let Some(codecs) = &audio.stats.codecs {
+            attributes.push_str(&format!(" codecs=\"{}\"", codecs));
+        }
+        mpd.push_str(&format!("      <Representation {}>\n", attributes));
+        mpd.push_str(&format!(
+            concat!(
+                "        <AudioChannelConfiguration ",
+                "schemeIdUri=\"urn:mpeg:dash:23003:3:audio_channel_configuration:2011\" ",
+                "value=\"{}\"/>\n"
+            ),
+            audio.channels
+        ));
+        mpd.push_str(&segment_template(AUDIO_RENDITION, &audio.stats));
+        mpd.push_str("      </Representation>\n");
+        mpd.push_str("    </AdaptationSet>\n");
+    }
+
+    mpd.push_str("  </Period>\n</MPD>\n");
+    mpd
+}
+
+/// Points a representation at a rendition's segments, listing when each of them starts
+fn segment_template(rendition: &str, stats: &RenditionStats) -> String {
+    // Times are in milliseconds, taken from the running total so rounding doesn't add up
+    let mut timeline: Vec<(u64, u64, u32)> = Vec::new();
+    let mut elapsed = 0.0f64;
+    for segment in &stats.segments {
+        let start = (elapsed * 1000.0).round() as u64;
+        elapsed += segment;
+        let length = (elapsed * 1000.0).round() as u64 - start;
+        match timeline.last_mut() {
+            Some((_, last_length, repeat)) if *last_length == length => *repeat += 1,
+            _ => timeline.push((start, length, 0)),
+        }
+    }
+
+    let mut template = format!(
+        concat!(
+            "        <SegmentTemplate timescale=\"1000\" initialization=\"{}/{}\" ",
+            "media=\"{}/{}\" startNumber=\"0\">\n",
+            "          <SegmentTimeline>\n"
+        ),
+        rendition,
+        INIT_SEGMENT,
+        rendition,
+        segment_pattern(rendition, ManifestType::Cmaf).replace("%03d", "$Number%03d$")
+    );
+    for (start, length, repeat) in timeline {
+        match repeat {
+            0 => template.push_str(&format!(
+                "            <S t=\"{}\" d=\"{}\"/>\n",
+                start, length
+            )),
+            _ => template.push_str(&format!(
+                "            <S t=\"{}\" d=\"{}\" r=\"{}\"/>\n",
+                start, length, repeat
+            )),
+        }
+    }
+    template.push_str("          </SegmentTimeline>\n        </SegmentTemplate>\n");
+    template
+}
+
+/// Writes a frame rate the way DASH wants it, as a whole number or a fraction
+fn dash_frame_rate(frame_rate: f64) -> String {
+    if (frame_rate - frame_rate.round()).abs() < 0.01 {
+        return format!("{}", frame_rate.round() as u64);
+    }
+    // NTSC rates like 29.97 are really 30000/1001
+    let ntsc = frame_rate * 1.001;
+    if (ntsc - ntsc.round()).abs() < 0.01 {
+        return format!("{}/1001", ntsc.round() as u64 * 1000);
+    }
+    format!("{}/1000", (frame_rate * 1000.0).round() as u64)
+}
@@ -1,13 +1,19 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::{
     db::{DBPool, Video},
     prelude::get_storage_dir,
+    storage::{download_file, DownloadOptions, Storage},
 };
-use anyhow::anyhow;
-use aws_sdk_s3::Client;
+use anyhow::{anyhow, Context};
 use stream::{get_ffmpeg_location, HLSConverter};
 
+pub mod archive;
+pub mod ffmpeg;
+pub mod images;
+pub mod ladder;
+pub mod manifest;
+pub mod probe;
 pub mod stream;
 
 #[derive(Clone)]
@@ -17,8 +23,7 @@ pub struct Vod {
 }
 
 pub struct DownloadSettings<'a> {
-    pub client: &'a Client,
-    pub bucket: &'a str,
+    pub storage: &'a dyn Storage,
 }
 
 impl Vod {
@@ -28,9 +33,10 @@ impl Vod {
         id: String,
         output_dir: PathBuf,
     ) -> Result<Self, anyhow::Error> {
+        // Keep the database error as the source so callers can tell a missing video apart
         let video = Video::by_id(pool, &id)
             .await
-            .map_err(|e| anyhow!("Could not get VOD by video ID {} {}", &id, e))?;
+            .with_context(|| format!("Could not get VOD by video ID {}", &id))?;
         let converter = HLSConverter::new(get_ffmpeg_location(), output_dir)
             .expect("Could not initialize HLS converter");
 
@@ -74,33 +80,24 @@ impl Vod {
         tracing::debug!("Local video available at {:?}", local_file_path);
         Ok(Some(local_file_path))
     }
-    /// Downloads the raw video from S3 to the target path
+    /// Downloads the raw video from storage to the target path
+    /// The download goes to a partial file first, so the target path only exists once it's complete
     pub async fn download_raw<'a>(
         &self,
         settings: DownloadSettings<'a>,
-        target_path: &PathBuf,
+        target_path: &Path,
     ) -> Result<(), anyhow::Error> {
-        // The source video is expected to be in R2
-        let folder = target_path.parent().unwrap();
-        std::fs::create_dir_all(folder).map_err(|e| anyhow!("Failed to create folders: {}", e))?;
-
         tracing::debug!(
             "Downloading raw video from path: {}",
             &self.video.raw_video_path
         );
-        let req = settings
-            .client
-            .get_object()
-            .bucket(settings.bucket)
-            .key(&self.video.raw_video_path)
-            .send()
-            .await
-            .map_err(|e| anyhow!("Failed to download from R2: {}", e))?;
-
-        let bytes = req.body.collect().await?;
-        std::fs::write(target_path, bytes.into_bytes())
-            .map_err(|e| anyhow!("Failed to save file: {}", e))?;
-
-        Ok(())
+        download_file(
+            settings.storage,
+            &self.video.raw_video_path,
+            target_path,
+            &DownloadOptions::default(),
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to download from storage: {}", e))
     }
 }
@@ -0,0 +1,177 @@
+use std::{collections::HashMap, ffi::OsStr, path::Path, time::Duration};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use super::ffmpeg::FfmpegCommand;
+
+/// How long probing a video can take before giving up on it
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// What ffprobe found in a video file
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProbeOutput {
+    #[serde(default)]
+    pub streams: Vec<ProbeStream>,
+    pub format: ProbeFormat,
+}
+
+/// The container of a probed file
+/// ffprobe writes numbers as strings in here, so they're parsed as they're read
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProbeFormat {
+    /// Every name ffmpeg knows the container by, e.g. mov,mp4,m4a,3gp,3g2,mj2
+    pub format_name: String,
+    pub duration: Option<String>,
+    pub bit_rate: Option<String>,
+    pub size: Option<String>,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+}
+
+/// A single stream of a probed file
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProbeStream {
+    pub codec_type: Option<String>,
+    pub codec_name: Option<String>,
+    /// The codec profile, e.g. Main or LC
+    pub profile: Option<String>,
+    /// The codec level, e.g. 31 for H.264 level 3.1 or 93 for H.265 level 3.1
+    pub level: Option<i32>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    /// The average frame rate as a fraction, e.g. 30000/1001
+    pub avg_frame_rate: Option<String>,
+    pub channels: Option<i32>,
+}
+
+/// What we keep about a video file once it's been probed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct MediaInfo {
+    /// Length of the video in seconds
+    pub duration: Option<f64>,
+    pub container: String,
+    pub video_codec: String,
+    pub audio_codec: Option<String>,
+    pub width: i32,
+    pub height: i32,
+    pub frame_rate: Option<f64>,
+    /// Overall bitrate in bits per second
+    pub bit_rate: Option<i64>,
+    pub audio_channels: Option<i32>,
+    /// Size of the file in bytes
+    pub file_size: i64,
+}
+
+impl ProbeStream {
+    /// The average frame rate, None when ffprobe doesn't know it
+    pub fn frame_rate(&self) -> Option<f64> {
+        self.avg_frame_rate.as_deref().and_then(parse_frame_rate)
+    }
+}
+
+impl ProbeOutput {
+    /// Runs ffprobe on the input, reading the container and its streams from the file itself
+    pub async fn from_file(ffprobe_path: &Path, input_path: &Path) -> Result<Self> {
+        Self::run(
+            ffprobe_path,
+            input_path.as_os_str(),
+            &format!("{:?}", input_path),
+        )
+        .await
+    }
+    /// Runs ffprobe on a file served over HTTP, which only fetches the ranges it reads
+    /// The URL is left out of errors, signed URLs work as credentials until they expire
+    pub async fn from_url(ffprobe_path: &Path, url: &str) -> Result<Self> {
+        Self::run(ffprobe_path, OsStr::new(url), "the signed URL").await
+    }
+    async fn run(ffprobe_path: &Path, input: &OsStr, description: &str) -> Result<Self> {
+        let mut command = FfmpegCommand::new(ffprobe_path);
+        command
+            .timeout(Some(PROBE_TIMEOUT))
+            .arg("-v")
+            .arg("error")
+            .arg("-show_entries")
+            .arg(concat!(
+                "format=format_name,duration,bit_rate,size:format_tags=major_brand",
+                ":stream=codec_type,codec_name,profile,level,width,height,avg_frame_rate,channels"
+            ))
+            .arg("-of")
+            .arg("json")
+            .arg(input);
+        let output = command
+            .output()
+            .await
+            .context("Failed to execute ffprobe command")?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "ffprobe could not read {}: {}",
+                description,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        serde_json::from_slice(&output.stdout).context("Could not parse ffprobe output")
+    }
+    /// Whether ffmpeg knows the container by the name
+    pub fn is_format(&self, name: &str) -> bool {
+        self.format
+            .format_name
+            .split(',')
+            .any(|format| format == name)
+    }
+    /// The first stream of the type, e.g. video or audio
+    pub fn stream(&self, codec_type: &str) -> Option<&ProbeStream> {
+        self.streams
+            .iter()
+            .find(|stream| stream.codec_type.as_deref() == Some(codec_type))
+    }
+    /// The codecs of every stream of the type, e.g. video or audio
+    pub fn codecs(&self, codec_type: &str) -> Vec<&str> {
+        self.streams
+            .iter()
+            .filter(|stream| stream.codec_type.as_deref() == Some(codec_type))
+            .map(|stream| stream.codec_name.as_deref().unwrap_or("unknown"))
+            .collect()
+    }
+    /// Pulls what we keep about the file out of the probe, using the container we detected
+    /// Returns None when there's no video stream to describe
+    pub fn media_info(&self, container: &str, file_size: i64) -> Option<MediaInfo> {
+        let video = self.stream("video")?;
+        let audio = self.stream("audio");
+        Some(MediaInfo {
+            duration: self
+                .format
+                .duration
+                .as_deref()
+                .and_then(|duration| duration.parse().ok()),
+            container: container.to_string(),
+            video_codec: video.codec_name.clone().unwrap_or("unknown".to_string()),
+            audio_codec: audio.and_then(|audio| audio.codec_name.clone()),
+            width: video.width.unwrap_or_default(),
+            height: video.height.unwrap_or_default(),
+            frame_rate: video.frame_rate(),
+            bit_rate: self
+                .format
+                .bit_rate
+                .as_deref()
+                .and_then(|bit_rate| bit_rate.parse().ok()),
+            audio_channels: audio.and_then(|audio| audio.channels),
+            file_size: self
+                .format
+                .size
+                .as_deref()
+                .and_then(|size| size.parse().ok())
+                .unwrap_or(file_size),
+        })
+    }
+}
+
+/// Parses a frame rate written as a fraction, ffprobe writes 0/0 when it doesn't know it
+fn parse_frame_rate(rate: &str) -> Option<f64> {
+    let (numerator, denominator) = rate.split_once('/')?;
+    let numerator: f64 = numerator.parse().ok()?;
+    let denominator: f64 = denominator.parse().ok()?;
+    (numerator > 0.0 && denominator > 0.0).then(|| numerator / denominator)
+}
@@ -1,26 +1,157 @@
 use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, warn};
 
+use super::{
+    ffmpeg::{FfmpegCommand, FfmpegProgress},
+    ladder::{EncodingMode, EncodingSettings, ManifestType, VideoCodec, AUDIO_RENDITION},
+    manifest::{
+        codec_string, playlist_name, segment_pattern, AudioRendition, RenditionStats,
+        VideoRendition, INIT_SEGMENT,
+    },
+    probe::{MediaInfo, ProbeOutput, PROBE_TIMEOUT},
+};
+use crate::error::VideoFormatError;
+
+/// Sample rate of the audio rendition
+const AUDIO_SAMPLE_RATE: u32 = 48000;
+
+/// Channels of the audio rendition, everything is mixed down to stereo
+const AUDIO_CHANNELS: u32 = 2;
+
+/// Video codecs ffmpeg can decode for the conversion
+const SUPPORTED_VIDEO_CODECS: &[&str] = &[
+    "h264",
+    "hevc",
+    "vp8",
+    "vp9",
+    "av1",
+    "mpeg4",
+    "mpeg2video",
+    "prores",
+    "flv1",
+];
+
+/// Audio codecs ffmpeg can decode for the conversion
+const SUPPORTED_AUDIO_CODECS: &[&str] = &[
+    "aac",
+    "mp3",
+    "opus",
+    "vorbis",
+    "ac3",
+    "eac3",
+    "flac",
+    "alac",
+    "pcm_s16le",
+    "pcm_s24le",
+];
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum VideoFormat {
     MP4,
     MOV,
+    MKV,
+    WebM,
+    FLV,
+    AVI,
+    TS,
 }
 
 impl VideoFormat {
-    fn from_path(path: &Path) -> Result<Self> {
-        let extension = path
-            .extension()
-            .and_then(|ext| ext.to_str())
-            .map(|s| s.to_lowercase())
-            .ok_or_else(|| anyhow::anyhow!("Input file has no extension"))?;
-
-        match extension.as_str() {
+    /// Works out the container from what ffprobe found in the file, rejecting codecs we can't convert
+    pub fn from_probe(probe: &ProbeOutput) -> Result<Self, VideoFormatError> {
+        let video_codecs = probe.codecs("video");
+        if video_codecs.is_empty() {
+            return Err(VideoFormatError::NoVideoStream);
+        }
+        Self::check_codecs("video", &video_codecs, SUPPORTED_VIDEO_CODECS)?;
+        let audio_codecs = probe.codecs("audio");
+        Self::check_codecs("audio", &audio_codecs, SUPPORTED_AUDIO_CODECS)?;
+
+        if probe.is_format("mov") {
+            // MP4 and MOV share a demuxer, QuickTime files are branded as such
+            let brand = probe.format.tags.get("major_brand").map(|b| b.trim());
+            Ok(match brand {
+                Some("qt") => VideoFormat::MOV,
+                _ => VideoFormat::MP4,
+            })
+        } else if probe.is_format("matroska") {
+            // WebM is Matroska limited to the open codecs
+            let is_webm = video_codecs
+                .iter()
+                .all(|codec| matches!(*codec, "vp8" | "vp9" | "av1"))
+                && audio_codecs
+                    .iter()
+                    .all(|codec| matches!(*codec, "opus" | "vorbis"));
+            Ok(match is_webm {
+                true => VideoFormat::WebM,
+                false => VideoFormat::MKV,
+            })
+        } else if probe.is_format("flv") {
+            Ok(VideoFormat::FLV)
+        } else if probe.is_format("avi") {
+            Ok(VideoFormat::AVI)
+        } else if probe.is_format("mpegts") {
+            Ok(VideoFormat::TS)
+        } else {
+            Err(VideoFormatError::UnsupportedContainer(
+                probe.format.format_name.clone(),
+            ))
+        }
+    }
+
+    /// Gets the container back out of what the probe kept about the file
+    pub fn from_media_info(info: &MediaInfo) -> Result<Self, VideoFormatError> {
+        match info.container.as_str() {
             "mp4" => Ok(VideoFormat::MP4),
             "mov" => Ok(VideoFormat::MOV),
-            _ => anyhow::bail!("Unsupported file format: {}", extension),
+            "mkv" => Ok(VideoFormat::MKV),
+            "webm" => Ok(VideoFormat::WebM),
+            "flv" => Ok(VideoFormat::FLV),
+            "avi" => Ok(VideoFormat::AVI),
+            "ts" => Ok(VideoFormat::TS),
+            other => Err(VideoFormatError::UnsupportedContainer(other.to_string())),
+        }
+    }
+
+    /// The short name of the container
+    pub fn name(&self) -> &'static str {
+        match self {
+            VideoFormat::MP4 => "mp4",
+            VideoFormat::MOV => "mov",
+            VideoFormat::MKV => "mkv",
+            VideoFormat::WebM => "webm",
+            VideoFormat::FLV => "flv",
+            VideoFormat::AVI => "avi",
+            VideoFormat::TS => "ts",
+        }
+    }
+
+    fn check_codecs(
+        kind: &str,
+        codecs: &[&str],
+        supported: &[&str],
+    ) -> Result<(), VideoFormatError> {
+        match codecs.iter().find(|codec| !supported.contains(codec)) {
+            Some(codec) => Err(VideoFormatError::UnsupportedCodec {
+                kind: kind.to_string(),
+                codec: codec.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Arguments that have to come before the input
+    fn get_input_args(&self) -> Vec<String> {
+        match self {
+            // Recordings in these often have missing timestamps, so have ffmpeg fill them in
+            VideoFormat::FLV | VideoFormat::AVI | VideoFormat::TS => {
+                vec!["-fflags".to_string(), "+genpts".to_string()]
+            }
+            _ => Vec::new(),
         }
     }
 
@@ -33,22 +164,32 @@ impl VideoFormat {
                 "-strict".to_string(),
                 "experimental".to_string(),
             ],
+            // Drop subtitle and data tracks, the stream only carries video and audio
+            VideoFormat::MKV | VideoFormat::WebM | VideoFormat::TS => {
+                vec!["-sn".to_string(), "-dn".to_string()]
+            }
+            VideoFormat::FLV | VideoFormat::AVI => Vec::new(),
         }
     }
 
-    fn get_hls_args(&self) -> Vec<String> {
-        vec![
+    fn get_hls_args(&self, settings: &EncodingSettings) -> Vec<String> {
+        let mut args = vec![
             "-f".to_string(),
             "hls".to_string(),
             "-hls_time".to_string(),
-            "6".to_string(),
+            settings.segment_duration.to_string(),
             "-hls_list_size".to_string(),
             "0".to_string(),
             "-hls_segment_type".to_string(),
-            "mpegts".to_string(),
+            settings.manifest.segment_type().to_string(),
             "-hls_flags".to_string(),
             "independent_segments+split_by_time".to_string(),
-        ]
+        ];
+        if settings.manifest == ManifestType::Cmaf {
+            args.push("-hls_fmp4_init_filename".to_string());
+            args.push(INIT_SEGMENT.to_string());
+        }
+        args
     }
 }
 
@@ -56,14 +197,24 @@ impl VideoFormat {
 pub struct HLSConverter {
     pub ffmpeg_path: PathBuf,
     pub output_dir: PathBuf,
+    /// How long a single ffmpeg conversion can take, no limit when unset
+    pub timeout: Option<Duration>,
+    pub settings: EncodingSettings,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Quality {
     pub width: u32,
     pub height: u32,
+    /// The target bitrate in kbps, or the cap on it when encoding with a CRF, e.g. 2800k
     pub bitrate: String,
     pub name: String,
+    /// Encodes at a constant quality instead of the target bitrate when set
+    #[serde(default)]
+    pub crf: Option<u8>,
+    /// Drops frames from sources above this frame rate
+    #[serde(default)]
+    pub max_fps: Option<u32>,
 }
 
 impl Quality {
@@ -78,87 +229,21 @@ impl Quality {
             height,
             bitrate: bitrate.into(),
             name: name.into(),
+            crf: None,
+            max_fps: None,
         }
     }
+    /// The bitrate as a number of kbps, None when it isn't written like 2800k
+    pub fn bitrate_kbps(&self) -> Option<u32> {
+        self.bitrate.strip_suffix('k')?.parse().ok()
+    }
+    /// The name of the quality's playlist, which lives in a directory named after the quality
+    pub fn playlist_name(&self) -> String {
+        playlist_name(&self.name)
+    }
 }
 
 impl HLSConverter {
-    fn get_video_dimensions(&self, input_path: &Path) -> Result<(u32, u32)> {
-        debug!("Getting dimensions for {:?}", input_path);
-        let output = Command::new(&self.ffmpeg_path)
-            .arg("-i")
-            .arg(input_path)
-            .output()
-            .context("Failed to execute FFmpeg command for video info")?;
-
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        debug!("FFmpeg output:\n{}", stderr);
-
-        // Parse the video dimensions from FFmpeg output
-        for line in stderr.lines() {
-            if line.contains("Stream") && line.contains("Video:") {
-                debug!("Found video stream line: {}", line);
-
-                // Try different patterns
-                let dimensions = line
-                    .split(',')
-                    .find(|s| s.contains('x') && s.trim().chars().any(|c| c.is_digit(10)))
-                    .or_else(|| {
-                        // Alternative pattern: look for dimensions like "1920x1080"
-                        line.split_whitespace()
-                            .find(|s| s.contains('x') && s.chars().any(|c| c.is_digit(10)))
-                    });
-
-                if let Some(dim_str) = dimensions {
-                    debug!("Found dimension string: {}", dim_str);
-
-                    // Clean up the dimension string
-                    let clean_dim = dim_str
-                        .trim()
-                        .split(|c: char| !c.is_digit(10) && c != 'x')
-                        .collect::<String>();
-
-                    if let Some(x_pos) = clean_dim.find('x') {
-                        if let (Ok(width), Ok(height)) = (
-                            clean_dim[..x_pos].parse::<u32>(),
-                            clean_dim[x_pos + 1..].parse::<u32>(),
-                        ) {
-                            debug!("Parsed dimensions: {}x{}", width, height);
-                            return Ok((width, height));
-                        }
-                    }
-                }
-            }
-        }
-
-        // If the above fails, try using ffprobe
-        let probe_output = Command::new(&self.ffmpeg_path.with_file_name("ffprobe"))
-            .arg("-v")
-            .arg("error")
-            .arg("-select_streams")
-            .arg("v:0")
-            .arg("-show_entries")
-            .arg("stream=width,height")
-            .arg("-of")
-            .arg("csv=p=0")
-            .arg(input_path)
-            .output()
-            .context("Failed to execute ffprobe command")?;
-
-        if probe_output.status.success() {
-            let output = String::from_utf8_lossy(&probe_output.stdout);
-            let dims: Vec<&str> = output.trim().split(',').collect();
-            if dims.len() == 2 {
-                if let (Ok(width), Ok(height)) = (dims[0].parse::<u32>(), dims[1].parse::<u32>()) {
-                    debug!("Got dimensions from ffprobe: {}x{}", width, height);
-                    return Ok((width, height));
-                }
-            }
-        }
-
-        anyhow::bail!("Could not determine video dimensions")
-    }
-
     fn verify_dimensions(&self, width: u32, height: u32) -> Result<()> {
         if width == 0 || height == 0 {
             anyhow::bail!("Invalid dimensions: {}x{}", width, height);
@@ -183,27 +268,81 @@ impl HLSConverter {
         Ok(Self {
             ffmpeg_path: ffmpeg,
             output_dir: out_dir,
+            timeout: None,
+            settings: EncodingSettings::default(),
         })
     }
 
-    fn validate_input_format(&self, input_path: &Path) -> Result<VideoFormat> {
-        VideoFormat::from_path(input_path)
+    /// Limits how long a single ffmpeg conversion can take
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
     }
 
-    pub fn convert_to_hls<P: AsRef<Path>>(
+    /// Sets how every quality is encoded
+    pub fn with_settings(mut self, settings: EncodingSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Builds a command for a quick look at a video, which shouldn't take long
+    fn probe(&self, program: impl AsRef<Path>) -> FfmpegCommand {
+        let mut command = FfmpegCommand::new(program);
+        command.timeout(Some(PROBE_TIMEOUT));
+        command
+    }
+
+    /// Probes the input for everything we keep about it, failing when it can't be converted
+    pub async fn probe_media(&self, input_path: &Path) -> Result<MediaInfo> {
+        let probe =
+            ProbeOutput::from_file(&self.ffmpeg_path.with_file_name("ffprobe"), input_path).await?;
+        let file_size = tokio::fs::metadata(input_path)
+            .await
+            .context("Failed to read input file size")?
+            .len();
+        let info = self.describe(&probe, file_size)?;
+        debug!("Probed {:?}: {:?}", input_path, info);
+        Ok(info)
+    }
+
+    /// Probes a file served over HTTP the same way as `probe_media`, without downloading it
+    pub async fn probe_url(&self, url: &str, file_size: u64) -> Result<MediaInfo> {
+        let probe = ProbeOutput::from_url(&self.ffmpeg_path.with_file_name("ffprobe"), url).await?;
+        let info = self.describe(&probe, file_size)?;
+        debug!("Probed a signed URL: {:?}", info);
+        Ok(info)
+    }
+
+    fn describe(&self, probe: &ProbeOutput, file_size: u64) -> Result<MediaInfo> {
+        let format = VideoFormat::from_probe(probe)?;
+        let info = probe
+            .media_info(format.name(), file_size as i64)
+            .ok_or(VideoFormatError::NoVideoStream)?;
+        self.verify_dimensions(info.width as u32, info.height as u32)?;
+        Ok(info)
+    }
+
+    /// Converts the input into a video only HLS stream for each quality
+    /// Each quality gets its own ffmpeg unless the settings ask for a single pass
+    /// The audio is converted on its own, so every quality can share it
+    /// `info` is what the probe found in the input, see `probe_media`
+    /// `on_progress` is called with the overall percent complete as ffmpeg reports progress
+    /// ffmpeg is killed and an error returned once `cancellation` is cancelled or the timeout passes
+    pub async fn convert_to_hls<F: Fn(f32) + Sync>(
         &self,
-        input_path: P,
+        input_path: &Path,
+        info: &MediaInfo,
         mut qualities: Vec<Quality>,
-    ) -> Result<()> {
-        let input_path = input_path.as_ref();
+        on_progress: F,
+        cancellation: &CancellationToken,
+    ) -> Result<Vec<VideoRendition>> {
         if !input_path.exists() {
             anyhow::bail!("Input file not found: {:?}", input_path);
         }
 
-        let format = self.validate_input_format(input_path)?;
-
-        // Get original video dimensions
-        let (original_width, original_height) = self.get_video_dimensions(input_path)?;
+        let format = VideoFormat::from_media_info(info)?;
+        let source_frame_rate = info.frame_rate;
+        let (original_width, original_height) = (info.width as u32, info.height as u32);
         self.verify_dimensions(original_width, original_height)?;
 
         // Filter out qualities higher than the original resolution
@@ -227,101 +366,305 @@ impl HLSConverter {
             );
         }
 
-        // Create variant playlist
-        let mut master_playlist = String::from("#EXTM3U\n#EXT-X-VERSION:3\n");
+        let duration = progress_duration(info);
+        if self.settings.mode == EncodingMode::SinglePass {
+            let report_progress = |progress: &FfmpegProgress| {
+                debug!(
+                    "Converted {:?} of every quality at {} fps, {}x speed",
+                    progress.processed,
+                    progress.fps.unwrap_or_default(),
+                    progress.speed.unwrap_or_default()
+                );
+                if let Some(duration) = duration {
+                    let processed = progress.processed.as_secs_f64();
+                    on_progress(((processed / duration).clamp(0.0, 1.0) * 100.0) as f32);
+                }
+            };
+            return self
+                .convert_single_pass(
+                    input_path,
+                    &qualities,
+                    &format,
+                    source_frame_rate,
+                    report_progress,
+                    cancellation,
+                )
+                .await
+                .context("Failed to convert every quality in a single pass");
+        }
+        let quality_count = qualities.len() as f64;
 
         // Process each quality
-        for quality in qualities.iter() {
-            let output_name = format!("stream_{}", quality.name);
-            let playlist_name = format!("{}.m3u8", output_name);
-            let segment_pattern = format!("{}_segment_%03d.ts", output_name);
-
-            // Add to variant playlist with updated path that includes quality directory
-            master_playlist.push_str(&format!(
-                "#EXT-X-STREAM-INF:BANDWIDTH={},RESOLUTION={}x{},NAME=\"{}\"\n{}/{}\n",
-                quality.bitrate.replace("k", "000"),
-                quality.width,
-                quality.height,
-                quality.name,
-                quality.name,
-                playlist_name
-            ));
+        let mut renditions = Vec::new();
+        for (index, quality) in qualities.iter().enumerate() {
+            if cancellation.is_cancelled() {
+                anyhow::bail!("Conversion cancelled");
+            }
 
             // Convert for this quality
-            self.convert_quality(
-                input_path,
-                &output_name,
-                quality,
-                &playlist_name,
-                &segment_pattern,
-                &format,
-            )
-            .with_context(|| {
-                format!(
-                    "Failed to convert quality: {} ({}x{})",
-                    quality.name, quality.width, quality.height
+            // Each quality makes up an equal share of the overall progress
+            let report_progress = |progress: &FfmpegProgress| {
+                debug!(
+                    "Converted {:?} of {} at {} fps, {}x speed",
+                    progress.processed,
+                    quality.name,
+                    progress.fps.unwrap_or_default(),
+                    progress.speed.unwrap_or_default()
+                );
+                if let Some(duration) = duration {
+                    let processed = progress.processed.as_secs_f64();
+                    let quality_progress = (processed / duration).clamp(0.0, 1.0);
+                    let percent = (index as f64 + quality_progress) / quality_count * 100.0;
+                    on_progress(percent as f32);
+                }
+            };
+            let rendition = self
+                .convert_quality(
+                    input_path,
+                    quality,
+                    &format,
+                    source_frame_rate,
+                    report_progress,
+                    cancellation,
                 )
-            })?;
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to convert quality: {} ({}x{})",
+                        quality.name, quality.width, quality.height
+                    )
+                })?;
+            renditions.push(rendition);
         }
 
-        // Write master playlist in the root output directory
-        std::fs::write(self.output_dir.join("master.m3u8"), master_playlist)
-            .context("Failed to write master playlist")?;
+        Ok(renditions)
+    }
 
-        Ok(())
+    /// Converts the input's audio into an HLS stream of its own, which every quality plays with
+    /// Returns None when the input has no audio to convert
+    /// `info` is what the probe found in the input, see `probe_media`
+    /// ffmpeg is killed and an error returned once `cancellation` is cancelled or the timeout passes
+    pub async fn convert_audio<F: Fn(f32)>(
+        &self,
+        input_path: &Path,
+        info: &MediaInfo,
+        on_progress: F,
+        cancellation: &CancellationToken,
+    ) -> Result<Option<AudioRendition>> {
+        if !input_path.exists() {
+            anyhow::bail!("Input file not found: {:?}", input_path);
+        }
+
+        let format = VideoFormat::from_media_info(info)?;
+        if info.audio_codec.is_none() {
+            debug!("No audio to convert in {:?}", input_path);
+            return Ok(None);
+        }
+        let duration = progress_duration(info);
+
+        let settings = &self.settings;
+        let audio_dir = self.output_dir.join(AUDIO_RENDITION);
+        tokio::fs::create_dir_all(&audio_dir)
+            .await
+            .context("Failed to create audio directory")?;
+        let playlist_path = audio_dir.join(playlist_name(AUDIO_RENDITION));
+
+        let mut command = FfmpegCommand::new(&self.ffmpeg_path);
+        command
+            .timeout(self.timeout)
+            .cancellation(cancellation.clone())
+            .args(format.get_input_args())
+            .arg("-i")
+            .arg(input_path)
+            // Only the first audio track is kept
+            .arg("-map")
+            .arg("0:a:0")
+            .arg("-c:a")
+            .arg("aac")
+            .arg("-ar")
+            .arg(AUDIO_SAMPLE_RATE.to_string())
+            .arg("-ac")
+            .arg(AUDIO_CHANNELS.to_string())
+            .arg("-b:a")
+            .arg("128k")
+            .args(format.get_hls_args(settings))
+            .arg("-hls_segment_filename")
+            .arg(audio_dir.join(segment_pattern(AUDIO_RENDITION, settings.manifest)))
+            .arg(&playlist_path);
+
+        command
+            .run(|progress| {
+                if let Some(duration) = duration {
+                    let processed = progress.processed.as_secs_f64();
+                    on_progress(((processed / duration).clamp(0.0, 1.0) * 100.0) as f32);
+                }
+            })
+            .await
+            .context("FFmpeg audio conversion failed")?;
+
+        let ffprobe = self.ffmpeg_path.with_file_name("ffprobe");
+        let output = ProbeOutput::from_file(&ffprobe, &playlist_path)
+            .await
+            .context("Failed to probe converted audio")?;
+        let codecs = output.stream("audio").and_then(codec_string);
+        Ok(Some(AudioRendition {
+            channels: AUDIO_CHANNELS,
+            sample_rate: AUDIO_SAMPLE_RATE,
+            stats: RenditionStats::measure(&playlist_path, codecs).await?,
+        }))
+    }
+
+    async fn convert_quality<F: FnMut(&FfmpegProgress)>(
+        &self,
+        input_path: &Path,
+        quality: &Quality,
+        format: &VideoFormat,
+        source_frame_rate: Option<f64>,
+        on_progress: F,
+        cancellation: &CancellationToken,
+    ) -> Result<VideoRendition> {
+        let mut command = FfmpegCommand::new(&self.ffmpeg_path);
+        command
+            .timeout(self.timeout)
+            .cancellation(cancellation.clone())
+            .args(format.get_input_args())
+            .arg("-i")
+            .arg(input_path)
+            // Resolution
+            .arg("-s")
+            .arg(format!("{}x{}", quality.width, quality.height));
+        self.add_quality_output(&mut command, quality, format)
+            .await?;
+
+        command
+            .run(on_progress)
+            .await
+            .context("FFmpeg conversion failed")?;
+
+        self.measure_quality(quality, source_frame_rate).await
     }
 
-    fn convert_quality(
+    /// Converts every quality with a single ffmpeg, which decodes the input once and scales
+    /// a copy of each frame for every quality
+    async fn convert_single_pass<F: FnMut(&FfmpegProgress)>(
         &self,
         input_path: &Path,
-        _output_name: &str,
+        qualities: &[Quality],
+        format: &VideoFormat,
+        source_frame_rate: Option<f64>,
+        on_progress: F,
+        cancellation: &CancellationToken,
+    ) -> Result<Vec<VideoRendition>> {
+        // [0:v]split=2[s0][s1];[s0]scale=1280:720[v0];[s1]scale=854:480[v1]
+        let mut splits = String::new();
+        let mut scales = String::new();
+        for (index, quality) in qualities.iter().enumerate() {
+            splits.push_str(&format!("[s{}]", index));
+            scales.push_str(&format!(
+                ";[s{}]scale={}:{}[v{}]",
+                index, quality.width, quality.height, index
+            ));
+        }
+        let filter = format!("[0:v]split={}{}{}", qualities.len(), splits, scales);
+
+        let mut command = FfmpegCommand::new(&self.ffmpeg_path);
+        command
+            .timeout(self.timeout)
+            .cancellation(cancellation.clone())
+            .args(format.get_input_args())
+            .arg("-i")
+            .arg(input_path)
+            .arg("-filter_complex")
+            .arg(filter);
+        // Every quality is an output of its own, so each keeps its own playlist and segments
+        for (index, quality) in qualities.iter().enumerate() {
+            command.arg("-map").arg(format!("[v{}]", index));
+            self.add_quality_output(&mut command, quality, format)
+                .await
+                .with_context(|| format!("Failed to set up quality {}", quality.name))?;
+        }
+
+        command
+            .run(on_progress)
+            .await
+            .context("FFmpeg conversion failed")?;
+
+        let mut renditions = Vec::new();
+        for quality in qualities {
+            renditions.push(self.measure_quality(quality, source_frame_rate).await?);
+        }
+        Ok(renditions)
+    }
+
+    /// Adds the encoding settings and HLS output of a quality to the command,
+    /// creating the directory the quality is written to
+    async fn add_quality_output(
+        &self,
+        command: &mut FfmpegCommand,
         quality: &Quality,
-        playlist_name: &str,
-        segment_pattern: &str,
         format: &VideoFormat,
     ) -> Result<()> {
+        let settings = &self.settings;
+        let segment_pattern = segment_pattern(&quality.name, settings.manifest);
+
         // Create quality-specific directory
         let quality_dir = self.output_dir.join(&quality.name);
-        std::fs::create_dir_all(&quality_dir)
+        tokio::fs::create_dir_all(&quality_dir)
+            .await
             .context("Failed to create quality-specific directory")?;
 
-        let mut command = Command::new(&self.ffmpeg_path);
-
-        command.arg("-i").arg(input_path);
-
         // Add format-specific arguments
         for arg in format.get_ffmpeg_args() {
             command.arg(arg);
         }
 
+        let bitrate = quality
+            .bitrate_kbps()
+            .with_context(|| format!("Invalid bitrate {}", quality.bitrate))?;
+
+        // Frame rate settings, frames are only dropped when there's a cap
+        match quality.max_fps {
+            Some(max_fps) => command
+                .arg("-fpsmax")
+                .arg(max_fps.to_string())
+                .arg("-vsync")
+                .arg("vfr"),
+            None => command.arg("-vsync").arg("0"),
+        };
+
         command
-            .arg("-vsync")
-            .arg("0")
-            // Video encoding settings
+            // Video encoding settings, the audio has a rendition of its own
             .arg("-c:v")
-            .arg("libx264")
-            .arg("-c:a")
-            .arg("aac")
+            .arg(settings.codec.encoder())
+            .arg("-an")
             // Force pixel format
             .arg("-pix_fmt")
-            .arg("yuv420p")
-            // Bitrate settings
-            .arg("-b:v")
-            .arg(&quality.bitrate)
+            .arg("yuv420p");
+        // Apple players only take H.265 in fMP4 when it's tagged as hvc1
+        if settings.codec == VideoCodec::H265 && settings.manifest == ManifestType::Cmaf {
+            command.arg("-tag:v").arg("hvc1");
+        }
+
+        // Bitrate settings, a CRF keeps the bitrate as a cap
+        match quality.crf {
+            Some(crf) => command.arg("-crf").arg(crf.to_string()),
+            None => command.arg("-b:v").arg(&quality.bitrate),
+        };
+        command
             .arg("-maxrate")
             .arg(&quality.bitrate)
             .arg("-bufsize")
-            .arg(format!(
-                "{}k",
-                quality.bitrate.replace("k", "").parse::<u32>()? * 2
-            ))
+            .arg(format!("{}k", bitrate * 2))
             // Encoding presets
             .arg("-preset")
-            .arg("faster")
+            .arg(&settings.preset)
             .arg("-profile:v")
-            .arg("main")
-            .arg("-level")
-            .arg("3.1")
+            .arg("main");
+        if settings.codec == VideoCodec::H264 {
+            command.arg("-level").arg("3.1");
+        }
+
+        command
             .arg("-g")
             .arg("60")
             .arg("-keyint_min")
@@ -329,47 +672,59 @@ impl HLSConverter {
             .arg("-sc_threshold")
             .arg("0")
             .arg("-force_key_frames")
-            .arg("expr:gte(t,n_forced*6)")
-            // Resolution
-            .arg("-s")
-            .arg(format!("{}x{}", quality.width, quality.height))
-            // Audio settings
-            .arg("-ar")
-            .arg("48000")
-            .arg("-ac")
-            .arg("2")
-            .arg("-b:a")
-            .arg("128k");
+            .arg(format!(
+                "expr:gte(t,n_forced*{})",
+                settings.segment_duration
+            ));
 
         // Add HLS-specific settings
-        for arg in format.get_hls_args() {
+        for arg in format.get_hls_args(settings) {
             command.arg(arg);
         }
 
         command
             .arg("-hls_segment_filename")
             .arg(quality_dir.join(segment_pattern))
-            .arg(quality_dir.join(playlist_name));
-
-        debug!("FFmpeg command: {:?}", command);
-
-        let output = command
-            .output()
-            .context("Failed to execute FFmpeg command")?;
-
-        if !output.status.success() {
-            let error = String::from_utf8_lossy(&output.stderr);
-            debug!("FFmpeg error output: {}", error);
-            anyhow::bail!("FFmpeg failed: {}", error);
-        }
-
+            .arg(quality_dir.join(quality.playlist_name()));
         Ok(())
     }
 
-    pub fn verify_ffmpeg(&self) -> Result<String> {
-        let output = Command::new(&self.ffmpeg_path)
+    /// Reads what was converted for a quality back, for the manifests that point at it
+    async fn measure_quality(
+        &self,
+        quality: &Quality,
+        source_frame_rate: Option<f64>,
+    ) -> Result<VideoRendition> {
+        let playlist_path = self
+            .output_dir
+            .join(&quality.name)
+            .join(quality.playlist_name());
+
+        // The codec string comes from what the encoder actually wrote
+        let output =
+            ProbeOutput::from_file(&self.ffmpeg_path.with_file_name("ffprobe"), &playlist_path)
+                .await
+                .with_context(|| format!("Failed to probe converted quality {}", quality.name))?;
+        let codecs = output.stream("video").and_then(codec_string);
+        // Frames are only dropped above the cap
+        let frame_rate = match (source_frame_rate, quality.max_fps) {
+            (Some(rate), Some(max_fps)) => Some(rate.min(max_fps as f64)),
+            (rate, _) => rate,
+        };
+
+        Ok(VideoRendition {
+            quality: quality.clone(),
+            frame_rate,
+            stats: RenditionStats::measure(&playlist_path, codecs).await?,
+        })
+    }
+
+    pub async fn verify_ffmpeg(&self) -> Result<String> {
+        let output = self
+            .probe(&self.ffmpeg_path)
             .arg("-version")
             .output()
+            .await
             .context("Failed to execute FFmpeg version command")?;
 
         if !output.status.success() {
@@ -384,6 +739,15 @@ impl HLSConverter {
     }
 }
 
+/// How long the input is, for reporting progress, None when the probe couldn't tell
+fn progress_duration(info: &MediaInfo) -> Option<f64> {
+    let duration = info.duration.filter(|duration| *duration > 0.0);
+    if duration.is_none() {
+        warn!("Video duration is unknown, progress won't be reported");
+    }
+    duration
+}
+
 /// Get the path to ffmpeg
 pub fn get_ffmpeg_location() -> PathBuf {
     let env_ffmpeg_path = PathBuf::from(